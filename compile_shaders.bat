@@ -1,2 +1,4 @@
 glslangValidator.exe -V shaders/src/shader.vert -o shaders/spirv/vert.spv
-glslangValidator.exe -V shaders/src/shader.frag -o shaders/spirv/frag.spv
\ No newline at end of file
+glslangValidator.exe -V shaders/src/shader.frag -o shaders/spirv/frag.spv
+glslangValidator.exe -V shaders/src/skybox.vert -o shaders/spirv/skybox_vert.spv
+glslangValidator.exe -V shaders/src/skybox.frag -o shaders/spirv/skybox_frag.spv
\ No newline at end of file
@@ -0,0 +1,47 @@
+#version 450
+
+layout(location = 0) in vec3 in_direction;
+
+layout(set = 0, binding = 0) uniform samplerCube environment_sampler;
+
+layout(location = 0) out vec4 out_color;
+
+// OutputColorSpace of the renderer, 0 is Srgb, 1 is Hdr10, 2 is ExtendedSrgb and 3 is Srgb into a format without sRGB
+// encoding.
+layout(constant_id = 0) const uint OUTPUT_COLOR_SPACE = 0;
+// Luminance that SDR white is shown with on HDR outputs, the reference white of ITU-R BT.2408.
+const float PAPER_WHITE_NITS = 203.0;
+
+// Encodes linear BT.709 (sRGB primaries) color for the output color space.
+// Note: Same as in shader.frag, keep them in sync.
+vec3 encode_output_color(vec3 color) {
+    if (OUTPUT_COLOR_SPACE == 1) {
+        // BT.2020 primaries with the SMPTE ST 2084 (PQ) curve, which encodes absolute luminance up to 10000 nits.
+        const mat3 bt709_to_bt2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956);
+        vec3 luminance = clamp(bt709_to_bt2020 * color * (PAPER_WHITE_NITS / 10000.0), 0.0, 1.0);
+        const float m1 = 0.1593017578125;
+        const float m2 = 78.84375;
+        const float c1 = 0.8359375;
+        const float c2 = 18.8515625;
+        const float c3 = 18.6875;
+        vec3 luminance_m1 = pow(luminance, vec3(m1));
+        return pow((c1 + c2 * luminance_m1) / (1.0 + c3 * luminance_m1), vec3(m2));
+    } else if (OUTPUT_COLOR_SPACE == 2) {
+        // scRGB is linear with 1.0 at 80 nits.
+        return color * (PAPER_WHITE_NITS / 80.0);
+    } else if (OUTPUT_COLOR_SPACE == 3) {
+        // The sRGB curve, which SRGB formats apply when the color is written.
+        vec3 clamped_color = clamp(color, 0.0, 1.0);
+        return mix(clamped_color * 12.92, 1.055 * pow(clamped_color, vec3(1.0 / 2.4)) - 0.055,
+            greaterThan(clamped_color, vec3(0.0031308)));
+    }
+    // SRGB formats encode it when it is written.
+    return color;
+}
+
+void main() {
+    out_color = vec4(encode_output_color(texture(environment_sampler, in_direction).rgb), 1.0);
+}
@@ -0,0 +1,16 @@
+#version 460
+
+layout(push_constant) uniform PushConstants {
+    mat4 inverse_view_projection; // View without its translation, so that the sky never comes closer.
+} push_constants;
+
+layout(location = 0) out vec3 out_direction;
+
+void main() {
+    // Vertices 0, 1 and 2 make a triangle that covers the whole viewport.
+    vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    // At the far plane, so that only the pixels without any geometry pass the depth test.
+    gl_Position = vec4(ndc, 1.0, 1.0);
+    vec4 world_position = push_constants.inverse_view_projection * vec4(ndc, 1.0, 1.0);
+    out_direction = world_position.xyz / world_position.w;
+}
@@ -1,11 +1,48 @@
-mod renderer;
-mod model;
+pub mod renderer;
+pub mod model;
 
 use winit::event::ElementState;
 use winit::event_loop;
 use winit::window;
 use winit::event;
 
+/// Errors that can happen while creating the engine.
+#[derive(Debug)]
+pub enum EngineError {
+    Window(winit::error::OsError),
+    Renderer(renderer::RendererError),
+}
+
+impl From<winit::error::OsError> for EngineError {
+    fn from(error: winit::error::OsError) -> Self {
+        EngineError::Window(error)
+    }
+}
+
+impl From<renderer::RendererError> for EngineError {
+    fn from(error: renderer::RendererError) -> Self {
+        EngineError::Renderer(error)
+    }
+}
+
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::Window(error) => write!(f, "Could not create a window: {}", error),
+            EngineError::Renderer(error) => write!(f, "Could not create the renderer: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Window(error) => Some(error),
+            EngineError::Renderer(error) => Some(error),
+        }
+    }
+}
+
 pub struct Engine {
     event_loop: event_loop::EventLoop<()>,
     window: window::Window,
@@ -14,93 +51,94 @@ pub struct Engine {
 }
 
 impl Engine {
-    pub fn new() -> Engine {
+    pub fn new() -> Result<Engine, EngineError> {
         let event_loop = winit::event_loop::EventLoop::new();
-        let window = winit::window::WindowBuilder::new().with_title("Hanokei Engine").build(&event_loop).expect("Could not create a window.");
-        let renderer = renderer::Renderer::new(&window, 4, 3);
+        let window = winit::window::WindowBuilder::new().with_title("Hanokei Engine").build(&event_loop)?;
+        let renderer = renderer::Renderer::new(&window, &renderer::RendererConfig::default())?;
 
-        Engine {
+        Ok(Engine {
             event_loop,
             window,
             renderer
-        }
+        })
     }
     pub fn loop_start(mut self) {
-        // This bool is needed because WindowEvent::Resized with incorrect height and width is sent when program starts:
-        // https://github.com/rust-windowing/winit/issues/2094
-        let mut is_first_resized_event  = true;
-
         let mut is_mouse_button_left_pressed = false;
 
         self.event_loop.run(move |event, _, control_flow| {
-            // Need to check this because when window is minimized,  WindowEvent::Resized is fired with (height: 0, width: 0).
-            let window_inner_size = self.window.inner_size();
-            if !(window_inner_size.height > 0 && window_inner_size.width > 0) { return;}
             match event {
-                event::Event::DeviceEvent { device_id: _, event } => {
-                    match event {
-                        event::DeviceEvent::MouseMotion { delta } => {
-                            if is_mouse_button_left_pressed {
-                                self.renderer.model.rotation += delta.0 as f32 * self.renderer.model.rotation_speed;
-                            }
-                        },
-                        _ => {}
-                    }
+                event::Event::DeviceEvent { event: event::DeviceEvent::MouseMotion { delta }, .. } if is_mouse_button_left_pressed => {
+                    self.renderer.model.rotation += delta.0 as f32 * self.renderer.model.rotation_speed;
                 },
                 event::Event::WindowEvent { window_id, event } if window_id == self.window.id() => match event {
                     event::WindowEvent::CloseRequested => {
                         *control_flow = event_loop::ControlFlow::Exit;
                     },
-                    event::WindowEvent::KeyboardInput { input, .. } => match input {
-                        event::KeyboardInput {virtual_keycode, state, ..} => 
-                            match (virtual_keycode, state) {
-                                (Some(event::VirtualKeyCode::Escape), event::ElementState::Pressed) => {
-                                    *control_flow = event_loop::ControlFlow::Exit;
-                                },
-                                _ => {}
+                    event::WindowEvent::KeyboardInput { input: event::KeyboardInput {virtual_keycode, state, ..}, .. } =>
+                        match (virtual_keycode, state) {
+                            (Some(event::VirtualKeyCode::Escape), event::ElementState::Pressed) => {
+                                *control_flow = event_loop::ControlFlow::Exit;
                             },
-                    },
-                    event::WindowEvent::MouseInput { device_id: _, state, button, modifiers: _ } => {
-                        match button {
-                            event::MouseButton::Left => {
-                                if !is_mouse_button_left_pressed && state == ElementState::Pressed {
-                                    is_mouse_button_left_pressed = true;
-                                } else {
-                                    // Released
-                                    is_mouse_button_left_pressed = false;
+                            (Some(event::VirtualKeyCode::V), event::ElementState::Pressed) => {
+                                if let Some(present_mode) = self.renderer.present_mode() {
+                                    if let Err(error) = self.renderer.set_present_mode(present_mode.next()) {
+                                        eprintln!("Could not change the present mode: {}", error);
+                                        *control_flow = event_loop::ControlFlow::ExitWithCode(1);
+                                    }
                                 }
                             },
-                            _ => {}
-                        }
-                    },
-                    event::WindowEvent::MouseWheel { device_id, delta, phase, modifiers } => {
-                        match delta {
-                            event::MouseScrollDelta::LineDelta(x, y) => {
-                                self.renderer.model.scale -= y * self.renderer.model.scale_speed;
+                            (Some(event::VirtualKeyCode::M), event::ElementState::Pressed) => {
+                                print!("{}", self.renderer.memory_report());
+                            },
+                            (Some(event::VirtualKeyCode::F12), event::ElementState::Pressed) => {
+                                let seconds_since_epoch = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                                    .map(|duration| duration.as_secs()).unwrap_or(0);
+                                if let Err(error) = self.renderer.capture_screenshot(&format!("screenshot_{}.png", seconds_since_epoch)) {
+                                    eprintln!("{}", error);
+                                }
                             },
                             _ => {}
-                        }
+                        },
+                    event::WindowEvent::MouseInput { state, button: event::MouseButton::Left, .. } => {
+                        // Released, or pressed again without a release in between.
+                        is_mouse_button_left_pressed = !is_mouse_button_left_pressed && state == ElementState::Pressed;
+                    },
+                    event::WindowEvent::MouseWheel { delta: event::MouseScrollDelta::LineDelta(_x, y), .. } => {
+                        self.renderer.model.scale -= y * self.renderer.model.scale_speed;
                     },
+                    // Info: WindowEvent::Resized with incorrect height and width is sent when program starts:
+                    // https://github.com/rust-windowing/winit/issues/2094 . It is harmless, since the renderer only recreates
+                    // the swapchain before the next frame and uses the window size at that time.
                     event::WindowEvent::Resized(new_inner_size) => {
-                        if is_first_resized_event {
-                            is_first_resized_event = false;
-                        } else {
-                            // println!("Event::WindowEvent::Resized: {new_inner_size:?}");
-                            self.renderer.on_window_resized(new_inner_size.width, new_inner_size.height);
-                        }
+                        // println!("Event::WindowEvent::Resized: {new_inner_size:?}");
+                        self.renderer.on_window_resized(new_inner_size.width, new_inner_size.height);
                     },
                     _ => {}
                 },
                 event::Event::MainEventsCleared => {
-                    self.window.request_redraw();
+                    // When window is minimized, its size is (height: 0, width: 0). Rendering is paused by waiting for the
+                    // next event, like the resize event of restoring the window, instead of polling.
+                    let window_inner_size = self.window.inner_size();
+                    if window_inner_size.width == 0 || window_inner_size.height == 0 {
+                        control_flow.set_wait();
+                    } else {
+                        control_flow.set_poll();
+                        self.window.request_redraw();
+                    }
                 },
                 event::Event::RedrawRequested(_window_id) => {
                     // println!("Event::Requested");
-                    self.renderer.render_frame(self.window.inner_size());
+                    match self.renderer.render_frame(self.window.inner_size()) {
+                        Ok(()) => {},
+                        // The frame is still rendered and presented, only the screenshot is lost.
+                        Err(error @ renderer::RendererError::ScreenshotFile { .. }) => eprintln!("{}", error),
+                        Err(error) => {
+                            eprintln!("Could not render the frame: {}", error);
+                            *control_flow = event_loop::ControlFlow::ExitWithCode(1);
+                        },
+                    }
                 },
-                _ => {
-                    *control_flow = event_loop::ControlFlow::Poll;
-                }
+                _ => {}
             }
         });
     }
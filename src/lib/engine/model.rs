@@ -1,14 +1,86 @@
 use ash::vk;
+use super::renderer::{RendererError, SamplerDesc, TextureColorSpace};
+
+mod obj_import;
+mod triangulation;
+mod normals;
+mod tangents;
+
+pub use normals::NormalWeighting;
 
 #[repr(C)]
 pub struct Vertex {
-    pub pos: glam::Vec3,
-    pub uv:  glam::Vec2,
+    pub pos:     glam::Vec3,
+    pub uv:      glam::Vec2,
+    pub normal:  glam::Vec3,
+    /// MikkTSpace tangent with the bitangent sign in w, zero unless ModelImportOptions::generate_tangents is set.
+    pub tangent: glam::Vec4,
+}
+
+/// Texture file that is sampled by a model, loaded through the renderer's texture cache.
+pub struct ModelTexture {
+    pub path:        String,
+    pub color_space: TextureColorSpace,
+    /// Identical descriptions share a sampler.
+    pub sampler:     SamplerDesc,
+}
+
+/// Textures of the submeshes that use the material called name.
+pub struct ModelMaterial {
+    pub name:     String,
+    /// The first one is the base color texture.
+    pub textures: Vec<ModelTexture>,
+}
+
+/// Range of a model's vertex indices that is drawn with a single material, from an object or group of the model file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub object_name:   Option<String>,
+    pub group_name:    Option<String>,
+    pub material_name: Option<String>,
+    pub first_index:   u32,
+    pub index_count:   u32,
+}
+
+/// How model files are read and parsed.
+#[derive(Clone, Debug)]
+pub struct ModelImportOptions {
+    /// Maps the file into memory instead of reading it into a buffer first.
+    pub memory_map: bool,
+    /// Parses large files in chunks of lines on every thread of the rayon thread pool.
+    pub parallel: bool,
+    /// Merges the submeshes that use the same material into the first of them, which keeps one draw per material.
+    pub merge_submeshes: bool,
+    /// Weighting of the generated normals, for the vertices that have none in the file.
+    pub normal_weighting: NormalWeighting,
+    /// Generated normals are only smoothed across edges whose triangles meet at up to this angle, in degrees.
+    pub crease_angle: f32,
+    /// Generates tangents for normal mapping, which splits the vertices whose triangles need different ones.
+    pub generate_tangents: bool,
+}
+
+impl Default for ModelImportOptions {
+    fn default() -> Self {
+        ModelImportOptions {
+            memory_map: true,
+            parallel: true,
+            merge_submeshes: false,
+            normal_weighting: NormalWeighting::default(),
+            crease_angle: 60.0,
+            generate_tangents: false,
+        }
+    }
 }
 
 pub struct Model {
     pub vertices:       Vec<Vertex>,
     pub vertex_indices: Vec<u32>,
+    /// Cover every vertex index, in file order.
+    pub submeshes:      Vec<Submesh>,
+    /// Textures of the submeshes without a material, or with one that is not in materials. The first one is the base color
+    /// texture.
+    pub textures:       Vec<ModelTexture>,
+    pub materials:      Vec<ModelMaterial>,
     pub rotation:       f32, 
     pub rotation_speed: f32,
     pub scale:          f32,
@@ -16,58 +88,29 @@ pub struct Model {
 }
 
 impl Model {
-    pub fn new (model_file_path: &str) -> Model {
-
-        let obj = obj::Obj::load(model_file_path).unwrap();
-        
-        let vertex_positions: Vec<glam::Vec3> = obj.data.position.iter().map(|pos| {glam::Vec3::from_array(*pos)}).collect();
-        let vertex_uvs: Vec<glam::Vec2> = obj.data.texture.iter().map(|uv| {
-            // ".obj" files need this operation on 'v-axis' to become compatible with vulkan.
-            glam::Vec2::from_array([uv[0], 1.0f32 - uv[1]])
-        }).collect();
-
-        // TODO: Use a HashSet.
-        let mut unique_index_tuples: Vec<(usize, usize)> = Vec::new();
-        // Each poly has 3 index_types.
-        let polys = &obj.data.objects[0].groups[0].polys;
-        let triangle_count = polys.len() * 3;
-        let mut vertex_indices: Vec<u32> = Vec::with_capacity(triangle_count);
-        for  poly in polys {
-            // Index tuple has: (vertex position index, vertex uv index, vertex normal index).
-            for index_tuple in &poly.0 {
-                let as_tuple = (index_tuple.0, index_tuple.1.unwrap());
-                if unique_index_tuples.contains(&as_tuple) {
-                    let idx = unique_index_tuples.iter().position(|elem| {*elem == as_tuple}).unwrap();
-                    vertex_indices.push(idx as u32);
-                } else {
-                    unique_index_tuples.push(as_tuple);
-                    vertex_indices.push((unique_index_tuples.len() - 1) as u32);
-                }
-            }
-        }
-        
-        println!("There are {} vertex_positions.", vertex_positions.len());
-        println!("There are {} vertex_uvs", vertex_uvs.len());
-        println!("Found     {} unique vertices from vertex_positions and vertex_uvs.", unique_index_tuples.len());
-        println!("There are {} triangles.", triangle_count);
-        println!("There are {} vertex_indices.", vertex_indices.len());
-
-        let mut vertices: Vec<Vertex> = Vec::with_capacity(unique_index_tuples.len());
-        for unique_index_tuple in unique_index_tuples {
-            vertices.push(Vertex { 
-                pos: vertex_positions[unique_index_tuple.0],
-                uv: vertex_uvs[unique_index_tuple.1]
-                })
+    pub fn new (model_file_path: &str, textures: Vec<ModelTexture>, materials: Vec<ModelMaterial>, import_options: &ModelImportOptions)
+    -> Result<Model, RendererError> {
+        let mut mesh = obj_import::import(model_file_path, import_options)?;
+        if import_options.generate_tangents && !tangents::generate_tangents(&mut mesh.vertices, &mut mesh.vertex_indices) {
+            println!("Model import: Could not generate tangents for '{}', they are left zero.", model_file_path);
         }
 
-        Model {
-            vertices,
-            vertex_indices,
+        Ok(Model {
+            vertices: mesh.vertices,
+            vertex_indices: mesh.vertex_indices,
+            submeshes: mesh.submeshes,
+            textures,
+            materials,
             rotation: 1.2,
             rotation_speed: 0.005,
             scale: 1.0,
             scale_speed: 0.2
-        }
+        })
+    }
+    /// Index into materials of the material that submesh is drawn with, None if it is drawn with the textures of the model.
+    pub fn get_submesh_material_idx(&self, submesh: &Submesh) -> Option<usize> {
+        let material_name = submesh.material_name.as_ref()?;
+        self.materials.iter().position(|material| &material.name == material_name)
     }
     #[inline(always)]
     pub fn get_vertex_input_binding_stride () -> u32 {
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+/// How the normals of the triangles around a vertex are weighted when they are averaged into its smooth normal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// By the angle of the triangle's corner at the vertex, which does not depend on how the surface is tessellated.
+    #[default]
+    Angle,
+    /// By the area of the triangle, so bigger triangles bend the normal more.
+    Area,
+}
+
+/// Generates a smooth normal for every corner of a triangle list, corner_positions has the position index of each corner.
+/// The normals of the triangles around a corner are averaged, but only the ones within crease_angle radians of the
+/// corner's own triangle, so sharper edges stay hard.
+///
+/// Info: Corners are smoothed by their position values, since files often duplicate positions along texture seams.
+/// Triangles are counter clockwise.
+pub fn generate_normals(positions: &[glam::Vec3], corner_positions: &[u32], weighting: NormalWeighting, crease_angle: f32)
+-> Vec<glam::Vec3> {
+    let mut welded_positions: HashMap<[u32; 3], u32> = HashMap::new();
+    let corner_welded_positions: Vec<u32> = corner_positions.iter().map(|position_index| {
+        let welded_position_count = welded_positions.len() as u32;
+        *welded_positions.entry(positions[*position_index as usize].to_array().map(f32::to_bits)).or_insert(welded_position_count)
+    }).collect();
+
+    // Cross products are twice the triangle areas long.
+    let triangle_normals: Vec<glam::Vec3> = corner_positions.chunks_exact(3).map(|triangle| {
+        let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+        (b - a).cross(c - a)
+    }).collect();
+    let corner_weights: Vec<f32> = (0..corner_positions.len()).map(|corner| match weighting {
+        NormalWeighting::Angle => {
+            let triangle = corner - corner % 3;
+            let position = |corner_in_triangle: usize| positions[corner_positions[triangle + corner_in_triangle % 3] as usize];
+            let current = position(corner % 3);
+            (position(corner % 3 + 1) - current).angle_between(position(corner % 3 + 2) - current)
+        },
+        NormalWeighting::Area => triangle_normals[corner / 3].length(),
+    }).map(|weight| if weight.is_finite() { weight } else { 0.0 }).collect();
+    let triangle_unit_normals: Vec<glam::Vec3> = triangle_normals.iter().map(|normal| normal.normalize_or_zero()).collect();
+
+    // Corners of every welded position, one range after another.
+    let mut welded_position_offsets = vec![0usize; welded_positions.len() + 1];
+    for welded_position in &corner_welded_positions {
+        welded_position_offsets[*welded_position as usize + 1] += 1;
+    }
+    for idx in 1..welded_position_offsets.len() {
+        welded_position_offsets[idx] += welded_position_offsets[idx - 1];
+    }
+    let mut fill_offsets = welded_position_offsets.clone();
+    let mut welded_position_corners = vec![0usize; corner_positions.len()];
+    for (corner, welded_position) in corner_welded_positions.iter().enumerate() {
+        welded_position_corners[fill_offsets[*welded_position as usize]] = corner;
+        fill_offsets[*welded_position as usize] += 1;
+    }
+
+    let min_cos_angle = crease_angle.cos();
+    corner_welded_positions.iter().enumerate().map(|(corner, welded_position)| {
+        let corners_around = &welded_position_corners[welded_position_offsets[*welded_position as usize]..
+            welded_position_offsets[*welded_position as usize + 1]];
+        let triangle_normal = triangle_unit_normals[corner / 3];
+        let smooth_normal: glam::Vec3 = corners_around.iter()
+            .filter(|corner_around| triangle_normal.dot(triangle_unit_normals[**corner_around / 3]) >= min_cos_angle)
+            .map(|corner_around| triangle_unit_normals[*corner_around / 3] * corner_weights[*corner_around])
+            .sum();
+        // Degenerate triangles have no normal of their own, they get the unweighted one of their surroundings.
+        match smooth_normal.try_normalize() {
+            Some(smooth_normal) => smooth_normal,
+            None => corners_around.iter().map(|corner_around| triangle_unit_normals[*corner_around / 3]).sum::<glam::Vec3>()
+                .try_normalize().unwrap_or(glam::Vec3::Z),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_normal_eq(normal: glam::Vec3, expected: glam::Vec3) {
+        assert!(normal.abs_diff_eq(expected.normalize(), 1e-5), "{} != {}", normal, expected.normalize());
+    }
+
+    /// Two triangles that meet at a right angle along the edge from the origin to +Y, facing +Z and +X.
+    const RIGHT_ANGLE_POSITIONS: [glam::Vec3; 4] = [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, glam::Vec3::NEG_Z];
+    const RIGHT_ANGLE_CORNERS: [u32; 6] = [0, 1, 2, 0, 3, 2];
+
+    #[test]
+    fn edges_sharper_than_the_crease_angle_stay_hard() {
+        let normals = generate_normals(&RIGHT_ANGLE_POSITIONS, &RIGHT_ANGLE_CORNERS, NormalWeighting::Angle, 60f32.to_radians());
+        normals[0..3].iter().for_each(|normal| assert_normal_eq(*normal, glam::Vec3::Z));
+        normals[3..6].iter().for_each(|normal| assert_normal_eq(*normal, glam::Vec3::X));
+    }
+
+    #[test]
+    fn edges_within_the_crease_angle_are_smoothed() {
+        let normals = generate_normals(&RIGHT_ANGLE_POSITIONS, &RIGHT_ANGLE_CORNERS, NormalWeighting::Angle, 100f32.to_radians());
+        // Shared corners, with the same corner angle in both triangles.
+        for corner in [0, 2, 3, 5] {
+            assert_normal_eq(normals[corner], glam::Vec3::new(1.0, 0.0, 1.0));
+        }
+        assert_normal_eq(normals[1], glam::Vec3::Z);
+        assert_normal_eq(normals[4], glam::Vec3::X);
+    }
+
+    #[test]
+    fn duplicated_positions_are_smoothed_together() {
+        let mut positions = RIGHT_ANGLE_POSITIONS.to_vec();
+        positions.extend_from_slice(&[glam::Vec3::ZERO, glam::Vec3::Y]);
+        let normals = generate_normals(&positions, &[0, 1, 2, 4, 3, 5], NormalWeighting::Angle, 100f32.to_radians());
+        assert_normal_eq(normals[0], glam::Vec3::new(1.0, 0.0, 1.0));
+        assert_normal_eq(normals[3], glam::Vec3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn weighting_by_angle_or_area() {
+        // A big triangle facing +Z with a right angle at the origin, and a small one facing +X with a 45 degree angle there.
+        let positions = [glam::Vec3::ZERO, glam::Vec3::X * 4.0, glam::Vec3::Y * 4.0, glam::Vec3::Y, glam::Vec3::new(0.0, 1.0, 1.0)];
+        let corner_positions = [0, 1, 2, 0, 3, 4];
+        let normals = generate_normals(&positions, &corner_positions, NormalWeighting::Angle, 100f32.to_radians());
+        assert_normal_eq(normals[0], glam::Vec3::new(0.5, 0.0, 1.0));
+        let normals = generate_normals(&positions, &corner_positions, NormalWeighting::Area, 100f32.to_radians());
+        assert_normal_eq(normals[0], glam::Vec3::new(0.5, 0.0, 8.0));
+    }
+
+    #[test]
+    fn degenerate_triangles_get_the_normal_of_their_surroundings() {
+        let positions = [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, glam::Vec3::X * 2.0];
+        let normals = generate_normals(&positions, &[0, 1, 2, 0, 1, 3], NormalWeighting::Angle, 60f32.to_radians());
+        normals.iter().for_each(|normal| assert_normal_eq(*normal, glam::Vec3::Z));
+        // Nothing around it.
+        let normals = generate_normals(&positions, &[0, 1, 3], NormalWeighting::Area, 60f32.to_radians());
+        normals.iter().for_each(|normal| assert_normal_eq(*normal, glam::Vec3::Z));
+    }
+}
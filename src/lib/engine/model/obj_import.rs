@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use rayon::prelude::*;
+use super::{normals, triangulation, ModelImportOptions, RendererError, Submesh, Vertex};
+
+/// Files are split into chunks of about this size at least, so that small files are parsed by a single thread.
+const MIN_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Deduplicated vertices and triangle list indices of an OBJ file.
+pub struct ObjMesh {
+    pub vertices: Vec<Vertex>,
+    pub vertex_indices: Vec<u32>,
+    pub submeshes: Vec<Submesh>,
+}
+
+/// Statement that starts a new submesh.
+enum SubmeshStatement {
+    Object(String),
+    Group(String),
+    Material(String),
+}
+
+/// Index of a position, texture coordinate or normal that a face refers to.
+#[derive(Clone, Copy)]
+enum FaceIndex {
+    /// 0 based index into every element of the file.
+    Absolute(u32),
+    /// Negative OBJ indices count back from the last element that is defined before the face, so they are relative to the
+    /// first element of the chunk until the chunks before it are parsed. Can be negative.
+    ChunkRelative(i64),
+}
+
+/// Texture coordinate and normal indices are optional.
+#[derive(Clone, Copy)]
+struct FaceCorner {
+    position: FaceIndex,
+    uv: Option<FaceIndex>,
+    normal: Option<FaceIndex>,
+}
+
+/// Normal of a vertex, which is either in the file or generated for the corners that do not have one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum VertexNormal {
+    File(u32),
+    /// Bits of the generated normal, so that corners with the same one share a vertex.
+    Generated([u32; 3]),
+}
+
+/// Elements of a range of whole lines.
+#[derive(Default)]
+struct ObjChunk {
+    positions: Vec<glam::Vec3>,
+    uvs: Vec<glam::Vec2>,
+    normals: Vec<glam::Vec3>,
+    /// Every corner of every face.
+    face_vertices: Vec<FaceCorner>,
+    /// Corner count of every face.
+    face_sizes: Vec<u32>,
+    /// Chunk line of every face, 0 based, for index errors.
+    face_lines: Vec<u32>,
+    /// Statements with the number of faces before them in the chunk.
+    submesh_statements: Vec<(usize, SubmeshStatement)>,
+}
+
+/// Parses an OBJ file into a triangle list, with every unique position, texture coordinate and normal combination as a
+/// vertex. Polygons are triangulated, see triangulation::triangulate(). Face corners without a normal get a generated one,
+/// see normals::generate_normals(). Every object, group and material change starts a new submesh, empty ones are left out.
+///
+/// Info: Texture coordinates are flipped vertically for Vulkan, face corners without one get (0, 0). Material libraries
+/// are ignored.
+pub fn import(path: &str, options: &ModelImportOptions) -> Result<ObjMesh, RendererError> {
+    let start_time = Instant::now();
+    let mapped_file;
+    let read_file;
+    let bytes: &[u8] = if options.memory_map {
+        // Note: The map is only valid as long as nobody truncates the file, which is accepted for model files like it is
+        // in other tools.
+        mapped_file = std::fs::File::open(path).and_then(|file| unsafe{memmap2::Mmap::map(&file)})
+            .map_err(|error| RendererError::ModelFile { path: path.to_owned(), error })?;
+        &mapped_file
+    } else {
+        read_file = std::fs::read(path).map_err(|error| RendererError::ModelFile { path: path.to_owned(), error })?;
+        &read_file
+    };
+    let read_time = Instant::now();
+
+    let chunk_ranges = split_into_chunks(bytes, if options.parallel { rayon::current_num_threads() * 4 } else { 1 });
+    let parse_chunk = |range: &std::ops::Range<usize>| parse_chunk(&bytes[range.clone()])
+        .map_err(|(chunk_line, reason)| parse_error(path, bytes, range.start, chunk_line, reason));
+    let chunks: Vec<ObjChunk> = if options.parallel {
+        chunk_ranges.par_iter().map(parse_chunk).collect::<Result<_, _>>()?
+    } else {
+        chunk_ranges.iter().map(parse_chunk).collect::<Result<_, _>>()?
+    };
+    let parse_time = Instant::now();
+
+    // Every element is gathered first, since faces can refer to the ones in other chunks.
+    let positions: Vec<glam::Vec3> = chunks.iter().flat_map(|chunk| chunk.positions.iter().copied()).collect();
+    let uvs: Vec<glam::Vec2> = chunks.iter().flat_map(|chunk| chunk.uvs.iter().copied()).collect();
+    let file_normals: Vec<glam::Vec3> = chunks.iter().flat_map(|chunk| chunk.normals.iter().copied()).collect();
+    let corner_count: usize = chunks.iter().map(|chunk| chunk.face_vertices.len()).sum();
+    // Position, texture coordinate and normal indices of every triangle corner.
+    let mut triangle_corners: Vec<(u32, Option<u32>, Option<u32>)> = Vec::with_capacity(corner_count);
+    let mut submeshes: Vec<Submesh> = Vec::new();
+    let mut submesh = Submesh { object_name: None, group_name: None, material_name: None, first_index: 0, index_count: 0 };
+    let (mut chunk_position_base, mut chunk_uv_base, mut chunk_normal_base) = (0, 0, 0);
+    let mut polygon_count = 0;
+    // Reused for every face.
+    let mut face_corner_keys: Vec<(u32, Option<u32>, Option<u32>)> = Vec::new();
+    let mut polygon: Vec<glam::Vec3> = Vec::new();
+    for (chunk, range) in chunks.iter().zip(&chunk_ranges) {
+        let mut submesh_statements = chunk.submesh_statements.iter().peekable();
+        let mut face_corners = chunk.face_vertices.as_slice();
+        for (face_idx, (face_size, face_line)) in chunk.face_sizes.iter().zip(&chunk.face_lines).enumerate() {
+            while let Some((_, statement)) = submesh_statements.next_if(|(statement_face_idx, _)| *statement_face_idx <= face_idx) {
+                start_submesh(&mut submeshes, &mut submesh, statement, triangle_corners.len() as u32);
+            }
+            let (face, next_face_corners) = face_corners.split_at(*face_size as usize);
+            face_corners = next_face_corners;
+
+            face_corner_keys.clear();
+            polygon.clear();
+            for corner in face {
+                let resolve_key = || -> Result<_, String> {
+                    Ok((resolve_index(corner.position, chunk_position_base, positions.len(), "position")?,
+                        corner.uv.map(|uv| resolve_index(uv, chunk_uv_base, uvs.len(), "texture coordinate")).transpose()?,
+                        corner.normal.map(|normal| resolve_index(normal, chunk_normal_base, file_normals.len(), "normal")).transpose()?))
+                };
+                let key = resolve_key().map_err(|reason| parse_error(path, bytes, range.start, *face_line as usize, reason))?;
+                face_corner_keys.push(key);
+                polygon.push(positions[key.0 as usize]);
+            }
+            if face.len() == 3 {
+                triangle_corners.extend_from_slice(&face_corner_keys);
+            } else {
+                polygon_count += 1;
+                for triangle in triangulation::triangulate(&polygon) {
+                    triangle_corners.extend(triangle.iter().map(|corner| face_corner_keys[*corner]));
+                }
+            }
+        }
+        // Statements after the last face of the chunk.
+        for (_, statement) in submesh_statements {
+            start_submesh(&mut submeshes, &mut submesh, statement, triangle_corners.len() as u32);
+        }
+        chunk_position_base += chunk.positions.len() as i64;
+        chunk_uv_base += chunk.uvs.len() as i64;
+        chunk_normal_base += chunk.normals.len() as i64;
+    }
+    let triangulation_time = Instant::now();
+
+    let generated_normal_count = triangle_corners.iter().filter(|(_, _, normal_index)| normal_index.is_none()).count();
+    let generated_normals = if generated_normal_count > 0 {
+        let corner_positions: Vec<u32> = triangle_corners.iter().map(|(position_index, _, _)| *position_index).collect();
+        normals::generate_normals(&positions, &corner_positions, options.normal_weighting, options.crease_angle.to_radians())
+    } else {
+        Vec::new()
+    };
+    let normals_time = Instant::now();
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut vertex_indices: Vec<u32> = Vec::with_capacity(triangle_corners.len());
+    let mut unique_vertices: HashMap<(u32, Option<u32>, VertexNormal), u32> = HashMap::new();
+    for (corner, (position_index, uv_index, normal_index)) in triangle_corners.iter().enumerate() {
+        let normal = match normal_index {
+            Some(normal_index) => VertexNormal::File(*normal_index),
+            None => VertexNormal::Generated(generated_normals[corner].to_array().map(f32::to_bits)),
+        };
+        let vertex_index = *unique_vertices.entry((*position_index, *uv_index, normal)).or_insert_with(|| {
+            vertices.push(Vertex {
+                pos: positions[*position_index as usize],
+                uv: uv_index.map_or(glam::Vec2::ZERO, |uv_index| uvs[uv_index as usize]),
+                normal: match normal {
+                    VertexNormal::File(normal_index) => file_normals[normal_index as usize].normalize_or_zero(),
+                    VertexNormal::Generated(_) => generated_normals[corner],
+                },
+                tangent: glam::Vec4::ZERO,
+            });
+            (vertices.len() - 1) as u32
+        });
+        vertex_indices.push(vertex_index);
+    }
+    submesh.index_count = vertex_indices.len() as u32 - submesh.first_index;
+    if submesh.index_count > 0 {
+        submeshes.push(submesh);
+    }
+    let submesh_count = submeshes.len();
+    if options.merge_submeshes {
+        submeshes = merge_submeshes(submeshes, &mut vertex_indices);
+    }
+    let dedup_time = Instant::now();
+
+    println!("Model import: '{}', {} positions, {} texture coordinates, {} normals, {} unique vertices, {} triangles.", path,
+        positions.len(), uvs.len(), file_normals.len(), vertices.len(), vertex_indices.len() / 3);
+    println!("Model import: {} triangulated polygons, {} generated normals, {} submeshes{}.", polygon_count, generated_normal_count,
+        submesh_count, if options.merge_submeshes { format!(" merged into {} by material", submeshes.len()) } else { String::new() });
+    println!("Model import: Read in {:?}, parsed {} chunks in {:?}, triangulated in {:?}, generated normals in {:?}, \
+        deduplicated in {:?}, {:?} in total.", read_time - start_time, chunk_ranges.len(), parse_time - read_time,
+        triangulation_time - parse_time, normals_time - triangulation_time, dedup_time - normals_time, dedup_time - start_time);
+    Ok(ObjMesh {
+        vertices,
+        vertex_indices,
+        submeshes,
+    })
+}
+
+/// Ends submesh at first_index, and starts the next one with the object, group or material of statement.
+fn start_submesh(submeshes: &mut Vec<Submesh>, submesh: &mut Submesh, statement: &SubmeshStatement, first_index: u32) {
+    submesh.index_count = first_index - submesh.first_index;
+    let mut next_submesh = Submesh { first_index, index_count: 0, ..submesh.clone() };
+    match statement {
+        // Groups and materials do not carry over to the next object.
+        SubmeshStatement::Object(name) => {
+            next_submesh.object_name = Some(name.clone());
+            next_submesh.group_name = None;
+            next_submesh.material_name = None;
+        },
+        SubmeshStatement::Group(name) => next_submesh.group_name = Some(name.clone()),
+        SubmeshStatement::Material(name) => next_submesh.material_name = Some(name.clone()),
+    }
+    let submesh = std::mem::replace(submesh, next_submesh);
+    if submesh.index_count > 0 {
+        submeshes.push(submesh);
+    }
+}
+
+/// Merges the submeshes with the same material into the first of them. Reorders vertex_indices, so that the indices of
+/// each merged submesh are next to each other.
+fn merge_submeshes(submeshes: Vec<Submesh>, vertex_indices: &mut Vec<u32>) -> Vec<Submesh> {
+    let mut merged_submeshes: Vec<(Submesh, Vec<u32>)> = Vec::new();
+    for submesh in submeshes {
+        let range = submesh.first_index as usize..(submesh.first_index + submesh.index_count) as usize;
+        match merged_submeshes.iter_mut().find(|(merged, _)| merged.material_name == submesh.material_name) {
+            Some((_, indices)) => indices.extend_from_slice(&vertex_indices[range]),
+            None => merged_submeshes.push((submesh, vertex_indices[range].to_vec())),
+        }
+    }
+    vertex_indices.clear();
+    merged_submeshes.into_iter().map(|(mut submesh, indices)| {
+        submesh.first_index = vertex_indices.len() as u32;
+        submesh.index_count = indices.len() as u32;
+        vertex_indices.extend_from_slice(&indices);
+        submesh
+    }).collect()
+}
+
+/// Splits bytes into about chunk_count ranges of whole lines.
+fn split_into_chunks(bytes: &[u8], chunk_count: usize) -> Vec<std::ops::Range<usize>> {
+    let chunk_size = usize::max(bytes.len() / chunk_count, MIN_CHUNK_SIZE);
+    let mut ranges = Vec::with_capacity(chunk_count);
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = match bytes[usize::min(start + chunk_size, bytes.len())..].iter().position(|byte| *byte == b'\n') {
+            Some(newline_offset) => start + chunk_size + newline_offset + 1,
+            None => bytes.len(),
+        };
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+/// Returns the 0 based chunk line and the reason on errors.
+fn parse_chunk(bytes: &[u8]) -> Result<ObjChunk, (usize, String)> {
+    let mut chunk = ObjChunk::default();
+    for (line_idx, line) in bytes.split(|byte| *byte == b'\n').enumerate() {
+        let mut tokens = line.split(|byte| byte.is_ascii_whitespace()).filter(|token| !token.is_empty());
+        let result = match tokens.next() {
+            Some(b"v") => parse_floats::<3>(&mut tokens, 3).map(|[x, y, z]| chunk.positions.push(glam::Vec3::new(x, y, z))),
+            Some(b"vt") => parse_floats::<2>(&mut tokens, 1).map(|[u, v]| {
+                // ".obj" files need this operation on 'v-axis' to become compatible with vulkan.
+                chunk.uvs.push(glam::Vec2::new(u, 1.0 - v));
+            }),
+            Some(b"vn") => parse_floats::<3>(&mut tokens, 3).map(|[x, y, z]| chunk.normals.push(glam::Vec3::new(x, y, z))),
+            Some(b"f") => parse_face(&mut tokens, &mut chunk).map(|()| chunk.face_lines.push(line_idx as u32)),
+            Some(b"o") => parse_name(&mut tokens).ok_or_else(|| "object has no name".to_owned()).map(|name| {
+                chunk.submesh_statements.push((chunk.face_sizes.len(), SubmeshStatement::Object(name)));
+            }),
+            // Faces without a group name are in the default group.
+            Some(b"g") => {
+                let name = parse_name(&mut tokens).unwrap_or_else(|| "default".to_owned());
+                chunk.submesh_statements.push((chunk.face_sizes.len(), SubmeshStatement::Group(name)));
+                Ok(())
+            },
+            Some(b"usemtl") => parse_name(&mut tokens).ok_or_else(|| "material has no name".to_owned()).map(|name| {
+                chunk.submesh_statements.push((chunk.face_sizes.len(), SubmeshStatement::Material(name)));
+            }),
+            // Comments, material libraries and smoothing groups.
+            _ => Ok(()),
+        };
+        result.map_err(|reason| (line_idx, reason))?;
+    }
+    Ok(chunk)
+}
+
+/// Parses the first N numbers of an element, of which required_count must be there. Missing ones are 0, the optional ones
+/// after them are ignored.
+fn parse_floats<'a, const N: usize>(tokens: &mut impl Iterator<Item = &'a [u8]>, required_count: usize) -> Result<[f32; N], String> {
+    let mut values = [0.0f32; N];
+    for (idx, value) in values.iter_mut().enumerate() {
+        let Some(token) = tokens.next() else {
+            if idx < required_count {
+                return Err(format!("expected at least {} numbers", required_count));
+            }
+            break;
+        };
+        *value = parse_token(token).ok_or_else(|| format!("'{}' is not a number", String::from_utf8_lossy(token)))?;
+    }
+    Ok(values)
+}
+
+/// Names can have spaces in them, groups also list every group name of the faces after them like that.
+fn parse_name<'a>(tokens: &mut impl Iterator<Item = &'a [u8]>) -> Option<String> {
+    let name = tokens.map(String::from_utf8_lossy).collect::<Vec<_>>().join(" ");
+    (!name.is_empty()).then_some(name)
+}
+
+fn parse_token<T: std::str::FromStr>(token: &[u8]) -> Option<T> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// Parses the position/texture coordinate/normal index triplets of a face into chunk.
+fn parse_face<'a>(tokens: &mut impl Iterator<Item = &'a [u8]>, chunk: &mut ObjChunk) -> Result<(), String> {
+    let first_corner = chunk.face_vertices.len();
+    for token in tokens {
+        let mut indices = token.split(|byte| *byte == b'/');
+        let position_index = indices.next().filter(|index| !index.is_empty())
+            .ok_or_else(|| format!("face vertex '{}' has no position index", String::from_utf8_lossy(token)))?;
+        let uv_index = indices.next().filter(|index| !index.is_empty());
+        let normal_index = indices.next().filter(|index| !index.is_empty());
+        chunk.face_vertices.push(FaceCorner {
+            position: parse_face_index(position_index, chunk.positions.len())?,
+            uv: uv_index.map(|uv_index| parse_face_index(uv_index, chunk.uvs.len())).transpose()?,
+            normal: normal_index.map(|normal_index| parse_face_index(normal_index, chunk.normals.len())).transpose()?,
+        });
+    }
+    let face_size = chunk.face_vertices.len() - first_corner;
+    if face_size < 3 {
+        return Err(format!("faces need at least 3 vertices, this one has {}", face_size));
+    }
+    chunk.face_sizes.push(face_size as u32);
+    Ok(())
+}
+
+/// chunk_element_count is the number of elements of the same kind that are defined in the chunk before the face.
+fn parse_face_index(token: &[u8], chunk_element_count: usize) -> Result<FaceIndex, String> {
+    match parse_token::<i64>(token) {
+        Some(index) if index > 0 && index <= u32::MAX as i64 => Ok(FaceIndex::Absolute((index - 1) as u32)),
+        Some(index) if index < 0 => Ok(FaceIndex::ChunkRelative(chunk_element_count as i64 + index)),
+        _ => Err(format!("'{}' is not a valid index", String::from_utf8_lossy(token))),
+    }
+}
+
+/// chunk_base is the number of elements that are defined before the chunk, element_count the number of all of them.
+fn resolve_index(index: FaceIndex, chunk_base: i64, element_count: usize, element_name: &str) -> Result<u32, String> {
+    let index = match index {
+        FaceIndex::Absolute(index) => index as i64,
+        FaceIndex::ChunkRelative(index) => chunk_base + index,
+    };
+    if index < 0 || index >= element_count as i64 {
+        return Err(format!("{} index {} is out of range, there are {}", element_name, index + 1, element_count));
+    }
+    Ok(index as u32)
+}
+
+fn parse_error(path: &str, bytes: &[u8], chunk_start: usize, chunk_line: usize, reason: String) -> RendererError {
+    let line = bytes[..chunk_start].iter().filter(|byte| **byte == b'\n').count() + chunk_line + 1;
+    RendererError::InvalidModelFile { path: path.to_owned(), line, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes contents to a file in the temporary directory and imports it.
+    fn import_str(file_name: &str, contents: &str, options: &ModelImportOptions) -> Result<ObjMesh, RendererError> {
+        let path = std::env::temp_dir().join(format!("hanokei_test_{}.obj", file_name));
+        std::fs::write(&path, contents).unwrap();
+        let mesh = import(path.to_str().unwrap(), options);
+        std::fs::remove_file(&path).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn deduplicates_vertices_and_flips_texture_coordinates() {
+        let mesh = import_str("dedup", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0.25\nvn 0 0 2\n\
+            f 1/1/1 2/2/1 3/2/1\nf 1/1/1 3/2/1 4/2/1\n", &ModelImportOptions::default()).unwrap();
+        assert_eq!(mesh.vertex_indices.len(), 6);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.vertices[0].uv, glam::Vec2::new(0.0, 1.0));
+        assert_eq!(mesh.vertices[1].uv, glam::Vec2::new(1.0, 0.75));
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == glam::Vec3::Z));
+    }
+
+    #[test]
+    fn resolves_negative_indices_and_generates_missing_normals() {
+        let mesh = import_str("negative", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n", &ModelImportOptions::default()).unwrap();
+        let positions: Vec<glam::Vec3> = mesh.vertex_indices.iter().map(|index| mesh.vertices[*index as usize].pos).collect();
+        assert_eq!(positions, [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y]);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == glam::Vec3::Z && vertex.uv == glam::Vec2::ZERO));
+    }
+
+    #[test]
+    fn resolves_negative_indices_across_chunks() {
+        // Big enough for several chunks, with the face in a later chunk than its positions.
+        let mut contents = "v 0 0 0\n".repeat(3 * MIN_CHUNK_SIZE / 8);
+        contents.push_str("v 1 0 0\nv 0 1 0\nf 1 -2 -1\n");
+        assert!(split_into_chunks(contents.as_bytes(), 4).len() > 1);
+        let options = ModelImportOptions { parallel: true, ..ModelImportOptions::default() };
+        let mesh = import_str("chunks", &contents, &options).unwrap();
+        let positions: Vec<glam::Vec3> = mesh.vertex_indices.iter().map(|index| mesh.vertices[*index as usize].pos).collect();
+        assert_eq!(positions, [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y]);
+    }
+
+    #[test]
+    fn chunks_end_after_newlines_and_cover_every_byte() {
+        let bytes = "v 0.5 0.5 0.5\n".repeat(MIN_CHUNK_SIZE / 4);
+        let ranges = split_into_chunks(bytes.as_bytes(), 3);
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, bytes.len());
+        for (range, next_range) in ranges.iter().zip(ranges.iter().skip(1)) {
+            assert_eq!(range.end, next_range.start);
+            assert_eq!(bytes.as_bytes()[range.end - 1], b'\n');
+        }
+    }
+
+    #[test]
+    fn splits_submeshes_by_object_group_and_material() {
+        let contents = "v 0 0 0\nv 1 0 0\nv 0 1 0\no first\nusemtl red\nf 1 2 3\ng empty\ng second\nf 1 2 3\n\
+            o other\nf 1 2 3\nusemtl red\nf 1 2 3\n";
+        let mesh = import_str("submeshes", contents, &ModelImportOptions::default()).unwrap();
+        let submesh = |object_name: &str, group_name: Option<&str>, material_name: Option<&str>, first_index| Submesh {
+            object_name: Some(object_name.to_owned()),
+            group_name: group_name.map(str::to_owned),
+            material_name: material_name.map(str::to_owned),
+            first_index,
+            index_count: 3,
+        };
+        assert_eq!(mesh.submeshes, [submesh("first", None, Some("red"), 0), submesh("first", Some("second"), Some("red"), 3),
+            submesh("other", None, None, 6), submesh("other", None, Some("red"), 9)]);
+
+        let options = ModelImportOptions { merge_submeshes: true, ..ModelImportOptions::default() };
+        let mesh = import_str("merged_submeshes", contents, &options).unwrap();
+        assert_eq!(mesh.submeshes.len(), 2);
+        assert_eq!((mesh.submeshes[0].material_name.as_deref(), mesh.submeshes[0].index_count), (Some("red"), 9));
+        assert_eq!((mesh.submeshes[1].material_name.as_deref(), mesh.submeshes[1].first_index), (None, 9));
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_faces() {
+        let error = |file_name, contents| match import_str(file_name, contents, &ModelImportOptions::default()) {
+            Err(RendererError::InvalidModelFile { line, reason, .. }) => (line, reason),
+            _ => panic!("'{}' was imported", contents),
+        };
+        assert_eq!(error("out_of_range", "v 0 0 0\nv 1 0 0\n\nf 1 2 3\n"),
+            (4, "position index 3 is out of range, there are 2".to_owned()));
+        assert_eq!(error("two_corners", "v 0 0 0\nv 1 0 0\nf 1 2\n"),
+            (3, "faces need at least 3 vertices, this one has 2".to_owned()));
+        assert_eq!(error("zero_index", "v 0 0 0\nf 0 1 1\n"), (2, "'0' is not a valid index".to_owned()));
+        assert_eq!(error("not_a_number", "v 0 zero 0\n"), (1, "'zero' is not a number".to_owned()));
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use super::Vertex;
+
+/// Triangle list that MikkTSpace reads the corners of and writes a tangent for every corner to.
+struct TangentGeometry<'a> {
+    vertices: &'a [Vertex],
+    vertex_indices: &'a [u32],
+    corner_tangents: Vec<glam::Vec4>,
+}
+
+impl TangentGeometry<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.vertex_indices[face * 3 + vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.vertex_indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).pos.to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.to_array()
+    }
+
+    /// Flips the texture coordinates back, see generate_tangents().
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.vertex(face, vert).uv;
+        [uv.x, 1.0 - uv.y]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = glam::Vec4::from_array(tangent);
+    }
+}
+
+/// Generates MikkTSpace tangents for a triangle list, with the bitangent sign in w. Vertices are split where the triangles
+/// that share them need different tangents, which keeps vertex_indices the same length and order. Returns false if
+/// MikkTSpace rejects the mesh, the tangents are left as they are then.
+///
+/// Info: MikkTSpace gets the texture coordinates of the file, before they are flipped for Vulkan, so that the tangents
+/// match the ones that Blender and Substance bake normal maps with.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, vertex_indices: &mut [u32]) -> bool {
+    let start_time = Instant::now();
+    let mut geometry = TangentGeometry {
+        vertices,
+        vertex_indices,
+        corner_tangents: vec![glam::Vec4::ZERO; vertex_indices.len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return false;
+    }
+    let corner_tangents = geometry.corner_tangents;
+
+    let vertex_count = vertices.len();
+    let mut split_vertices: Vec<Vertex> = Vec::with_capacity(vertex_count);
+    let mut unique_vertices: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    for (vertex_index, tangent) in vertex_indices.iter_mut().zip(corner_tangents) {
+        *vertex_index = *unique_vertices.entry((*vertex_index, tangent.to_array().map(f32::to_bits))).or_insert_with(|| {
+            split_vertices.push(Vertex { tangent, ..vertices[*vertex_index as usize] });
+            (split_vertices.len() - 1) as u32
+        });
+    }
+    *vertices = split_vertices;
+    println!("Model import: Generated tangents in {:?}, {} vertices are split into {}.", start_time.elapsed(), vertex_count,
+        vertices.len());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tangent_eq(tangent: glam::Vec4, expected: glam::Vec4) {
+        assert!(tangent.abs_diff_eq(expected, 1e-5), "{} != {}", tangent, expected);
+    }
+
+    /// Vertices in the XY plane facing +Z, with the texture coordinates of the file flipped like the importer does.
+    fn vertices(positions_and_file_uvs: &[(glam::Vec2, glam::Vec2)]) -> Vec<Vertex> {
+        positions_and_file_uvs.iter().map(|(position, file_uv)| Vertex {
+            pos: position.extend(0.0),
+            uv: glam::Vec2::new(file_uv.x, 1.0 - file_uv.y),
+            normal: glam::Vec3::Z,
+            tangent: glam::Vec4::ZERO,
+        }).collect()
+    }
+
+    /// Unit quad with corners (0, 0), (1, 0), (1, 1) and (0, 1), file V grows with +Y.
+    fn quad(file_us: [f32; 4]) -> Vec<Vertex> {
+        let positions = [glam::Vec2::ZERO, glam::Vec2::X, glam::Vec2::ONE, glam::Vec2::Y];
+        let positions_and_file_uvs: Vec<_> = positions.iter().zip(file_us)
+            .map(|(position, file_u)| (*position, glam::Vec2::new(file_u, position.y))).collect();
+        vertices(&positions_and_file_uvs)
+    }
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    #[test]
+    fn increasing_file_u_gives_x_tangents() {
+        let mut vertices = quad([0.0, 1.0, 1.0, 0.0]);
+        let mut vertex_indices = QUAD_INDICES;
+        assert!(generate_tangents(&mut vertices, &mut vertex_indices));
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(vertex_indices, QUAD_INDICES);
+        // The bitangent follows file V, which is +Y, so it is cross(normal, tangent) and the sign is positive.
+        vertices.iter().for_each(|vertex| assert_tangent_eq(vertex.tangent, glam::Vec4::new(1.0, 0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn mirrored_u_flips_the_tangent_and_its_sign() {
+        let mut vertices = quad([1.0, 0.0, 0.0, 1.0]);
+        let mut vertex_indices = QUAD_INDICES;
+        assert!(generate_tangents(&mut vertices, &mut vertex_indices));
+        vertices.iter().for_each(|vertex| assert_tangent_eq(vertex.tangent, glam::Vec4::new(-1.0, 0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn vertices_on_a_mirror_seam_are_split() {
+        // Two quads side by side, sharing the vertices at x = 1. U is mirrored at the seam, so the right quad has -X tangents.
+        let positions_and_file_uvs: Vec<_> = (0..6).map(|idx| {
+            let position = glam::Vec2::new((idx % 3) as f32, (idx / 3) as f32);
+            (position, glam::Vec2::new(1.0 - (position.x - 1.0).abs(), position.y))
+        }).collect();
+        let original_vertices = vertices(&positions_and_file_uvs);
+        let original_indices = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+        let mut vertices = vertices(&positions_and_file_uvs);
+        let mut vertex_indices = original_indices;
+        assert!(generate_tangents(&mut vertices, &mut vertex_indices));
+
+        assert_eq!(vertices.len(), 8);
+        // Every corner still has the position and texture coordinates of its original vertex, in the same order.
+        for (index, original_index) in vertex_indices.iter().zip(original_indices) {
+            let (vertex, original_vertex) = (&vertices[*index as usize], &original_vertices[original_index as usize]);
+            assert_eq!((vertex.pos, vertex.uv), (original_vertex.pos, original_vertex.uv));
+        }
+        vertex_indices[0..6].iter().for_each(|index| {
+            assert_tangent_eq(vertices[*index as usize].tangent, glam::Vec4::new(1.0, 0.0, 0.0, 1.0));
+        });
+        vertex_indices[6..12].iter().for_each(|index| {
+            assert_tangent_eq(vertices[*index as usize].tangent, glam::Vec4::new(-1.0, 0.0, 0.0, -1.0));
+        });
+    }
+}
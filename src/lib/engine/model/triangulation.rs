@@ -0,0 +1,133 @@
+/// Triangulates a polygon, returns the corners of its triangles as indices into polygon, in the polygon's winding order.
+///
+/// Info: Convex polygons are fanned from their first corner. Concave ones are ear clipped in the plane that fits them best,
+/// which needs a simple polygon; the remaining corners of self-intersecting ones are fanned once no ear is left.
+pub fn triangulate(polygon: &[glam::Vec3]) -> Vec<[usize; 3]> {
+    let corner_count = polygon.len();
+    if corner_count < 4 {
+        return fan(corner_count);
+    }
+    // Newell's method, which also works for polygons that are not exactly planar.
+    let mut normal = glam::Vec3::ZERO;
+    for (idx, current) in polygon.iter().enumerate() {
+        let next = polygon[(idx + 1) % corner_count];
+        normal += glam::Vec3::new((current.y - next.y) * (current.z + next.z), (current.z - next.z) * (current.x + next.x),
+            (current.x - next.x) * (current.y + next.y));
+    }
+    if normal.length_squared() == 0.0 {
+        return fan(corner_count);
+    }
+    let (axis_x, axis_y) = normal.normalize().any_orthonormal_pair();
+    let mut points: Vec<glam::Vec2> = polygon.iter().map(|corner| glam::Vec2::new(corner.dot(axis_x), corner.dot(axis_y))).collect();
+    // Makes the winding counter clockwise, so convex corners have positive cross products.
+    let doubled_area: f32 = (0..corner_count).map(|idx| points[idx].perp_dot(points[(idx + 1) % corner_count])).sum();
+    if doubled_area < 0.0 {
+        points.iter_mut().for_each(|point| point.y = -point.y);
+    }
+    let is_convex_corner = |prev: usize, current: usize, next: usize| {
+        (points[current] - points[prev]).perp_dot(points[next] - points[current]) >= 0.0
+    };
+    if (0..corner_count).all(|idx| is_convex_corner((idx + corner_count - 1) % corner_count, idx, (idx + 1) % corner_count)) {
+        return fan(corner_count);
+    }
+
+    let mut remaining: Vec<usize> = (0..corner_count).collect();
+    let mut triangles = Vec::with_capacity(corner_count - 2);
+    while remaining.len() > 3 {
+        let remaining_count = remaining.len();
+        let ear = (0..remaining_count).find(|idx| {
+            let (prev, current, next) =
+                (remaining[(idx + remaining_count - 1) % remaining_count], remaining[*idx], remaining[(idx + 1) % remaining_count]);
+            is_convex_corner(prev, current, next) && !remaining.iter()
+                .filter(|corner| ![prev, current, next].contains(corner))
+                .any(|corner| is_in_triangle(points[*corner], points[prev], points[current], points[next]))
+        });
+        let Some(ear) = ear else {
+            break;
+        };
+        triangles.push([remaining[(ear + remaining_count - 1) % remaining_count], remaining[ear], remaining[(ear + 1) % remaining_count]]);
+        remaining.remove(ear);
+    }
+    triangles.extend(fan(remaining.len()).into_iter().map(|[a, b, c]| [remaining[a], remaining[b], remaining[c]]));
+    triangles
+}
+
+fn fan(corner_count: usize) -> Vec<[usize; 3]> {
+    (1..corner_count.saturating_sub(1)).map(|idx| [0, idx, idx + 1]).collect()
+}
+
+/// Points on the edges count as inside, for triangles with counter clockwise winding.
+fn is_in_triangle(point: glam::Vec2, a: glam::Vec2, b: glam::Vec2, c: glam::Vec2) -> bool {
+    (b - a).perp_dot(point - a) >= 0.0 && (c - b).perp_dot(point - b) >= 0.0 && (a - c).perp_dot(point - c) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy_polygon(points: &[(f32, f32)]) -> Vec<glam::Vec3> {
+        points.iter().map(|(x, y)| glam::Vec3::new(*x, *y, 0.0)).collect()
+    }
+
+    /// Checks that the triangles of a simple polygon cover it exactly, with its winding. normal is the polygon's normal for
+    /// counter clockwise winding.
+    fn assert_covers(polygon: &[glam::Vec3], normal: glam::Vec3) {
+        let triangles = triangulate(polygon);
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        let polygon_area: f32 = (0..polygon.len()).map(|idx| polygon[idx].cross(polygon[(idx + 1) % polygon.len()]).dot(normal))
+            .sum::<f32>() / 2.0;
+        let mut triangles_area = 0.0;
+        for [a, b, c] in triangles {
+            assert!(a != b && b != c && c != a);
+            let area = (polygon[b] - polygon[a]).cross(polygon[c] - polygon[a]).dot(normal) / 2.0;
+            assert!(area * polygon_area.signum() > 0.0, "triangle {:?} is flipped", [a, b, c]);
+            triangles_area += area;
+        }
+        assert!((triangles_area - polygon_area).abs() < 1e-5, "{} != {}", triangles_area, polygon_area);
+    }
+
+    #[test]
+    fn triangles_and_smaller_faces_are_kept() {
+        assert_eq!(triangulate(&xy_polygon(&[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)])), [[0, 1, 2]]);
+        assert!(triangulate(&xy_polygon(&[(0.0, 0.0), (1.0, 0.0)])).is_empty());
+    }
+
+    #[test]
+    fn convex_polygons_are_fanned() {
+        let square = xy_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(triangulate(&square), [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn concave_polygons_are_ear_clipped() {
+        let arrow = [(0.0, 0.0), (2.0, 1.0), (0.0, 2.0), (1.0, 1.0)];
+        assert_covers(&xy_polygon(&arrow), glam::Vec3::Z);
+        let l_shape = [(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)];
+        assert_covers(&xy_polygon(&l_shape), glam::Vec3::Z);
+        // Clockwise, with the reflex corner first.
+        let comb = [(1.0, 1.0), (1.0, 3.0), (2.0, 3.0), (2.0, 0.0), (-1.0, 0.0), (-1.0, 3.0), (0.0, 3.0), (0.0, 1.0)];
+        assert_covers(&xy_polygon(&comb), glam::Vec3::Z);
+    }
+
+    #[test]
+    fn concave_polygons_in_other_planes_are_ear_clipped() {
+        // In the plane through the origin that is spanned by axis_x and Z.
+        let axis_x = glam::Vec3::new(1.0, 0.5, 0.0);
+        let arrow: Vec<glam::Vec3> = [(0.0, 0.0), (2.0, 1.0), (0.0, 2.0), (1.0, 1.0)].iter()
+            .map(|(x, z)| axis_x * *x + glam::Vec3::Z * *z).collect();
+        assert_covers(&arrow, axis_x.cross(glam::Vec3::Z).normalize());
+    }
+
+    #[test]
+    fn degenerate_polygons_still_get_every_triangle() {
+        let line = xy_polygon(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]);
+        assert_eq!(triangulate(&line), [[0, 1, 2], [0, 2, 3]]);
+        let point = xy_polygon(&[(1.0, 1.0); 5]);
+        assert_eq!(triangulate(&point).len(), 3);
+        // Repeated corner of a concave polygon.
+        let arrow = xy_polygon(&[(0.0, 0.0), (2.0, 1.0), (2.0, 1.0), (0.0, 2.0), (1.0, 1.0)]);
+        let triangles = triangulate(&arrow);
+        assert_eq!(triangles.len(), 3);
+        assert!(triangles.iter().flatten().all(|corner| *corner < arrow.len()));
+    }
+}
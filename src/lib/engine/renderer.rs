@@ -1,16 +1,39 @@
-use std::{ffi::{CString}, io::Read};
-use winit::{dpi, platform::windows::WindowExtWindows, };
+use std::ffi::CString;
+use raw_window_handle::HasRawDisplayHandle;
 use std::ptr;
+use std::rc::Rc;
 use ash::{vk::{self}};
 use super::model;
 mod commandbuffer;
 mod swapchain;
+mod allocator;
+mod device;
 mod buffer;
 mod image;
 mod vk_creations;
 mod queries;
 mod surface;
-extern crate image as img;
+mod offscreen;
+mod screenshot;
+mod error;
+mod upload;
+mod deletion;
+mod texture;
+mod mipmap;
+mod cubemap;
+mod texture_file;
+mod block_decompression;
+mod sampler;
+mod skybox;
+
+pub use queries::{PhysicalDeviceOverride, PHYSICAL_DEVICE_OVERRIDE_ENV_VAR};
+pub use error::RendererError;
+pub use swapchain::PresentMode;
+pub use surface::OutputColorSpace;
+pub use allocator::{AllocationUsage, HeapReport, MemoryReport};
+pub use texture::{CubemapSource, FloatTextureFormat, TextureColorSpace};
+pub use mipmap::MipmapFilter;
+pub use sampler::SamplerDesc;
 
 #[repr(C)]
 pub struct UniformBufferObject {
@@ -19,30 +42,135 @@ pub struct UniformBufferObject {
     projection:  glam::Mat4,    
 }
 
+/// Startup options of the renderer.
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    pub frames_in_flight_count: u32,
+    /// Ignored by headless renderers.
+    pub swapchain_min_image_count: u32,
+    /// Picks a physical device instead of the highest scored one. PHYSICAL_DEVICE_OVERRIDE_ENV_VAR takes precedence over this.
+    pub physical_device_override: Option<PhysicalDeviceOverride>,
+    /// Falls back to FIFO if the surface does not support it. Can be changed later with set_present_mode().
+    /// Ignored by headless renderers.
+    pub present_mode: PresentMode,
+    /// HDR output is opt-in, it falls back to Srgb if the surface does not support it. Ignored by headless renderers.
+    pub output_color_space: OutputColorSpace,
+    /// Size of the staging ring buffer that buffer and image data is uploaded through. Bigger uploads get a staging buffer
+    /// of their own.
+    pub staging_buffer_size: u64,
+    /// Filter that texture mip levels are generated with, if their files do not store them.
+    pub mipmap_filter: MipmapFilter,
+    /// Format that HDR and EXR textures are loaded as.
+    pub float_texture_format: FloatTextureFormat,
+    /// Cube map that is drawn as the skybox behind the model. Image files without a color space are loaded as sRGB.
+    pub environment_map: Option<CubemapSource>,
+    pub model_import_options: model::ModelImportOptions,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            frames_in_flight_count: 4,
+            swapchain_min_image_count: 3,
+            physical_device_override: None,
+            present_mode: PresentMode::default(),
+            output_color_space: OutputColorSpace::default(),
+            staging_buffer_size: 32 * 1024 * 1024,
+            mipmap_filter: MipmapFilter::default(),
+            float_texture_format: FloatTextureFormat::default(),
+            environment_map: None,
+            model_import_options: model::ModelImportOptions::default(),
+        }
+    }
+}
+
+/// Where the render pass resolves its final color image into.
+enum RenderTarget {
+    /// Swapchain images of a window surface, which are presented after rendering.
+    Swapchain(Box<swapchain::Swapchain>),
+    /// Offscreen images which are read back by the host after rendering.
+    Offscreen(offscreen::Offscreen),
+}
+
+impl RenderTarget {
+    fn image_count(&self) -> u32 {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.image_count,
+            RenderTarget::Offscreen(offscreen) => offscreen.image_count,
+        }
+    }
+
+    fn image_views(&self) -> &[image::ImageView] {
+        match self {
+            RenderTarget::Swapchain(swapchain) => &swapchain.image_views,
+            RenderTarget::Offscreen(offscreen) => &offscreen.image_views,
+        }
+    }
+
+    fn extent(&self) -> vk::Extent2D {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.extent,
+            RenderTarget::Offscreen(offscreen) => vk::Extent2D { width: offscreen.width, height: offscreen.height },
+        }
+    }
+
+    fn format(&self) -> vk::Format {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.surface.format,
+            RenderTarget::Offscreen(offscreen) => offscreen.format,
+        }
+    }
+
+    /// Headless renderers render into sRGB images.
+    fn output_color_space(&self) -> OutputColorSpace {
+        match self {
+            RenderTarget::Swapchain(swapchain) => OutputColorSpace::from_surface_color_space(swapchain.surface.color_space),
+            RenderTarget::Offscreen(_) => OutputColorSpace::Srgb,
+        }
+    }
+
+    fn swapchain_mut(&mut self) -> Result<&mut swapchain::Swapchain, RendererError> {
+        match self {
+            RenderTarget::Swapchain(swapchain) => Ok(swapchain),
+            RenderTarget::Offscreen(_) => Err(RendererError::WrongRenderTarget(
+                "headless renderers have no swapchain, use render_offscreen() instead")),
+        }
+    }
+
+    fn offscreen(&self) -> Result<&offscreen::Offscreen, RendererError> {
+        match self {
+            RenderTarget::Offscreen(offscreen) => Ok(offscreen),
+            RenderTarget::Swapchain(_) => Err(RendererError::WrongRenderTarget(
+                "renderers with a window have no offscreen images, use render_frame() instead")),
+        }
+    }
+}
+
 pub struct Renderer {
-    entry: ash::Entry,
-    instance: ash::Instance,
-    physical_device: vk::PhysicalDevice,
-    device: ash::Device,
+    /// Every resource below keeps the device alive, which is destroyed after all of them are dropped.
+    device: Rc<device::Device>,
+    graphics_queue_family_idx: u32,
     graphics_queue: vk::Queue,
+    /// Swapchain images are presented with this.
+    present_queue: vk::Queue,
+    upload_manager: upload::UploadManager,
+    /// Resources that are replaced while frames are in flight are retired into this. Declared before target, since retired
+    /// swapchains must be dropped before the surface.
+    deletion_queue: deletion::DeletionQueue,
 
-    surface: surface::Surface,
-    swapchain: swapchain::Swapchain,
+    target: RenderTarget,
 
-    vertex_shader_module: vk::ShaderModule,
-    fragment_shader_module: vk::ShaderModule,
+    render_pass: device::RenderPass,
+    framebuffers: Vec<device::Framebuffer>,
+    pipeline_layout: device::PipelineLayout,
+    graphics_pipelines: Vec<device::Pipeline>,
 
-    render_pass: vk::RenderPass,
-    framebuffers: Vec<vk::Framebuffer>,
-    pipeline_layout: vk::PipelineLayout,
-    graphics_pipelines: Vec<vk::Pipeline>,
-
-    command_pool: vk::CommandPool,
+    _command_pool: device::CommandPool, // Command buffers are freed with it.
     cmd_buffers: Vec<vk::CommandBuffer>,
 
-    image_available_semaphores: Vec<vk::Semaphore>,
-    render_finished_semaphores: Vec<vk::Semaphore>,
-    queue_submit_finished_fences: Vec<vk::Fence>,
+    image_available_semaphores: Vec<device::Semaphore>,
+    render_finished_semaphores: Vec<device::Semaphore>,
+    queue_submit_finished_fences: Vec<device::Fence>,
 
     frames_in_flight_count: u32,
     frame_in_flight_idx: usize,
@@ -51,78 +179,179 @@ pub struct Renderer {
 
     vertex_buffer: buffer::Buffer,
     index_buffer: buffer::Buffer,
-    uniform_buffers: Vec<buffer::Buffer>,
+    _uniform_buffers: Vec<buffer::Buffer>, // Written through uniform_buffer_mapped_memory_ptrs.
 
     uniform_buffer_mapped_memory_ptrs: Vec<*mut UniformBufferObject>,
 
-    descriptor_pool: vk::DescriptorPool,
-    descriptor_sets: Vec<vk::DescriptorSet>,
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    _descriptor_pool: device::DescriptorPool, // Descriptor sets are freed with it.
+    /// One per frame in flight, bound to set 0.
+    frame_descriptor_sets: Vec<vk::DescriptorSet>,
+    /// The one of the model textures first, then one per model material, bound to set 1.
+    material_descriptor_sets: Vec<vk::DescriptorSet>,
+    _frame_descriptor_set_layout: device::DescriptorSetLayout,
+    _material_descriptor_set_layout: device::DescriptorSetLayout,
 
-    texture_image: image::Image,
-    texture_view: vk::ImageView,
-    texture_sampler: vk::Sampler,
+    // Only referenced by the descriptor sets. Keeps the model and material textures alive.
+    _texture_cache: texture::TextureCache,
+    /// Draws the environment map with graphics_pipelines[1], if there is one.
+    skybox: Option<skybox::Skybox>,
+    // Only referenced by the descriptor sets. Keeps the samplers of the model and material textures alive.
+    _sampler_cache: sampler::SamplerCache,
 
-    depth_images: Vec<image::Image>,
-    depth_image_views: Vec<vk::ImageView>,
+    depth_image_views: Vec<image::ImageView>,
 
     msaa_sample_count: vk::SampleCountFlags,
-    msaa_color_images: Vec<image::Image>,
-    msaa_color_image_views: Vec<vk::ImageView>,
+    msaa_color_image_views: Vec<image::ImageView>,
+
+    /// Set by capture_screenshot(), the next rendered frame is saved to this path.
+    pending_screenshot_path: Option<String>,
+    /// Set by on_window_resized(), the swapchain is recreated before the next frame is rendered.
+    is_swapchain_recreation_pending: bool,
 }
 
 impl Renderer {
-    pub fn new(window: &winit::window::Window, frames_in_flight_count: u32, swapchain_min_image_count: u32) -> Renderer {
+    /// Creates a renderer that presents to the window.
+    pub fn new(window: &winit::window::Window, config: &RendererConfig) -> Result<Renderer, RendererError> {
         let window_inner_size = window.inner_size();
-        let model = model::Model::new("models/viking_room.obj");
+        Renderer::create(Some(window), window_inner_size.width, window_inner_size.height, config, config.swapchain_min_image_count)
+    }
+
+    /// Creates a renderer without a window that renders into an offscreen color image of width x height. Use
+    /// render_offscreen() or render_offscreen_to_png() to render a frame.
+    pub fn new_headless(width: u32, height: u32, config: &RendererConfig) -> Result<Renderer, RendererError> {
+        // Every frame is waited for before its pixels are read back, so a single offscreen image is enough.
+        Renderer::create(None, width, height, config, 1)
+    }
+
+    /// Renders to a swapchain if there is a window, otherwise to offscreen images. target_image_count is the swapchain min
+    /// image count or the offscreen image count.
+    fn create(window: Option<&winit::window::Window>, width: u32, height: u32, config: &RendererConfig, target_image_count: u32)
+    -> Result<Renderer, RendererError> {
+        let frames_in_flight_count = config.frames_in_flight_count;
+        let model = model::Model::new("models/viking_room.obj", vec![model::ModelTexture {
+            path: "./images/viking_room.png".to_owned(),
+            color_space: TextureColorSpace::Srgb,
+            sampler: SamplerDesc::default(),
+        }], Vec::new(), &config.model_import_options)?;
 
         let entry = unsafe {
-            ash::Entry::load().unwrap()
-        };
+            ash::Entry::load()
+        }?;
+        // HDR color spaces are only exposed by surfaces if VK_EXT_swapchain_colorspace is enabled.
+        let mut output_color_space = config.output_color_space;
+        let with_swapchain_colorspace = window.is_some() && output_color_space != OutputColorSpace::Srgb &&
+            queries::has_instance_extension(&entry, vk::ExtSwapchainColorspaceFn::name())?;
+        if window.is_some() && output_color_space != OutputColorSpace::Srgb && !with_swapchain_colorspace {
+            println!("Output color space: {:?} needs VK_EXT_swapchain_colorspace, falling back to Srgb.", output_color_space);
+            output_color_space = OutputColorSpace::Srgb;
+        }
         let app_info = vk_creations::create_app_info();
-        let instance = vk_creations::create_instance(&entry, &app_info);
-        let physical_device = queries::get_physical_device(&instance);
-        let surface = surface::Surface::new(&entry, &instance, window.hinstance() as vk::HINSTANCE, window.hwnd() as vk::HWND, physical_device);
-        let graphics_queue_family_idx = queries::get_graphics_queue_family_idx(&instance, physical_device, &surface);
-        let queue_cis = &[
-            vk_creations::QueueCreateInfo::new(graphics_queue_family_idx, 1, &[1.0])
-            ];
-        let device = vk_creations::create_device(&instance, physical_device, queue_cis);
-        
-        // Pass one less image count to swapchain, to make sure that CPU goes one frame ahead of swapchain as recommended.
-        if !surface.get_min_image_support(swapchain_min_image_count, physical_device) {
-            panic!("The requested min image count: '{}' is not supported on swapchain's surface: {}! ", swapchain_min_image_count, surface);
+        let instance = vk_creations::create_instance(&entry, &app_info, window.map(|window| window.raw_display_handle()),
+            with_swapchain_colorspace)?;
+        let instance = device::Instance::new(entry, instance);
+        let surface_loader = ash::extensions::khr::Surface::new(instance.entry(), &instance);
+        let surface_khr = window.map(|window| surface::create_surface_khr(instance.entry(), &instance, window)).transpose()?;
+        let physical_device = queries::get_physical_device(&instance, surface_khr.map(|surface_khr| (&surface_loader, surface_khr)),
+            config.physical_device_override.as_ref());
+        // Surface is owned by surface::Surface once a physical device is picked, until then it is destroyed by hand.
+        let physical_device = match physical_device {
+            Ok(physical_device) => physical_device,
+            Err(error) => {
+                if let Some(surface_khr) = surface_khr {
+                    unsafe{surface_loader.destroy_surface(surface_khr, None)};
+                }
+                return Err(error);
+            }
+        };
+        let surface = surface_khr.map(|surface_khr| {
+            surface::Surface::new(&instance, surface_loader, surface_khr, physical_device, output_color_space)
+        }).transpose()?;
+        let queue_family_indices = queries::get_queue_family_indices(&instance, physical_device, surface.as_ref())?;
+        let graphics_queue_family_idx = queue_family_indices.graphics;
+        let queue_cis: Vec<vk_creations::QueueCreateInfo> = queue_family_indices.unique().into_iter()
+            .map(|queue_family_idx| vk_creations::QueueCreateInfo::new(queue_family_idx, 1, &[1.0]))
+            .collect();
+        let with_memory_budget = queries::has_memory_budget_support(&instance, physical_device)?;
+        let max_sampler_anisotropy = queries::get_max_sampler_anisotropy(&instance, physical_device);
+        if max_sampler_anisotropy.is_none() {
+            println!("Sampler anisotropy is not supported, textures are sampled without anisotropic filtering.");
         }
-        let swapchain = swapchain::Swapchain::new(&instance, device.clone(), surface.clone(), swapchain_min_image_count, window_inner_size.width,
-            window_inner_size.height);
-        
-        // Set real_frames_in_flight_count from swapchain. Swapchain can not always create swapchain_min_image_count amount images.
-        println!("\nThere are {} frames in flight and {} swapchain images.", frames_in_flight_count, swapchain.image_count);
+        let has_image_cube_array = queries::has_image_cube_array_support(&instance, physical_device);
+        if !has_image_cube_array {
+            println!("Image cube arrays are not supported, cube map array textures can not be loaded.");
+        }
+        let device = vk_creations::create_device(&instance, physical_device, &queue_cis, surface.is_some(), with_memory_budget,
+            max_sampler_anisotropy.is_some(), has_image_cube_array)?;
+        let allocator = allocator::Allocator::new(&instance, physical_device, with_memory_budget);
+        let device = device::Device::new(instance.clone(), physical_device, device, max_sampler_anisotropy, has_image_cube_array,
+            allocator);
+
+        let target = match surface {
+            Some(surface) => {
+                // Pass one less image count to swapchain, to make sure that CPU goes one frame ahead of swapchain as recommended.
+                surface.check_min_image_support(target_image_count, physical_device)?;
+                let swapchain = swapchain::Swapchain::new(&device, surface, target_image_count, width, height,
+                    config.present_mode, graphics_queue_family_idx, queue_family_indices.present.unwrap_or(graphics_queue_family_idx))?;
+                
+                // Set real_frames_in_flight_count from swapchain. Swapchain can not always create swapchain_min_image_count amount images.
+                println!("\nThere are {} frames in flight and {} swapchain images.", frames_in_flight_count, swapchain.image_count);
+                RenderTarget::Swapchain(Box::new(swapchain))
+            },
+            None => {
+                // R8G8B8A8 so that read back pixels can be saved as RGBA without any conversion.
+                let offscreen = offscreen::Offscreen::new(&device, vk::Format::R8G8B8A8_SRGB, width, height,
+                    target_image_count, graphics_queue_family_idx)?;
+                println!("\nThere are {} frames in flight and {} offscreen images.", frames_in_flight_count, offscreen.image_count);
+                RenderTarget::Offscreen(offscreen)
+            }
+        };
+        let color_format = target.format();
+        // Surface might dictate a different extent than the window size.
+        let vk::Extent2D { width, height } = target.extent();
 
         let graphics_queue: vk::Queue = unsafe {
             device.get_device_queue(graphics_queue_family_idx, 0)
         };
+        // Same queue as graphics_queue if they are the same family, since only one queue is created per family.
+        let present_queue: vk::Queue = unsafe {
+            device.get_device_queue(queue_family_indices.present.unwrap_or(graphics_queue_family_idx), 0)
+        };
+        let transfer_queue: vk::Queue = unsafe {
+            device.get_device_queue(queue_family_indices.transfer, 0)
+        };
 
-        let vertex_shader_module = vk_creations::create_shader_module(&device, "shaders/spirv/vert.spv");
-        let fragment_shader_module = vk_creations::create_shader_module(&device, "shaders/spirv/frag.spv");
+        let vertex_shader_module = vk_creations::create_shader_module(&device, "shaders/spirv/vert.spv")?;
+        let fragment_shader_module = vk_creations::create_shader_module(&device, "shaders/spirv/frag.spv")?;
 
         let main_fn_name = CString::new("main").unwrap();
+        // Fragment shaders encode their output for the color space of the target, see OutputColorSpace.
+        let output_color_space_constant = target.output_color_space().shader_constant(target.format());
+        let output_color_space_map_entry = vk::SpecializationMapEntry {
+            constant_id: 0, // OUTPUT_COLOR_SPACE
+            offset: 0,
+            size: std::mem::size_of::<u32>(),
+        };
+        let fragment_specialization_info = vk::SpecializationInfo {
+            map_entry_count: 1,
+            p_map_entries: &output_color_space_map_entry,
+            data_size: std::mem::size_of::<u32>(),
+            p_data: &output_color_space_constant as *const u32 as *const std::ffi::c_void,
+        };
         let pipeline_vertex_shader_stage_ci = vk_creations::create_pipeline_shader_stage_create_info(&main_fn_name, 
-            vk::ShaderStageFlags::VERTEX, vertex_shader_module);
+            vk::ShaderStageFlags::VERTEX, vertex_shader_module.raw(), None);
         let pipeline_fragment_shader_stage_ci = vk_creations::create_pipeline_shader_stage_create_info(&main_fn_name, 
-            vk::ShaderStageFlags::FRAGMENT, fragment_shader_module);
+            vk::ShaderStageFlags::FRAGMENT, fragment_shader_module.raw(), Some(&fragment_specialization_info));
         // These will be passed into PipelineCreateInfo
         let pipeline_shader_stages_ci = [pipeline_vertex_shader_stage_ci, pipeline_fragment_shader_stage_ci];
         
-        let (msaa_color_images, msaa_color_image_views) = vk_creations::create_msaa_color_images_and_views(&device, &instance, physical_device,
-            window_inner_size.width, window_inner_size.height, surface.format, swapchain.image_count as usize);
+        let msaa_sample_count = queries::get_max_usable_sample_count(&instance, physical_device, vk::SampleCountFlags::TYPE_8);
+        let msaa_color_image_views = vk_creations::create_msaa_color_image_views(&device, width, height, color_format, msaa_sample_count, target.image_count() as usize)?;
 
         // Create Attachment References and Attachment Descriptions:
-        let msaa_sample_count = vk::SampleCountFlags::TYPE_8; // TODO: Query it.
         // Pipeline will use this attachment as color output:
         let msaa_color_attachment_desc = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: surface.format,
+            format: color_format,
             samples: msaa_sample_count,
             load_op: vk::AttachmentLoadOp::CLEAR,
             store_op: vk::AttachmentStoreOp::DONT_CARE,
@@ -153,17 +382,21 @@ impl Renderer {
             layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
         };
 
-        // This will be presented on screen.
+        // This will be presented on screen, or copied to host if rendering offscreen.
+        let resolve_color_final_layout = match target {
+            RenderTarget::Swapchain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            RenderTarget::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        };
         let resolve_color_attachment_desc = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: surface.format,
+            format: color_format,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::CLEAR,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: resolve_color_final_layout,
         };
         let resolve_color_attachment_ref = vk::AttachmentReference {
             // Specifies which attachment to reference by its index in the attachment descriptions array. 
@@ -259,9 +492,9 @@ impl Renderer {
             p_dependencies: subpass_deps.as_ptr(),
         };
 
-        let render_pass = unsafe {
-            device.create_render_pass(&render_pass_ci, None).unwrap()
-        };
+        let render_pass = device::Owned::new(&device, unsafe {
+            device.create_render_pass(&render_pass_ci, None)
+        }?);
 
         // Vertex Input Binding and Descriptions:
         let vertex_input_binding_desc = vk::VertexInputBindingDescription {
@@ -282,247 +515,108 @@ impl Renderer {
             format: vk::Format::R32G32_SFLOAT,
             offset: std::mem::size_of::<glam::Vec3>() as u32
         };
-            
-        let vertex_input_attribute_descriptions = [vertex_input_pos_attribute_desc, vertex_input_uv_attribute_desc];
-
-        let physical_device_memory_properties = unsafe {
-            instance.get_physical_device_memory_properties(physical_device)
+        let vertex_input_normal_attribute_desc = vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: (std::mem::size_of::<glam::Vec3>() + std::mem::size_of::<glam::Vec2>()) as u32
+        };
+        let vertex_input_tangent_attribute_desc = vk::VertexInputAttributeDescription {
+            location: 3,
+            binding: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: (2 * std::mem::size_of::<glam::Vec3>() + std::mem::size_of::<glam::Vec2>()) as u32
         };
+            
+        let vertex_input_attribute_descriptions = [vertex_input_pos_attribute_desc, vertex_input_uv_attribute_desc,
+            vertex_input_normal_attribute_desc, vertex_input_tangent_attribute_desc];
 
         // Vertex Buffer
         let vertex_buffer_size = model.get_vertex_buffer_size();
-        let vertex_staging_buffer = buffer::Buffer::new(&device, vertex_buffer_size, vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, 
-            &graphics_queue_family_idx, &physical_device_memory_properties);
-            vertex_staging_buffer.copy_host_data_into_buffer(&device, model.vertices.as_ptr(), model.vertices.len(), vertex_buffer_size);
-        let vertex_buffer = buffer::Buffer::new(&device, vertex_buffer_size, 
+        let vertex_buffer = buffer::Buffer::new(&device, AllocationUsage::Vertex, vertex_buffer_size,
             vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL, 
-            &graphics_queue_family_idx, &physical_device_memory_properties);
-
-        let (single_time_cmd_buffer, single_time_cmd_pool) = commandbuffer::single_time_cmdbuffer_start(&device, graphics_queue_family_idx);
-        let vertices_copy_region = vk::BufferCopy {
-            src_offset: 0,
-            dst_offset: 0,
-            size: vertex_buffer_size,
-        };
-        unsafe {
-            device.cmd_copy_buffer(single_time_cmd_buffer, vertex_staging_buffer.raw, vertex_buffer.raw, &[vertices_copy_region]);
-        }     
+            &graphics_queue_family_idx)?;
 
         // Index buffer:
         let index_buffer_size = model.get_index_buffer_size();
-        let index_staging_buffer = buffer::Buffer::new(&device, index_buffer_size, vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            &graphics_queue_family_idx, &physical_device_memory_properties);
-        index_staging_buffer.copy_host_data_into_buffer(&device, model.vertex_indices.as_ptr(), model.vertex_indices.len(), index_buffer_size);
-        let index_buffer = buffer::Buffer::new(&device, index_buffer_size,
+        let index_buffer = buffer::Buffer::new(&device, AllocationUsage::Index, index_buffer_size,
             vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            &graphics_queue_family_idx, &physical_device_memory_properties);
-        let indices_copy_region = vk::BufferCopy {
-            src_offset: 0,
-            dst_offset: 0,
-            size: index_buffer_size,
-        };
-        unsafe {
-            device.cmd_copy_buffer(single_time_cmd_buffer, index_staging_buffer.raw, index_buffer.raw, &[indices_copy_region]);
-        }
+            &graphics_queue_family_idx)?;
         
         // Create Uniform Buffers:
         let uniform_buffer_size = std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize;
         let mut uniform_buffers: Vec<buffer::Buffer>  = Vec::with_capacity(frames_in_flight_count as usize);
         let mut uniform_buffer_mapped_memory_ptrs: Vec<*mut UniformBufferObject> = Vec::with_capacity(frames_in_flight_count as usize);
         for _ in 0..frames_in_flight_count {
-            let uniform_buffer = buffer::Buffer::new(&device, uniform_buffer_size, vk::BufferUsageFlags::UNIFORM_BUFFER,
+            let uniform_buffer = buffer::Buffer::new(&device, AllocationUsage::Uniform, uniform_buffer_size,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
                     vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                    &graphics_queue_family_idx, &physical_device_memory_properties
-                );
+                    &graphics_queue_family_idx
+                )?;
 
             // Get persistent mapped memory pointers, since I am going to use it every frame:
             uniform_buffer_mapped_memory_ptrs.push(
-                unsafe {
-                    device.map_memory(uniform_buffer.device_memory, 0, uniform_buffer_size, vk::MemoryMapFlags::empty()).unwrap()
-                        as *mut UniformBufferObject
-                }
+                uniform_buffer.mapped_ptr().expect("Uniform buffers must be HOST_VISIBLE!") as *mut UniformBufferObject
             );
             uniform_buffers.push(uniform_buffer);
         }
 
         // Create depth Images and views:   
-        let (depth_images, depth_image_views) = vk_creations::create_depth_images_and_views(&device, &instance, physical_device,
-            window_inner_size.width, window_inner_size.height, depth_format, msaa_sample_count, swapchain.image_count as usize);
+        let depth_image_views = vk_creations::create_depth_image_views(&device, width, height, depth_format, msaa_sample_count, target.image_count() as usize)?;
                 
         // Create Framebuffers:
         // Info: Render passes operate in conjunction with framebuffers. Framebuffers represent a collection of
         // specific memory attachments that a render pass instance uses.
-        let mut framebuffers : Vec<vk::Framebuffer> = Vec::with_capacity(swapchain.image_count as usize);
-        for (idx, image_view) in swapchain.image_views.iter().enumerate() {
-            framebuffers.push(vk_creations::create_framebuffer(&device, &[msaa_color_image_views[idx], depth_image_views[idx], *image_view],
-                render_pass, window_inner_size.width, window_inner_size.height));
+        let mut framebuffers : Vec<device::Framebuffer> = Vec::with_capacity(target.image_count() as usize);
+        for (idx, image_view) in target.image_views().iter().enumerate() {
+            framebuffers.push(vk_creations::create_framebuffer(&device, &[msaa_color_image_views[idx].raw, depth_image_views[idx].raw, image_view.raw],
+                render_pass.raw(), width, height)?);
         }
 
-        // Load Textures:
-        let image_reader = img::io::Reader::open("./images/viking_room.png").unwrap();
-        let image_buffer = image_reader.decode().unwrap().into_rgba8();
-        let image_bytes: Vec<u8> = image_buffer.bytes().map(|byte| {byte.unwrap()}).collect();
+        // Every upload below is recorded into a single batch, which is submitted once the texture mipmaps are recorded.
+        // Note: The texture cache is declared before the upload manager, so that the manager is dropped first and waits for
+        // the uploads if anything fails later on. The same goes for the buffers above.
+        let mut texture_cache = texture::TextureCache::new(config.mipmap_filter, config.float_texture_format);
+        let mut upload_manager = upload::UploadManager::new(&device, transfer_queue, queue_family_indices.transfer,
+            graphics_queue, graphics_queue_family_idx, config.staging_buffer_size)?;
+        upload_manager.upload_buffer(vertex_buffer.raw, 0, &model.vertices)?;
+        upload_manager.upload_buffer(index_buffer.raw, 0, &model.vertex_indices)?;
 
-        
-        let texture_size = image_bytes.len() as vk::DeviceSize;
-        let texture_staging_buffer = buffer::Buffer::new(&device, texture_size as u64, vk::BufferUsageFlags::TRANSFER_SRC, 
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, 
-            &graphics_queue_family_idx, &physical_device_memory_properties);
-        texture_staging_buffer.copy_host_data_into_buffer(&device, image_bytes.as_ptr(), texture_size as usize, texture_size);
-                
-        // Create Image:
-        // TODO: Should mip_levels be the max(width,height) or min(width, height)? How can you divide 64 for 7 times if other
-        // axis is 128?
-        let texture_mipmap_levels = ((u32::max(image_buffer.width(), image_buffer.height()) as f32).log2().floor() + 1.0) as u32;
-        let texture_image = image::Image::new( &device, &instance, physical_device, image_buffer.width(), image_buffer.height(),
-            texture_mipmap_levels, vk::SampleCountFlags::TYPE_1, vk::Format::R8G8B8A8_SRGB, vk::ImageTiling::OPTIMAL, 
-            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL);       
-
-        // Copying from a buffer to image requires to change IMAGELAYOUT. So, first I need to set the layout by using memory barriers:
-        let image_subresource_range = vk::ImageSubresourceRange {
-            aspect_mask: vk::ImageAspectFlags::COLOR,
-            base_mip_level: 0,
-            level_count: 1,
-            base_array_layer: 0,
-            layer_count: 1,
-        };
-        commandbuffer::transition_image_layout(&device, single_time_cmd_buffer, texture_image.raw, image_subresource_range, 
-            vk::ImageLayout::UNDEFINED,          vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::AccessFlags::NONE,               vk::AccessFlags::TRANSFER_WRITE,
-            vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER);
-        // At that point, the image layout will be "TRANSFER_DST_OPTIMAL", so we can just do a copy operation:
-        let extent = vk::Extent3D {
-            width: image_buffer.width(),
-            height: image_buffer.height(),
-            depth: 1
-        };
-        
-        commandbuffer::copy_device_buffer_to_device_image(&device, single_time_cmd_buffer, texture_staging_buffer.raw, texture_image.raw, &extent);
-        // After doing the copy, we need to prepare the first mipmap level(0) as a read source for blit:
-        commandbuffer::transition_image_layout(&device, single_time_cmd_buffer, texture_image.raw, image_subresource_range, 
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-            vk::AccessFlags::TRANSFER_WRITE,       vk::AccessFlags::TRANSFER_READ,
-            vk::PipelineStageFlags::TRANSFER,      vk::PipelineStageFlags::TRANSFER);
-
-        commandbuffer::single_time_cmdbuffer_end(&device, graphics_queue, single_time_cmd_buffer, single_time_cmd_pool);
-        // Free staging buffers and device memories.
-        unsafe {
-            device.destroy_buffer(texture_staging_buffer.raw, None);
-            device.destroy_buffer(vertex_staging_buffer.raw, None);
-            device.destroy_buffer(index_staging_buffer.raw, None);
-            device.free_memory(texture_staging_buffer.device_memory, None);
-            device.free_memory(vertex_staging_buffer.device_memory, None);
-            device.free_memory(index_staging_buffer.device_memory, None);
-        }
-
-        // Generate texture mipmaps:
-        let (single_time_cmd_buffer, single_time_cmd_pool) = commandbuffer::single_time_cmdbuffer_start(&device,
-             graphics_queue_family_idx);
-        // mipmap_level 0 is reserved for the original size image.
-        for mipmap_level in 1..texture_mipmap_levels {
-            let image_width = image_buffer.width();
-            let image_height = image_buffer.height();
-            let image_blit = vk::ImageBlit {
-                src_subresource: vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: mipmap_level - 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                },
-                src_offsets: [
-                    vk::Offset3D {x: 0, y: 0, z: 0}, 
-                    vk::Offset3D {
-                        x: (image_width >> (mipmap_level - 1)) as i32,
-                        y: (image_height >> (mipmap_level - 1)) as i32,
-                        z: 1
-                    }],
-                dst_subresource: vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: mipmap_level,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                },
-                dst_offsets: [
-                    vk::Offset3D {x: 0, y: 0, z: 0}, 
-                    vk::Offset3D {
-                        x: (image_width >> mipmap_level) as i32,
-                        y: (image_height >> mipmap_level) as i32,
-                        z: 1
-                    }],
-            };
-            // This mipmap level will have undefined layout and no access flag prior so make it ready for dst write
-            let image_subresource_range = vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
-                base_mip_level: mipmap_level,   // Starting from this mipmap_level...
-                level_count: 1, // ... just get this many level into the image view.
-                base_array_layer: 0,
-                layer_count: 1,
-            };
-            commandbuffer::transition_image_layout(&device, single_time_cmd_buffer, texture_image.raw, image_subresource_range, 
-                vk::ImageLayout::UNDEFINED,         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                vk::AccessFlags::empty(),           vk::AccessFlags::TRANSFER_WRITE,
-                vk::PipelineStageFlags::TRANSFER,   vk::PipelineStageFlags::TRANSFER);
-            // TODO: Need to check physicaldeviceformatproperties for linear filtering support.
-            unsafe {
-                device.cmd_blit_image(single_time_cmd_buffer, texture_image.raw, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, texture_image.raw,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[image_blit], vk::Filter::LINEAR);
-            }
-            // The newly blitted mipmap level becomes the src read for the next loop:
-            commandbuffer::transition_image_layout(&device, single_time_cmd_buffer, texture_image.raw, image_subresource_range, 
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                vk::AccessFlags::TRANSFER_WRITE,       vk::AccessFlags::TRANSFER_READ,
-                vk::PipelineStageFlags::TRANSFER,      vk::PipelineStageFlags::TRANSFER);
+        // Load Textures:
+        // The textures of the model come first, then the ones of each material.
+        let mut material_textures: Vec<&[model::ModelTexture]> = Vec::with_capacity(1 + model.materials.len());
+        material_textures.push(&model.textures);
+        material_textures.extend(model.materials.iter().map(|material| material.textures.as_slice()));
+        let mut base_color_textures: Vec<Rc<texture::Texture>> = Vec::with_capacity(material_textures.len());
+        for (material_idx, textures) in material_textures.iter().enumerate() {
+            // TODO: Bind every texture of a material, only the base color texture is sampled for now.
+            let base_color_texture = textures.first().ok_or_else(|| RendererError::MissingModelTexture {
+                material_name: material_idx.checked_sub(1).map(|idx| model.materials[idx].name.clone())
+            })?;
+            base_color_textures.push(texture_cache.get_or_load(&device, &mut upload_manager, &base_color_texture.path,
+                base_color_texture.color_space)?);
         }
-        // Make all mipmap levels ready to be read from fragment shader:
-        let image_subresource_range = vk::ImageSubresourceRange {
-            aspect_mask: vk::ImageAspectFlags::COLOR,
-            base_mip_level: 0,
-            level_count: texture_mipmap_levels,
-            base_array_layer: 0,
-            layer_count: 1,
-        };
-        commandbuffer::transition_image_layout(&device, single_time_cmd_buffer, texture_image.raw, image_subresource_range, 
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,  vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            vk::AccessFlags::TRANSFER_READ,         vk::AccessFlags::SHADER_READ,
-            vk::PipelineStageFlags::TRANSFER,       vk::PipelineStageFlags::FRAGMENT_SHADER);
-        commandbuffer::single_time_cmdbuffer_end(&device, graphics_queue, single_time_cmd_buffer, single_time_cmd_pool);
-
-        // Create texture image view:
-        let texture_image_view = texture_image.create_image_view(&device, vk::Format::R8G8B8A8_SRGB, texture_mipmap_levels,
-            vk::ImageAspectFlags::COLOR);
-
-        // Create Texture Sampler:
-        let physical_device_properties = unsafe {
-            instance.get_physical_device_properties(physical_device)
-        };
-        let texture_sampler_ci = vk::SamplerCreateInfo {
-            s_type: vk::StructureType::SAMPLER_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::SamplerCreateFlags::empty(),
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-            address_mode_u: vk::SamplerAddressMode::REPEAT,
-            address_mode_v: vk::SamplerAddressMode::REPEAT,
-            address_mode_w: vk::SamplerAddressMode::REPEAT,
-            mip_lod_bias: 0.0f32,
-            anisotropy_enable: vk::TRUE,
-            max_anisotropy: physical_device_properties.limits.max_sampler_anisotropy, // 16 for me.
-            compare_enable: vk::FALSE,
-            compare_op: vk::CompareOp::ALWAYS,
-            min_lod: 0.0f32,
-            max_lod: texture_mipmap_levels as f32,
-            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
-            unnormalized_coordinates: vk::FALSE,
+        let environment_map = match &config.environment_map {
+            Some(source) => Some(texture_cache.get_or_load_cubemap(&device, &mut upload_manager, source, TextureColorSpace::Srgb)?),
+            None => None,
         };
-        let texture_sampler = unsafe {
-            device.create_sampler(&texture_sampler_ci, None).unwrap()
+        // Not waited for, the first frame is submitted to the same queue after the batch.
+        upload_manager.flush()?;
+
+        // Create Texture Samplers:
+        let mut sampler_cache = sampler::SamplerCache::new();
+        let mut base_color_samplers: Vec<Rc<device::Sampler>> = Vec::with_capacity(material_textures.len());
+        for textures in &material_textures {
+            base_color_samplers.push(sampler_cache.get_or_create(&device, &textures[0].sampler)?);
+        }
+        let skybox = match environment_map {
+            Some(environment_map) => Some(skybox::Skybox::new(&device, environment_map,
+                sampler_cache.get_or_create(&device, &SamplerDesc::default())?)?),
+            None => None,
         };
 
-        // Create Descriptor Layout:
+        // Create Descriptor Layouts:
+        // Set 0 is written once per frame in flight, set 1 once per material.
         let ub_descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding {
             binding: 0,
             descriptor_type: vk::DescriptorType::UNIFORM_BUFFER, // Uniform buffer is read-only/load only buffer.
@@ -531,83 +625,58 @@ impl Renderer {
             p_immutable_samplers: ptr::null(),
         };  
         let sampler_descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding {
-            binding: 1,
+            binding: 0,
             // COMBINED_IMAGE_SAMPLER combines image and sampler in a single descriptor.
             descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
             descriptor_count: 1,
             stage_flags: vk::ShaderStageFlags::FRAGMENT,
             p_immutable_samplers: ptr::null(),
         };
-        let bindings = [ub_descriptor_set_layout_binding, sampler_descriptor_set_layout_binding];
-        let descriptor_layout_ci = vk::DescriptorSetLayoutCreateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
-            binding_count: bindings.len() as u32,
-            p_bindings: bindings.as_ptr(),
-        };
-        let descriptor_set_layout = unsafe {
-            device.create_descriptor_set_layout(&descriptor_layout_ci, None).unwrap()
-        };
+        let frame_descriptor_set_layout = vk_creations::create_descriptor_set_layout(&device, &[ub_descriptor_set_layout_binding])?;
+        let material_descriptor_set_layout = vk_creations::create_descriptor_set_layout(&device,
+            &[sampler_descriptor_set_layout_binding])?;
 
         // Create Descriptor Pool:
+        let material_count = material_textures.len() as u32;
         let ub_descriptor_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::UNIFORM_BUFFER,
             descriptor_count: frames_in_flight_count,
         };
         let sampler_descriptor_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: frames_in_flight_count,
+            descriptor_count: material_count,
         };
         let descriptor_pool_sizes = [ub_descriptor_pool_size, sampler_descriptor_pool_size];
         let descriptor_pool_ci = vk::DescriptorPoolCreateInfo {
             s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::DescriptorPoolCreateFlags::empty(),
-            max_sets: frames_in_flight_count, // is the maximum number of descriptor sets that can be allocated from the pool.
+            max_sets: frames_in_flight_count + material_count, // is the maximum number of descriptor sets that can be allocated from the pool.
             pool_size_count: descriptor_pool_sizes.len() as u32,
             p_pool_sizes: descriptor_pool_sizes.as_ptr(), // This is the total bytes that will be pre-allocated from this pool.
         };
-        let descriptor_pool = unsafe {
-            device.create_descriptor_pool(&descriptor_pool_ci, None).unwrap()
-        };
+        let descriptor_pool = device::Owned::new(&device, unsafe {
+            device.create_descriptor_pool(&descriptor_pool_ci, None)
+        }?);
 
         // Allocate descriptor sets from the pool:
         // vk::DescriptorSetAllocateInfo needs matching number of descriptorsetlayout elements for descriptionsets.
-        let descriptor_sets_alloc_count = frames_in_flight_count;
-        let mut descriptor_set_layout_vec: Vec<vk::DescriptorSetLayout> = Vec::with_capacity(descriptor_sets_alloc_count as usize);
-        for _ in 0..descriptor_sets_alloc_count {
-            descriptor_set_layout_vec.push(descriptor_set_layout);
-        }
-        let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
-            p_next: ptr::null(),
-            descriptor_pool: descriptor_pool,
-            descriptor_set_count: descriptor_sets_alloc_count, // Allocates this many descriptor sets by...
-            p_set_layouts: descriptor_set_layout_vec.as_ptr(), // ...using these layouts. So you basically can combine different amount of...
-            // ...descriptor sets and descriptors arbitrarily! It's a little bit confusing matter at first.
-        };
-        let descriptor_sets = unsafe {
-            device.allocate_descriptor_sets(&descriptor_set_alloc_info).unwrap()
-        };
+        let frame_descriptor_sets = vk_creations::allocate_descriptor_sets(&device, descriptor_pool.raw(),
+            frame_descriptor_set_layout.raw(), frames_in_flight_count)?;
+        let material_descriptor_sets = vk_creations::allocate_descriptor_sets(&device, descriptor_pool.raw(),
+            material_descriptor_set_layout.raw(), material_count)?;
 
         // Update descriptor buffers:
-        for frame_idx in 0..descriptor_sets_alloc_count as usize {
+        for (frame_idx, frame_descriptor_set) in frame_descriptor_sets.iter().enumerate() {
             let descriptor_ub_buffer_info = vk::DescriptorBufferInfo {
                     buffer: uniform_buffers[frame_idx].raw,
                     offset: 0,
                     range: std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize
             };
-            let descriptor_image_info = vk::DescriptorImageInfo {
-                    sampler: texture_sampler,
-                    image_view: texture_image_view,
-                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            };
-
             let write_descriptor_set_ub = vk::WriteDescriptorSet {
                 s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
                 p_next: ptr::null(),
-                dst_set: descriptor_sets[frame_idx],
+                dst_set: *frame_descriptor_set,
                 dst_binding: 0,
                 dst_array_element: 0,
                 descriptor_count: 1,
@@ -617,11 +686,21 @@ impl Renderer {
                 p_buffer_info: &descriptor_ub_buffer_info,
                 p_texel_buffer_view: ptr::null(),
             };
+            unsafe {
+                device.update_descriptor_sets(&[write_descriptor_set_ub], &[]);
+            }
+        }
+        for (material_idx, material_descriptor_set) in material_descriptor_sets.iter().enumerate() {
+            let descriptor_image_info = vk::DescriptorImageInfo {
+                    sampler: base_color_samplers[material_idx].raw(),
+                    image_view: base_color_textures[material_idx].view.raw,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            };
             let write_descriptor_set_image_sampler = vk::WriteDescriptorSet {
                 s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
                 p_next: ptr::null(),
-                dst_set: descriptor_sets[frame_idx],
-                dst_binding: 1,
+                dst_set: *material_descriptor_set,
+                dst_binding: 0,
                 dst_array_element: 0,
                 descriptor_count: 1,
                 descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
@@ -631,7 +710,7 @@ impl Renderer {
                 p_texel_buffer_view: ptr::null(),
             };
             unsafe {
-                device.update_descriptor_sets(&[write_descriptor_set_ub, write_descriptor_set_image_sampler], &[]);
+                device.update_descriptor_sets(&[write_descriptor_set_image_sampler], &[]);
             }
         }
 
@@ -736,18 +815,19 @@ impl Renderer {
             dynamic_state_count: dynamic_states.len() as u32,
             p_dynamic_states: dynamic_states.as_ptr()
         };  
+        let set_layouts = [frame_descriptor_set_layout.raw(), material_descriptor_set_layout.raw()];
         let pipeline_layout_ci = vk::PipelineLayoutCreateInfo {
             s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineLayoutCreateFlags::empty(),
-            set_layout_count: 1,
-            p_set_layouts: &descriptor_set_layout,
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
             push_constant_range_count: 0,
             p_push_constant_ranges: ptr::null(),
         };
-        let pipeline_layout = unsafe {
-            device.create_pipeline_layout(&pipeline_layout_ci, None).unwrap()
-        };
+        let pipeline_layout = device::Owned::new(&device, unsafe {
+            device.create_pipeline_layout(&pipeline_layout_ci, None)
+        }?);
 
         let graphics_pipeline_ci = vk::GraphicsPipelineCreateInfo {
             s_type: vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
@@ -764,16 +844,60 @@ impl Renderer {
             p_depth_stencil_state: &depth_stencil_state_ci,
             p_color_blend_state: &color_blend_state_ci,
             p_dynamic_state: &dynamic_state_ci,
-            layout: pipeline_layout,
-            render_pass: render_pass,
+            layout: pipeline_layout.raw(),
+            render_pass: render_pass.raw(),
             subpass: 0,
             base_pipeline_handle: vk::Pipeline::null(),
             base_pipeline_index: 0,
         };
-        let graphics_pipeline_cis = [graphics_pipeline_ci];
-        let graphics_pipelines = unsafe {
-            device.create_graphics_pipelines(vk::PipelineCache::null(), &graphics_pipeline_cis, None).unwrap()
+        let mut graphics_pipeline_cis = vec![graphics_pipeline_ci];
+        // The skybox is a single triangle without vertex buffers at the far plane, see skybox::Skybox. It is drawn after the
+        // model, so it only passes the depth test where the depth buffer is still cleared, and it does not write depth.
+        let skybox_vertex_input_state_ci = vk::PipelineVertexInputStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineVertexInputStateCreateFlags::empty(),
+            vertex_binding_description_count: 0,
+            p_vertex_binding_descriptions: ptr::null(),
+            vertex_attribute_description_count: 0,
+            p_vertex_attribute_descriptions: ptr::null()
+        };
+        let skybox_rasterization_state_ci = vk::PipelineRasterizationStateCreateInfo {
+            cull_mode: vk::CullModeFlags::NONE,
+            ..rasterization_state_ci
+        };
+        let skybox_depth_stencil_state_ci = vk::PipelineDepthStencilStateCreateInfo {
+            depth_write_enable: vk::FALSE,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            ..depth_stencil_state_ci
         };
+        let skybox_shader_modules;
+        let skybox_shader_stages_ci;
+        if let Some(skybox) = &skybox {
+            skybox_shader_modules = [vk_creations::create_shader_module(&device, "shaders/spirv/skybox_vert.spv")?,
+                vk_creations::create_shader_module(&device, "shaders/spirv/skybox_frag.spv")?];
+            skybox_shader_stages_ci = [
+                vk_creations::create_pipeline_shader_stage_create_info(&main_fn_name, vk::ShaderStageFlags::VERTEX,
+                    skybox_shader_modules[0].raw(), None),
+                vk_creations::create_pipeline_shader_stage_create_info(&main_fn_name, vk::ShaderStageFlags::FRAGMENT,
+                    skybox_shader_modules[1].raw(), Some(&fragment_specialization_info)),
+            ];
+            graphics_pipeline_cis.push(vk::GraphicsPipelineCreateInfo {
+                stage_count: skybox_shader_stages_ci.len() as u32,
+                p_stages: skybox_shader_stages_ci.as_ptr(),
+                p_vertex_input_state: &skybox_vertex_input_state_ci,
+                p_rasterization_state: &skybox_rasterization_state_ci,
+                p_depth_stencil_state: &skybox_depth_stencil_state_ci,
+                layout: skybox.pipeline_layout.raw(),
+                ..graphics_pipeline_ci
+            });
+        }
+        let graphics_pipelines = unsafe {
+            device.create_graphics_pipelines(vk::PipelineCache::null(), &graphics_pipeline_cis, None)
+        }.map_err(|(_, result)| result)?;
+        let graphics_pipelines: Vec<device::Pipeline> = graphics_pipelines.into_iter()
+            .map(|graphics_pipeline| device::Owned::new(&device, graphics_pipeline))
+            .collect();
 
         // Creates Semaphores and Fences:
         let semaphore_ci = vk::SemaphoreCreateInfo {
@@ -781,12 +905,12 @@ impl Renderer {
             p_next: ptr::null(),
             flags: vk::SemaphoreCreateFlags::empty(),
         };
-        let mut image_available_semaphores = Vec::<vk::Semaphore>::with_capacity(frames_in_flight_count as usize);
-        let mut render_finished_semaphores = Vec::<vk::Semaphore>::with_capacity(frames_in_flight_count as usize);
+        let mut image_available_semaphores = Vec::<device::Semaphore>::with_capacity(frames_in_flight_count as usize);
+        let mut render_finished_semaphores = Vec::<device::Semaphore>::with_capacity(frames_in_flight_count as usize);
         for _ in 0..frames_in_flight_count {
             unsafe {
-                image_available_semaphores.push(device.create_semaphore(&semaphore_ci, None).unwrap());
-                render_finished_semaphores.push(device.create_semaphore(&semaphore_ci, None).unwrap());
+                image_available_semaphores.push(device::Owned::new(&device, device.create_semaphore(&semaphore_ci, None)?));
+                render_finished_semaphores.push(device::Owned::new(&device, device.create_semaphore(&semaphore_ci, None)?));
             }
         }
 
@@ -795,11 +919,11 @@ impl Renderer {
             p_next: ptr::null(),
             flags: vk::FenceCreateFlags::SIGNALED
         };
-        let mut queue_submit_finished_fences = Vec::<vk::Fence>::with_capacity(frames_in_flight_count as usize);
+        let mut queue_submit_finished_fences = Vec::<device::Fence>::with_capacity(frames_in_flight_count as usize);
         for _ in 0..frames_in_flight_count {
-            queue_submit_finished_fences.push(unsafe {
-                device.create_fence(&fence_ci, None).unwrap()
-            });   
+            queue_submit_finished_fences.push(device::Owned::new(&device, unsafe {
+                device.create_fence(&fence_ci, None)
+            }?));
         }
 
         // Command Pool Creation:
@@ -809,38 +933,38 @@ impl Renderer {
             flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
             queue_family_index: graphics_queue_family_idx,
         };
-        let command_pool = unsafe {
-            device.create_command_pool(&command_pool_ci, None).unwrap()
-        };
+        let command_pool = device::Owned::new(&device, unsafe {
+            device.create_command_pool(&command_pool_ci, None)
+        }?);
         
         // Command Buffer Allocation:
         let command_buffer_alloc_info = vk::CommandBufferAllocateInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
             p_next: ptr::null(),
-            command_pool: command_pool,
+            command_pool: command_pool.raw(),
             level: vk::CommandBufferLevel::PRIMARY,
-            command_buffer_count: frames_in_flight_count as u32,
+            command_buffer_count: frames_in_flight_count,
         };
         let command_buffers = unsafe {
-            device.allocate_command_buffers(&command_buffer_alloc_info).unwrap()
-        };
+            device.allocate_command_buffers(&command_buffer_alloc_info)
+        }?;
+
+        print!("{}", device.allocator().memory_report());
         
-        Renderer {
-            entry,
-            instance,
-            physical_device,
+        Ok(Renderer {
             device,
+            graphics_queue_family_idx,
             graphics_queue,
-            surface,
-            swapchain,
+            present_queue,
+            upload_manager,
+            deletion_queue: deletion::DeletionQueue::new(frames_in_flight_count),
+            target,
 
-            vertex_shader_module,
-            fragment_shader_module,
             render_pass,
             framebuffers,
             pipeline_layout,
             graphics_pipelines,
-            command_pool,
+            _command_pool: command_pool,
             cmd_buffers: command_buffers,
             image_available_semaphores,
             render_finished_semaphores,
@@ -851,42 +975,281 @@ impl Renderer {
             model,
             vertex_buffer,
             index_buffer,
-            descriptor_set_layout,
+            _frame_descriptor_set_layout: frame_descriptor_set_layout,
+            _material_descriptor_set_layout: material_descriptor_set_layout,
 
-            uniform_buffers,
+            _uniform_buffers: uniform_buffers,
             uniform_buffer_mapped_memory_ptrs,
-            descriptor_pool,
-            descriptor_sets,
+            _descriptor_pool: descriptor_pool,
+            frame_descriptor_sets,
+            material_descriptor_sets,
             
-            texture_image,
-            texture_view: texture_image_view,
-            texture_sampler,
+            _texture_cache: texture_cache,
+            skybox,
+            _sampler_cache: sampler_cache,
 
-            depth_images,
             depth_image_views,
 
             msaa_sample_count,
-            msaa_color_images,
             msaa_color_image_views,
+
+            pending_screenshot_path: None,
+            is_swapchain_recreation_pending: false,
+        })
+    }
+
+    /// Renders and presents a frame. The frame is skipped if the window is minimized, or if the swapchain is out of date, in which
+    /// case the swapchain and the resources depending on it are recreated for the next frame.
+    pub fn render_frame (&mut self, window_inner_size: winit::dpi::PhysicalSize<u32>) -> Result<(), RendererError> {
+        // Swapchains can not be created with 0 extent, so nothing is rendered until the window is restored.
+        if window_inner_size.width == 0 || window_inner_size.height == 0 {
+            return Ok(());
+        }
+        if self.is_swapchain_recreation_pending {
+            self.recreate_swapchain_resources(window_inner_size.width, window_inner_size.height)?;
+        }
+
+        let queue_submit_finished_fence = self.queue_submit_finished_fences[self.frame_in_flight_idx].raw();
+        unsafe {
+            self.device.wait_for_fences(&[queue_submit_finished_fence], true, u64::MAX)?;
+        }
+        self.upload_manager.collect_finished()?;
+        self.deletion_queue.on_frame_finished(self.frame_in_flight_idx);
+
+        let image_available_semaphore = self.image_available_semaphores[self.frame_in_flight_idx].raw();
+        let render_finished_semaphore = self.render_finished_semaphores[self.frame_in_flight_idx].raw();
+        let swapchain = self.target.swapchain_mut()?;
+        let acquire_result = unsafe {
+            swapchain.loader.acquire_next_image(swapchain.raw, u64::MAX, image_available_semaphore, vk::Fence::null())
+        };
+        // Suboptimal swapchain images can still be presented, so the swapchain is recreated after presenting.
+        let (swapchain_image_idx, mut is_swapchain_recreation_needed) = match acquire_result {
+            Ok(acquired) => acquired,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                // Nothing has been submitted, so the fence stays signaled for the next try.
+                println!("Swapchain is out of date returned from acquire_next_image, skipping the frame!");
+                return self.recreate_swapchain_resources(window_inner_size.width, window_inner_size.height);
+            },
+            Err(result) => return Err(result.into()),
+        };
+        let extent = swapchain.extent;
+
+        let screenshot = self.pending_screenshot_path.take().map(|path| {
+            screenshot::Screenshot::new(&self.device, path, extent.width, extent.height, self.target.format(),
+                self.graphics_queue_family_idx)
+        }).transpose()?;
+        self.update_uniform_buffer(extent);
+        self.record_command_buffer(swapchain_image_idx as usize, extent, screenshot.as_ref())?;
+    
+        // SUBMITTING:
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &image_available_semaphore,
+            p_wait_dst_stage_mask: &vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            command_buffer_count: 1,
+            p_command_buffers: &self.cmd_buffers[self.frame_in_flight_idx],
+            signal_semaphore_count: 1,
+            // Info: binary semaphore signal and wait must be 1:1 pair because waiting on a semaphore also unsignals it.
+            // Also binary semaphores does only device-device synchronization.
+            // If you need more complex semaphore where you need to wait for a semaphore from 2 batches of commands let say,
+            // you can use timeline semaphores. Timeline semaphores have an internal u64 that can be incremented either by 
+            // host or device. It can be read from host. It can be waited from either host or device.
+            // this signal semaphore will be signaled once all of the p_command_buffers have completed execution:
+            p_signal_semaphores: &render_finished_semaphore,
+        };
+
+        // Reset right before submitting, otherwise returning early would leave the fence unsignaled forever.
+        unsafe {
+            self.device.reset_fences(&[queue_submit_finished_fence])?;
+            self.device.queue_submit( self.graphics_queue, &[submit_info], 
+            // Info: This fence will be signaled once whole BATCH(group of command buffers that have been sent) is completed in queue.
+            // In this case the batch has only 1 command buffer, if there are other commands/commandbuffers/batches that has
+            // no relationship with the submitinfo we sent, they can be continued after the fence is signaled.
+            // If there is cmdA, batch1[cmd1, cmd2, cmd3], cmdB in a queue and if batch1 has to signal a fence in the end,
+            // cmdA and batch1 must be completed in any other in order to signal the fence. Because this fence mechanism puts 
+            // "syncronization scopes". Syncronization scopes has first scope and second scope.
+            // Syncronization scopes creates "execution dependency" that dictates for two sets of operations, first set must happen
+            // before the second set.
+            // In the example: cmdA, batch1[cmd1, cmd2, cmd3](signal fence here), cmdB
+            // then, this fence adds a first synchronization scope that encapsulates cmdA and batch1. Second syncronization scope
+            // normally would be the signalling fence op and the ops after the fence signalling, but queue_submit command puts only
+            // the fence signalling op as second syncronization scope. After fence signalling, it has no execution dependencies for 
+            // subsequent ops. 
+            queue_submit_finished_fence
+        )}?;
+        self.deletion_queue.on_frame_submitted(self.frame_in_flight_idx);
+    
+        let present_info = vk::PresentInfoKHR {
+            s_type: vk::StructureType::PRESENT_INFO_KHR,
+            p_next: ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &render_finished_semaphore,
+            swapchain_count: 1,
+            p_swapchains: &self.target.swapchain_mut()?.raw,
+            p_image_indices: &swapchain_image_idx,
+            p_results: ptr::null_mut()
+        };
+        let present_queue = self.present_queue;
+        let swapchain = self.target.swapchain_mut()?;
+        let present_result = unsafe {
+            swapchain.loader.queue_present(present_queue, &present_info)
+        };
+        match present_result {
+            Ok(is_swapchain_suboptimal) => is_swapchain_recreation_needed |= is_swapchain_suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => is_swapchain_recreation_needed = true,
+            Err(result) => return Err(result.into()),
+        }
+
+        // Saving errors are returned once the frame is finished, so that the next frame is not affected by them.
+        let screenshot_result = match screenshot {
+            Some(screenshot) => {
+                // Screenshot is dropped after waiting either way, which destroys its buffer.
+                unsafe {
+                    self.device.wait_for_fences(&[queue_submit_finished_fence], true, u64::MAX)?;
+                }
+                screenshot.save()
+            },
+            None => Ok(()),
+        };
+
+        self.frame_in_flight_idx = (self.frame_in_flight_idx + 1) % (self.frames_in_flight_count as usize);
+        if is_swapchain_recreation_needed {
+            println!("Swapchain is suboptimal or out of date returned from acquire_next_image or queue_present!");
+            self.recreate_swapchain_resources(window_inner_size.width, window_inner_size.height)?;
         }
+        screenshot_result
     }
 
-    pub fn render_frame (&mut self, window_inner_size: winit::dpi::PhysicalSize<u32>) {
+    /// Renders a frame into the offscreen image and returns its pixels as tightly packed R8G8B8A8 rows. Blocks until the
+    /// frame is finished on the device. Only usable on renderers created with new_headless().
+    pub fn render_offscreen(&mut self) -> Result<Vec<u8>, RendererError> {
+        let offscreen = self.target.offscreen()?;
+        let (width, height, image_count) = (offscreen.width, offscreen.height, offscreen.image_count);
+        let fence = self.queue_submit_finished_fences[self.frame_in_flight_idx].raw();
+        unsafe {
+            self.device.wait_for_fences(&[fence], true, u64::MAX)?;
+        }
+        self.upload_manager.collect_finished()?;
+        self.deletion_queue.on_frame_finished(self.frame_in_flight_idx);
+
+        let extent = vk::Extent2D{width, height};
+        let offscreen_image_idx = self.frame_in_flight_idx % image_count as usize;
+        self.update_uniform_buffer(extent);
+        self.record_command_buffer(offscreen_image_idx, extent, None)?;
+
+        // There is nothing to acquire or present, so no semaphores are needed. Fence is waited right away to read the pixels.
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_count: 0,
+            p_wait_semaphores: ptr::null(),
+            p_wait_dst_stage_mask: ptr::null(),
+            command_buffer_count: 1,
+            p_command_buffers: &self.cmd_buffers[self.frame_in_flight_idx],
+            signal_semaphore_count: 0,
+            p_signal_semaphores: ptr::null(),
+        };
+        unsafe {
+            self.device.reset_fences(&[fence])?;
+            self.device.queue_submit(self.graphics_queue, &[submit_info], fence)?;
+        }
+        self.deletion_queue.on_frame_submitted(self.frame_in_flight_idx);
         unsafe {
-            self.device.wait_for_fences(&[self.queue_submit_finished_fences[self.frame_in_flight_idx]], true, u64::MAX).unwrap();
-            self.device.reset_fences(&[self.queue_submit_finished_fences[self.frame_in_flight_idx]]).unwrap()
+            self.device.wait_for_fences(&[fence], true, u64::MAX)?;
+        }
+
+        let pixels = self.target.offscreen()?.read_pixels();
+
+        self.frame_in_flight_idx = (self.frame_in_flight_idx + 1) % (self.frames_in_flight_count as usize);
+        Ok(pixels)
+    }
+
+    /// Renders a frame into the offscreen image and saves it as a PNG file. Only usable on renderers created with new_headless().
+    pub fn render_offscreen_to_png(&mut self, path: &str) -> Result<(), RendererError> {
+        let pixels = self.render_offscreen()?;
+        let offscreen = self.target.offscreen()?;
+        screenshot::save_png(path, &pixels, offscreen.width, offscreen.height, offscreen.format)
+    }
+
+    /// Saves the presented frame as a PNG file. With a window, the swapchain image of the next rendered frame is copied and
+    /// saved once that frame has finished, errors of saving it are returned by that render_frame(). Headless renderers save
+    /// the last frame rendered by render_offscreen().
+    pub fn capture_screenshot(&mut self, path: &str) -> Result<(), RendererError> {
+        match &self.target {
+            RenderTarget::Swapchain(swapchain) => {
+                if !swapchain.supports_transfer_src() {
+                    return Err(RendererError::UncopyableSwapchainImages);
+                }
+                screenshot::check_format(swapchain.surface.format)?;
+                self.pending_screenshot_path = Some(path.to_owned());
+                Ok(())
+            },
+            RenderTarget::Offscreen(offscreen) => {
+                screenshot::save_png(path, &offscreen.read_pixels(), offscreen.width, offscreen.height, offscreen.format)
+            }
+        }
+    }
+
+    /// Returns the GPU memory usage of every memory heap, grouped by what the memory is used for.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.device.allocator().memory_report()
+    }
+
+    /// Returns the requested present mode, or None for headless renderers.
+    pub fn present_mode(&self) -> Option<PresentMode> {
+        match &self.target {
+            RenderTarget::Swapchain(swapchain) => Some(swapchain.requested_present_mode),
+            RenderTarget::Offscreen(_) => None,
         }
+    }
 
-        let (swapchain_image_idx, is_swapchain_suboptimal) = unsafe {
-            self.swapchain.loader.acquire_next_image(self.swapchain.raw, u64::MAX, self.image_available_semaphores[self.frame_in_flight_idx],
-                vk::Fence::null()).unwrap()
+    /// Switches the present mode, falling back to FIFO if the surface does not support it. The swapchain and the resources
+    /// that depend on it are recreated if the present mode has changed. Does nothing on headless renderers.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<(), RendererError> {
+        let swapchain = match &mut self.target {
+            RenderTarget::Swapchain(swapchain) => swapchain,
+            RenderTarget::Offscreen(_) => {
+                println!("Headless renderers do not present, present mode: {:?} is ignored.", present_mode);
+                return Ok(());
+            }
         };
+        if swapchain.set_present_mode(present_mode) {
+            let extent = swapchain.extent;
+            self.recreate_swapchain_resources(extent.width, extent.height)?;
+        }
+        Ok(())
+    }
+
+    /// Updates the uniform buffer of the current frame in flight.
+    /// View and projection matrices of the camera, which looks at the model.
+    fn get_view_projection(&self, extent: vk::Extent2D) -> (glam::Mat4, glam::Mat4) {
+        let view = glam::Mat4::look_at_lh(glam::vec3(0.0, 1.25 * self.model.scale, 1.25 * self.model.scale), glam::vec3(0.0, 0.0, 0.0), glam::vec3(0.0, 0.0, -1.0));
+        let projection = glam::Mat4::perspective_lh(std::f32::consts::PI / 2.5f32, extent.width as f32 / extent.height as f32, 0.1, 100.0);
+        (view, projection)
+    }
 
-        if is_swapchain_suboptimal {
-            println!("Swapchain is suboptimal returned from queue_present!");
-            self.swapchain.recreate_swapchain(window_inner_size.width, window_inner_size.height);
+    fn update_uniform_buffer(&self, extent: vk::Extent2D) {
+        // Update corresponding uniform buffer:
+        let (view, projection) = self.get_view_projection(extent);
+        let ubo = UniformBufferObject {
+            model: glam::Mat4::from_rotation_z(self.model.rotation),//glam::Mat4::from_rotation_z(time_since_start.as_millis() as f32 / 1000.0f32),
+            view,
+            projection,
+        };
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                &ubo, 
+                self.uniform_buffer_mapped_memory_ptrs[self.frame_in_flight_idx],
+                1);
         }
+    }
 
+    /// Records the frame into the command buffer of the current frame in flight. target_image_idx is the index of the swapchain
+    /// or offscreen image that will be rendered into. If there is a screenshot, the rendered swapchain image is also copied into it.
+    fn record_command_buffer(&self, target_image_idx: usize, extent: vk::Extent2D, screenshot: Option<&screenshot::Screenshot>)
+    -> Result<(), RendererError> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
             p_next: ptr::null(),
@@ -910,11 +1273,11 @@ impl Renderer {
         let render_pass_begin_info = vk::RenderPassBeginInfo {
             s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
             p_next: ptr::null(),
-            render_pass: self.render_pass,
-            framebuffer: self.framebuffers[swapchain_image_idx as usize],
+            render_pass: self.render_pass.raw(),
+            framebuffer: self.framebuffers[target_image_idx].raw(),
             render_area: vk::Rect2D{
                 offset: vk::Offset2D{x: 0, y: 0},
-                extent: vk::Extent2D{width: window_inner_size.width, height: window_inner_size.height}
+                extent
             },
             clear_value_count: clear_values.len() as u32,
             p_clear_values: clear_values.as_ptr()
@@ -924,8 +1287,8 @@ impl Renderer {
         let viewport = vk::Viewport {
             x: 0f32,
             y: 0f32,
-            width: window_inner_size.width as f32,
-            height: window_inner_size.height as f32,
+            width: extent.width as f32,
+            height: extent.height as f32,
             min_depth: 0.0f32,
             max_depth: 1.0f32
         };
@@ -935,215 +1298,117 @@ impl Renderer {
                 x: 0,
                 y: 0
             },
-            extent: vk::Extent2D {
-                width: window_inner_size.width,
-                height: window_inner_size.height,
-            },
+            extent,
         };
 
-        // Update corresponding uniform buffer:
-        let ubo = UniformBufferObject {
-            model: glam::Mat4::from_rotation_z(self.model.rotation),//glam::Mat4::from_rotation_z(time_since_start.as_millis() as f32 / 1000.0f32),
-            view:  glam::Mat4::look_at_lh(glam::vec3(0.0, 1.25 * self.model.scale, 1.25 * self.model.scale), glam::vec3(0.0, 0.0, 0.0), glam::vec3(0.0, 0.0, -1.0)),     
-            projection: glam::Mat4::perspective_lh(std::f32::consts::PI / 2.5f32, window_inner_size.width as f32 / window_inner_size.height as f32, 0.1, 100.0)
-        };
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                &ubo, 
-                (self.uniform_buffer_mapped_memory_ptrs[self.frame_in_flight_idx]) as *mut UniformBufferObject,
-                1);
-        }
-        
         // COMMAND BUFFER RECORDING:
         unsafe {
-            self.device.reset_command_buffer(self.cmd_buffers[self.frame_in_flight_idx], vk::CommandBufferResetFlags::empty()).unwrap();
+            self.device.reset_command_buffer(self.cmd_buffers[self.frame_in_flight_idx], vk::CommandBufferResetFlags::empty())?;
             
-            self.device.begin_command_buffer(self.cmd_buffers[self.frame_in_flight_idx], &command_buffer_begin_info).unwrap();
+            self.device.begin_command_buffer(self.cmd_buffers[self.frame_in_flight_idx], &command_buffer_begin_info)?;
                 self.device.cmd_begin_render_pass(self.cmd_buffers[self.frame_in_flight_idx], &render_pass_begin_info, vk::SubpassContents::INLINE);
                     self.device.cmd_set_viewport(self.cmd_buffers[self.frame_in_flight_idx], 0, &[viewport]);
                     self.device.cmd_set_scissor(self.cmd_buffers[self.frame_in_flight_idx], 0, &[scissor]);
                     self.device.cmd_bind_pipeline(self.cmd_buffers[self.frame_in_flight_idx], 
-                        vk::PipelineBindPoint::GRAPHICS, self.graphics_pipelines[0]);
+                        vk::PipelineBindPoint::GRAPHICS, self.graphics_pipelines[0].raw());
                     self.device.cmd_bind_vertex_buffers(self.cmd_buffers[self.frame_in_flight_idx], 0, &[self.vertex_buffer.raw], &[0]);
                     
                     self.device.cmd_bind_index_buffer(self.cmd_buffers[self.frame_in_flight_idx], self.index_buffer.raw, 0, vk::IndexType::UINT32);
                     self.device.cmd_bind_descriptor_sets(self.cmd_buffers[self.frame_in_flight_idx], 
-                        vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout, 0, &[self.descriptor_sets[self.frame_in_flight_idx]], &[]);
-                    self.device.cmd_draw_indexed(self.cmd_buffers[self.frame_in_flight_idx], self.model.vertex_indices.len() as u32, 1, 0, 0, 0);
+                        vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout.raw(), 0, &[self.frame_descriptor_sets[self.frame_in_flight_idx]], &[]);
+                    let mut bound_material_set_idx = None;
+                    for submesh in &self.model.submeshes {
+                        // Consecutive submeshes of the same material keep its set bound.
+                        let material_set_idx = self.model.get_submesh_material_idx(submesh).map_or(0, |material_idx| material_idx + 1);
+                        if bound_material_set_idx != Some(material_set_idx) {
+                            self.device.cmd_bind_descriptor_sets(self.cmd_buffers[self.frame_in_flight_idx],
+                                vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout.raw(), 1,
+                                &[self.material_descriptor_sets[material_set_idx]], &[]);
+                            bound_material_set_idx = Some(material_set_idx);
+                        }
+                        self.device.cmd_draw_indexed(self.cmd_buffers[self.frame_in_flight_idx], submesh.index_count, 1,
+                            submesh.first_index, 0, 0);
+                    }
+                    if let Some(skybox) = &self.skybox {
+                        let (view, projection) = self.get_view_projection(extent);
+                        skybox.record_draw(&self.device, self.cmd_buffers[self.frame_in_flight_idx], self.graphics_pipelines[1].raw(),
+                            view, projection);
+                    }
                 self.device.cmd_end_render_pass(self.cmd_buffers[self.frame_in_flight_idx]);
-            self.device.end_command_buffer(self.cmd_buffers[self.frame_in_flight_idx]).unwrap();
-        }
-    
-        // SUBMITTING:
-        let submit_info = vk::SubmitInfo {
-            s_type: vk::StructureType::SUBMIT_INFO,
-            p_next: ptr::null(),
-            wait_semaphore_count: 1,
-            p_wait_semaphores: &self.image_available_semaphores[self.frame_in_flight_idx],
-            p_wait_dst_stage_mask: &vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            command_buffer_count: 1,
-            p_command_buffers: &self.cmd_buffers[self.frame_in_flight_idx],
-            signal_semaphore_count: 1,
-            // Info: binary semaphore signal and wait must be 1:1 pair because waiting on a semaphore also unsignals it.
-            // Also binary semaphores does only device-device synchronization.
-            // If you need more complex semaphore where you need to wait for a semaphore from 2 batches of commands let say,
-            // you can use timeline semaphores. Timeline semaphores have an internal u64 that can be incremented either by 
-            // host or device. It can be read from host. It can be waited from either host or device.
-            // this signal semaphore will be signaled once all of the p_command_buffers have completed execution:
-            p_signal_semaphores: &self.render_finished_semaphores[self.frame_in_flight_idx],
-        };
-
-        unsafe {
-            self.device.queue_submit( self.graphics_queue, &[submit_info], 
-            // Info: This fence will be signaled once whole BATCH(group of command buffers that have been sent) is completed in queue.
-            // In this case the batch has only 1 command buffer, if there are other commands/commandbuffers/batches that has
-            // no relationship with the submitinfo we sent, they can be continued after the fence is signaled.
-            // If there is cmdA, batch1[cmd1, cmd2, cmd3], cmdB in a queue and if batch1 has to signal a fence in the end,
-            // cmdA and batch1 must be completed in any other in order to signal the fence. Because this fence mechanism puts 
-            // "syncronization scopes". Syncronization scopes has first scope and second scope.
-            // Syncronization scopes creates "execution dependency" that dictates for two sets of operations, first set must happen
-            // before the second set.
-            // In the example: cmdA, batch1[cmd1, cmd2, cmd3](signal fence here), cmdB
-            // then, this fence adds a first synchronization scope that encapsulates cmdA and batch1. Second syncronization scope
-            // normally would be the signalling fence op and the ops after the fence signalling, but queue_submit command puts only
-            // the fence signalling op as second syncronization scope. After fence signalling, it has no execution dependencies for 
-            // subsequent ops. 
-            self.queue_submit_finished_fences[self.frame_in_flight_idx] 
-        )}.unwrap();
-    
-        let present_info = vk::PresentInfoKHR {
-            s_type: vk::StructureType::PRESENT_INFO_KHR,
-            p_next: ptr::null(),
-            wait_semaphore_count: 1,
-            p_wait_semaphores: &self.render_finished_semaphores[self.frame_in_flight_idx],
-            swapchain_count: 1,
-            p_swapchains: &self.swapchain.raw,
-            p_image_indices: &swapchain_image_idx,
-            p_results: ptr::null_mut()
-        };
-        let is_swapchain_suboptimal = unsafe {
-            self.swapchain.loader.queue_present(self.graphics_queue, &present_info).unwrap()
-        };
-        if is_swapchain_suboptimal {
-            println!("Swapchain is suboptimal returned from queue_present!");
-            self.swapchain.recreate_swapchain(window_inner_size.width, window_inner_size.height);
+                match (&self.target, screenshot) {
+                    (RenderTarget::Offscreen(offscreen), _) => {
+                        offscreen.record_copy_to_readback_buffer(&self.device, self.cmd_buffers[self.frame_in_flight_idx], target_image_idx);
+                    },
+                    (RenderTarget::Swapchain(swapchain), Some(screenshot)) => {
+                        screenshot.record_copy_from_swapchain_image(&self.device, self.cmd_buffers[self.frame_in_flight_idx],
+                            swapchain.images[target_image_idx]);
+                    },
+                    (RenderTarget::Swapchain(_), None) => {}
+                }
+            self.device.end_command_buffer(self.cmd_buffers[self.frame_in_flight_idx])?;
         }
-
-        self.frame_in_flight_idx = (self.frame_in_flight_idx + 1) % (self.frames_in_flight_count as usize);
+        Ok(())
     }
 
+    /// Recreation is deferred to the next render_frame(), so that multiple resize events only recreate the swapchain once.
     pub fn on_window_resized(&mut self, width_new: u32, height_new: u32) {
-        unsafe {
-            self.device.device_wait_idle().unwrap();
+        if let RenderTarget::Swapchain(swapchain) = &self.target {
+            let extent = swapchain.extent;
+            self.is_swapchain_recreation_pending |= extent.width != width_new || extent.height != height_new;
         }
-        self.swapchain.recreate_swapchain(width_new, height_new);
-        self.recreate_depth_images(width_new, height_new);
-        self.recreate_msaa_color_images(width_new, height_new);
-        self.recreate_framebuffers(width_new, height_new);
-
-        println!("Resources are recreated with: (width: {}, height: {})", width_new, height_new);
     }
 
-    fn recreate_depth_images(&mut self, width: u32, height: u32) {
-        for image_view in &self.depth_image_views {
-            unsafe {
-                self.device.destroy_image_view(*image_view, None);
-            }
-        }
-        self.depth_image_views.clear();
-        for depth_image in &self.depth_images {
-            depth_image.destroy(&self.device);
+    /// The single recreation path of the swapchain and every resource that depends on its extent or image count.
+    /// width and height are only used if the surface does not dictate its extent. Does nothing while the window is minimized.
+    fn recreate_swapchain_resources(&mut self, width_new: u32, height_new: u32) -> Result<(), RendererError> {
+        if width_new == 0 || height_new == 0 {
+            return Ok(());
         }
-        self.depth_images.clear();
-
-        (self.depth_images, self.depth_image_views) = vk_creations::create_depth_images_and_views(&self.device, &self.instance,
-            self.physical_device, width, height, vk::Format::D32_SFLOAT, self.msaa_sample_count, self.swapchain.image_count as usize);
+        // Nothing is waited for, resources of the old extent are retired until the frames in flight using them have finished.
+        let swapchain = self.target.swapchain_mut()?;
+        swapchain.recreate_swapchain(width_new, height_new, &mut self.deletion_queue)?;
+        let extent = swapchain.extent;
+        self.recreate_depth_images(extent.width, extent.height)?;
+        self.recreate_msaa_color_images(extent.width, extent.height)?;
+        self.recreate_framebuffers(extent.width, extent.height)?;
+        self.is_swapchain_recreation_pending = false;
+
+        println!("Resources are recreated with: (width: {}, height: {})", extent.width, extent.height);
+        Ok(())
     }
 
-    fn recreate_msaa_color_images(&mut self, width: u32, height: u32) {
-        for image_view in &self.msaa_color_image_views {
-            unsafe {
-                self.device.destroy_image_view(*image_view, None);
-            }
-        }
-        self.msaa_color_image_views.clear();
-        for msaa_color_image in &self.msaa_color_images {
-            msaa_color_image.destroy(&self.device);
-        }
-        self.msaa_color_images.clear();
+    fn recreate_depth_images(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        self.deletion_queue.retire(std::mem::take(&mut self.depth_image_views));
+        self.depth_image_views = vk_creations::create_depth_image_views(&self.device, width, height, vk::Format::D32_SFLOAT,
+            self.msaa_sample_count, self.target.image_count() as usize)?;
+        Ok(())
+    }
 
-        (self.msaa_color_images, self.msaa_color_image_views) = vk_creations::create_msaa_color_images_and_views(&self.device, &self.instance,
-            self.physical_device, width, height, self.surface.format, self.swapchain.image_count as usize);
+    fn recreate_msaa_color_images(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        self.deletion_queue.retire(std::mem::take(&mut self.msaa_color_image_views));
+        self.msaa_color_image_views = vk_creations::create_msaa_color_image_views(&self.device, width, height, self.target.format(),
+            self.msaa_sample_count, self.target.image_count() as usize)?;
+        Ok(())
     }
 
-    fn recreate_framebuffers(&mut self, width: u32, height: u32) {
-        for framebuffer in &self.framebuffers {
-            unsafe {
-                self.device.destroy_framebuffer(*framebuffer, None);
-            }
-        }
-        self.framebuffers.clear(); // Capacity of the Vec stays same after clearing.
-        for (idx, swapchain_image_view) in self.swapchain.image_views.iter().enumerate() {
-            self.framebuffers.push(vk_creations::create_framebuffer(
-                &self.device, &[self.msaa_color_image_views[idx], self.depth_image_views[idx], *swapchain_image_view], self.render_pass,
-                width, height));
+    fn recreate_framebuffers(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        self.deletion_queue.retire(std::mem::take(&mut self.framebuffers));
+        for (idx, target_image_view) in self.target.image_views().iter().enumerate() {
+            self.framebuffers.push(vk_creations::create_framebuffer(&self.device,
+                &[self.msaa_color_image_views[idx].raw, self.depth_image_views[idx].raw, target_image_view.raw], self.render_pass.raw(),
+                width, height)?);
         }
+        Ok(())
     }
 }
 
 impl Drop for Renderer {
+    /// Every resource is destroyed by its own Drop once the device has finished using them, then the device and the instance
+    /// are destroyed after the last of them.
     fn drop(&mut self) {
         unsafe {
-            self.device.device_wait_idle().unwrap();
-            // Command buffers are automatically freed when corresponding Command Pool is destroyed.
-            self.device.destroy_command_pool(self.command_pool, None);
-            for fence in &self.queue_submit_finished_fences {
-                self.device.destroy_fence(*fence, None);
-            }
-            for semaphore in &self.image_available_semaphores {
-                self.device.destroy_semaphore(*semaphore, None);
-            }
-            for semaphore in &self.render_finished_semaphores {
-                self.device.destroy_semaphore(*semaphore, None);
-            }
-            for pipeline in &self.graphics_pipelines {
-                self.device.destroy_pipeline(*pipeline, None);
-            }
-            // Descriptor sets are automatically freed when corresponding Descriptor Pool is destroyed.
-            self.device.destroy_descriptor_pool(self.descriptor_pool, None);
-            self.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
-            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
-            self.vertex_buffer.destroy(&self.device);
-            self.index_buffer.destroy(&self.device);
-            for buffer in &self.uniform_buffers {
-                buffer.destroy(&self.device);
-            }
-            self.texture_image.destroy(&self.device);
-            for msaa_color_image in &self.msaa_color_images {
-                msaa_color_image.destroy(&self.device);
-            }
-            for depth_image in &self.depth_images {
-                depth_image.destroy(&self.device);
-            }
-            for framebuffer in &self.framebuffers {
-                self.device.destroy_framebuffer(*framebuffer, None);
-            }
-            self.device.destroy_render_pass(self.render_pass, None);
-            self.device.destroy_shader_module(self.vertex_shader_module, None);
-            self.device.destroy_shader_module(self.fragment_shader_module, None);
-            self.device.destroy_sampler(self.texture_sampler, None);
-            self.device.destroy_image_view(self.texture_view, None);
-            for msaa_color_image_view in &self.msaa_color_image_views {
-                self.device.destroy_image_view(*msaa_color_image_view, None);
-            }
-            for depth_image_view in &self.depth_image_views {
-                self.device.destroy_image_view(*depth_image_view, None);
-            }
-            self.swapchain.destroy_image_views();
-            self.swapchain.loader.destroy_swapchain(self.swapchain.raw, None);
-            self.surface.loader.destroy_surface(self.surface.surface_khr, None);
-            self.device.destroy_device(None);
-            self.instance.destroy_instance(None);
+            // Resources are destroyed anyway, there is nothing else to do if the device is lost.
+            let _ = self.device.device_wait_idle();
         }
         println!("Renderer has been dropped!");
     }
@@ -0,0 +1,577 @@
+use ash::vk;
+use std::ptr;
+use super::error::RendererError;
+use super::queries;
+
+/// Preferred size of memory blocks. Heaps that are 1 GiB or smaller use 1/8 of their size instead.
+const PREFERRED_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+/// Share of the heap size that is used as the budget if VK_EXT_memory_budget is not available.
+const FALLBACK_BUDGET_RATIO: f64 = 0.8;
+/// A warning is printed once a heap's usage goes above this share of its budget.
+const BUDGET_WARNING_RATIO: f64 = 0.9;
+
+/// What an allocation is used for, only used for statistics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationUsage {
+    Vertex,
+    Index,
+    Uniform,
+    Texture,
+    Depth,
+    Msaa,
+    /// Host visible buffers that are only used to upload data to device local resources.
+    Staging,
+    /// Offscreen color images that are rendered into instead of swapchain images.
+    RenderTarget,
+    /// Host visible buffers that rendered pixels are copied into.
+    Readback,
+}
+
+impl AllocationUsage {
+    pub const ALL: [AllocationUsage; 9] = [AllocationUsage::Vertex, AllocationUsage::Index, AllocationUsage::Uniform,
+        AllocationUsage::Texture, AllocationUsage::Depth, AllocationUsage::Msaa, AllocationUsage::Staging,
+        AllocationUsage::RenderTarget, AllocationUsage::Readback];
+}
+
+/// Whether a resource is laid out linearly in memory (buffers and LINEAR tiling images) or not (OPTIMAL tiling images).
+///
+/// Info: Linear and optimal resources that are placed next to each other in the same VkDeviceMemory must be
+/// bufferImageGranularity apart, otherwise they might alias on some GPUs. Each memory type has a separate pool for both of them,
+/// so they never share a block and allocations only need their own alignment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceTiling {
+    Linear,
+    Optimal,
+}
+
+/// Buffer or image that memory is allocated for.
+#[derive(Clone, Copy, Debug)]
+pub enum MemoryResource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+/// Memory requirements of a resource, and whether the driver asks for a dedicated allocation of it.
+pub struct ResourceMemoryRequirements {
+    pub resource: MemoryResource,
+    pub raw: vk::MemoryRequirements,
+    /// The resource can only be bound to a dedicated allocation.
+    pub requires_dedicated: bool,
+    /// The resource may be faster with a dedicated allocation, e.g. render targets on some GPUs.
+    pub prefers_dedicated: bool,
+}
+
+impl ResourceMemoryRequirements {
+    /// Info: vkGet...MemoryRequirements2 and VkMemoryDedicatedRequirements are core since Vulkan 1.1.
+    pub fn query(device: &ash::Device, resource: MemoryResource) -> ResourceMemoryRequirements {
+        let mut dedicated_requirements = vk::MemoryDedicatedRequirements::default();
+        let mut memory_requirements2 = vk::MemoryRequirements2::builder()
+            .push_next(&mut dedicated_requirements)
+            .build();
+        unsafe {
+            match resource {
+                MemoryResource::Buffer(buffer) => {
+                    let buffer_memory_requirements_info = vk::BufferMemoryRequirementsInfo2 {
+                        s_type: vk::StructureType::BUFFER_MEMORY_REQUIREMENTS_INFO_2,
+                        p_next: ptr::null(),
+                        buffer,
+                    };
+                    device.get_buffer_memory_requirements2(&buffer_memory_requirements_info, &mut memory_requirements2);
+                },
+                MemoryResource::Image(image) => {
+                    let image_memory_requirements_info = vk::ImageMemoryRequirementsInfo2 {
+                        s_type: vk::StructureType::IMAGE_MEMORY_REQUIREMENTS_INFO_2,
+                        p_next: ptr::null(),
+                        image,
+                    };
+                    device.get_image_memory_requirements2(&image_memory_requirements_info, &mut memory_requirements2);
+                },
+            }
+        }
+        ResourceMemoryRequirements {
+            resource,
+            raw: memory_requirements2.memory_requirements,
+            requires_dedicated: dedicated_requirements.requires_dedicated_allocation == vk::TRUE,
+            prefers_dedicated: dedicated_requirements.prefers_dedicated_allocation == vk::TRUE,
+        }
+    }
+}
+
+/// A range of device memory that a buffer or an image is bound to.
+pub struct Allocation {
+    pub device_memory: vk::DeviceMemory,
+    /// Offset into device_memory that the resource must be bound at.
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub usage: AllocationUsage,
+    /// Null if the memory type is not HOST_VISIBLE.
+    mapped_ptr: *mut u8,
+    heap_idx: u32,
+    location: AllocationLocation,
+}
+
+impl Allocation {
+    /// Returns the host pointer to the start of this allocation if its memory is HOST_VISIBLE. Blocks stay mapped for
+    /// as long as they live, so there is no need to map or unmap allocations.
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        if self.mapped_ptr.is_null() {
+            None
+        } else {
+            Some(self.mapped_ptr)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum AllocationLocation {
+    Block { pool_idx: usize, block_idx: usize },
+    /// Has its own VkDeviceMemory, which is freed with the allocation.
+    Dedicated,
+}
+
+/// Blocks of a single memory type for either linear or optimal resources.
+struct MemoryPool {
+    memory_type_idx: u32,
+    heap_idx: u32,
+    tiling: ResourceTiling,
+    block_size: vk::DeviceSize,
+    /// Freed blocks leave None behind, so that block indices of live allocations stay valid.
+    blocks: Vec<Option<MemoryBlock>>,
+}
+
+struct MemoryBlock {
+    device_memory: vk::DeviceMemory,
+    mapped_ptr: *mut u8,
+    /// Free (offset, size) ranges sorted by offset. Adjacent ranges are always merged.
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+    allocation_count: u32,
+}
+
+impl MemoryBlock {
+    /// First fit. Returns the aligned offset of the allocated range.
+    fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        for (range_idx, (range_offset, range_size)) in self.free_ranges.iter().copied().enumerate() {
+            let aligned_offset = align_up(range_offset, alignment);
+            let range_end = range_offset + range_size;
+            if aligned_offset + size > range_end {
+                continue;
+            }
+
+            // Padding before the aligned offset and the rest after the allocation stay free.
+            self.free_ranges.remove(range_idx);
+            if aligned_offset + size < range_end {
+                self.free_ranges.insert(range_idx, (aligned_offset + size, range_end - aligned_offset - size));
+            }
+            if aligned_offset > range_offset {
+                self.free_ranges.insert(range_idx, (range_offset, aligned_offset - range_offset));
+            }
+            self.allocation_count += 1;
+            return Some(aligned_offset);
+        }
+        None
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let range_idx = self.free_ranges.partition_point(|(range_offset, _)| *range_offset < offset);
+        self.free_ranges.insert(range_idx, (offset, size));
+
+        // Merge with the next range first, so that range_idx stays valid for merging with the previous one.
+        if range_idx + 1 < self.free_ranges.len() && offset + size == self.free_ranges[range_idx + 1].0 {
+            self.free_ranges[range_idx].1 += self.free_ranges[range_idx + 1].1;
+            self.free_ranges.remove(range_idx + 1);
+        }
+        if range_idx > 0 {
+            let (previous_offset, previous_size) = self.free_ranges[range_idx - 1];
+            if previous_offset + previous_size == offset {
+                self.free_ranges[range_idx - 1].1 += self.free_ranges[range_idx].1;
+                self.free_ranges.remove(range_idx);
+            }
+        }
+        self.allocation_count -= 1;
+    }
+}
+
+/// Per heap statistics of the allocator.
+#[derive(Clone, Copy, Default)]
+struct HeapStats {
+    /// Size of every block and dedicated allocation of this heap.
+    allocated_bytes: vk::DeviceSize,
+    /// Indexed by the position of the usage in AllocationUsage::ALL.
+    used_bytes_by_usage: [vk::DeviceSize; AllocationUsage::ALL.len()],
+    /// Set once the budget warning is printed, reset when the usage goes below the warning threshold again.
+    is_budget_warned: bool,
+}
+
+/// Memory usage of a single memory heap, see Allocator::memory_report().
+#[derive(Clone, Debug)]
+pub struct HeapReport {
+    pub heap_idx: u32,
+    pub is_device_local: bool,
+    pub size: vk::DeviceSize,
+    /// How much this process can allocate from the heap before allocations start to fail or slow down. Reported by
+    /// VK_EXT_memory_budget, or a share of the heap size if it is not available.
+    pub budget: vk::DeviceSize,
+    /// Usage of the whole process reported by VK_EXT_memory_budget, driver internal allocations included. None if
+    /// VK_EXT_memory_budget is not available.
+    pub process_usage: Option<vk::DeviceSize>,
+    /// Size of every VkDeviceMemory allocated by the allocator. Unused ranges of blocks are included.
+    pub allocated_bytes: vk::DeviceSize,
+    /// Bytes used by resources, in the order of AllocationUsage::ALL.
+    pub used_bytes_by_usage: [vk::DeviceSize; AllocationUsage::ALL.len()],
+}
+
+impl HeapReport {
+    pub fn used_bytes(&self, usage: AllocationUsage) -> vk::DeviceSize {
+        self.used_bytes_by_usage[usage as usize]
+    }
+
+    /// process_usage if it is known, allocated_bytes otherwise.
+    pub fn usage(&self) -> vk::DeviceSize {
+        self.process_usage.unwrap_or(self.allocated_bytes)
+    }
+}
+
+/// Memory usage of every memory heap.
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapReport>,
+}
+
+impl std::fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MIB: f64 = 1024.0 * 1024.0;
+        writeln!(f, "GPU memory report:")?;
+        for heap in &self.heaps {
+            write!(f, "\tHeap {} ({}): size: {:.1} MiB, budget: {:.1} MiB, allocated: {:.1} MiB",
+                heap.heap_idx, if heap.is_device_local {"device local"} else {"host"}, heap.size as f64 / MIB,
+                heap.budget as f64 / MIB, heap.allocated_bytes as f64 / MIB)?;
+            if let Some(process_usage) = heap.process_usage {
+                write!(f, ", process usage: {:.1} MiB", process_usage as f64 / MIB)?;
+            }
+            writeln!(f)?;
+            for usage in AllocationUsage::ALL {
+                let used_bytes = heap.used_bytes(usage);
+                if used_bytes > 0 {
+                    writeln!(f, "\t\t{:?}: {:.2} MiB", usage, used_bytes as f64 / MIB)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sub-allocates buffers and images from big VkDeviceMemory blocks, so that the count of allocations stays far below
+/// maxMemoryAllocationCount. Resources that are bigger than half of a block get dedicated allocations, and so do the ones
+/// that the driver requires or prefers dedicated allocations for.
+pub struct Allocator {
+    /// Only kept if VK_EXT_memory_budget is enabled, to query heap budgets.
+    instance: Option<ash::Instance>,
+    physical_device: vk::PhysicalDevice,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// Two pools per memory type: [memory_type_idx * 2] for linear and [memory_type_idx * 2 + 1] for optimal resources.
+    pools: Vec<MemoryPool>,
+    heap_stats: Vec<HeapStats>,
+    dedicated_allocation_count: u32,
+}
+
+impl Allocator {
+    /// with_memory_budget must only be true if VK_EXT_memory_budget is enabled on the device.
+    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice, with_memory_budget: bool) -> Allocator {
+        let memory_properties = unsafe {
+            instance.get_physical_device_memory_properties(physical_device)
+        };
+        let physical_device_properties = unsafe {
+            instance.get_physical_device_properties(physical_device)
+        };
+
+        let mut pools = Vec::with_capacity(memory_properties.memory_type_count as usize * 2);
+        for memory_type_idx in 0..memory_properties.memory_type_count {
+            let heap_idx = memory_properties.memory_types[memory_type_idx as usize].heap_index;
+            let heap_size = memory_properties.memory_heaps[heap_idx as usize].size;
+            let block_size = if heap_size <= 1024 * 1024 * 1024 {
+                heap_size / 8
+            } else {
+                PREFERRED_BLOCK_SIZE
+            };
+            for tiling in [ResourceTiling::Linear, ResourceTiling::Optimal] {
+                pools.push(MemoryPool {
+                    memory_type_idx,
+                    heap_idx,
+                    tiling,
+                    block_size,
+                    blocks: Vec::new(),
+                });
+            }
+        }
+        println!("Allocator: buffer image granularity: {}, linear and optimal resources are kept in separate blocks.",
+            physical_device_properties.limits.buffer_image_granularity);
+
+        println!("Allocator: VK_EXT_memory_budget is {}.", if with_memory_budget {"enabled"} else {"not available"});
+
+        Allocator {
+            instance: if with_memory_budget {Some(instance.clone())} else {None},
+            physical_device,
+            memory_properties,
+            pools,
+            heap_stats: vec![HeapStats::default(); memory_properties.memory_heap_count as usize],
+            dedicated_allocation_count: 0,
+        }
+    }
+
+    /// Allocates memory that satisfies the memory requirements of a resource and has all of the required property flags.
+    /// The resource must then be bound at allocation.offset of allocation.device_memory.
+    pub fn allocate(&mut self, device: &ash::Device, resource_memory_requirements: &ResourceMemoryRequirements,
+    required_flags: vk::MemoryPropertyFlags, tiling: ResourceTiling, usage: AllocationUsage) -> Result<Allocation, RendererError> {
+        let memory_requirements = &resource_memory_requirements.raw;
+        let memory_type_idx = queries::find_memory_type_idx(&self.memory_properties, memory_requirements.memory_type_bits,
+            required_flags)?;
+        let pool_idx = memory_type_idx as usize * 2 + match tiling {
+            ResourceTiling::Linear => 0,
+            ResourceTiling::Optimal => 1,
+        };
+
+        if resource_memory_requirements.requires_dedicated || resource_memory_requirements.prefers_dedicated ||
+        memory_requirements.size > self.pools[pool_idx].block_size / 2 {
+            return self.allocate_dedicated(device, resource_memory_requirements, memory_type_idx, usage);
+        }
+
+        let pool = &mut self.pools[pool_idx];
+        let heap_idx = pool.heap_idx;
+        for (block_idx, block) in pool.blocks.iter_mut().enumerate() {
+            let Some(block) = block else { continue };
+            if let Some(offset) = block.allocate(memory_requirements.size, memory_requirements.alignment) {
+                self.heap_stats[heap_idx as usize].used_bytes_by_usage[usage as usize] += memory_requirements.size;
+                return Ok(Allocation {
+                    device_memory: block.device_memory,
+                    offset,
+                    size: memory_requirements.size,
+                    usage,
+                    mapped_ptr: if block.mapped_ptr.is_null() {ptr::null_mut()} else {unsafe{block.mapped_ptr.add(offset as usize)}},
+                    heap_idx,
+                    location: AllocationLocation::Block { pool_idx, block_idx },
+                });
+            }
+        }
+
+        // None of the blocks has enough space, so a new block is allocated. Reuses the slot of a freed block if there is any.
+        let block_size = pool.block_size;
+        let (device_memory, mapped_ptr) = self.allocate_heap_memory(device, block_size, memory_type_idx, None)?;
+        let pool = &mut self.pools[pool_idx];
+        println!("Allocator: allocated a {} MiB block from memory type {} for {:?} resources.", block_size / (1024 * 1024),
+            memory_type_idx, pool.tiling);
+        let mut block = MemoryBlock {
+            device_memory,
+            mapped_ptr,
+            free_ranges: vec![(0, block_size)],
+            allocation_count: 0,
+        };
+        // Block is empty and allocation size is at most half of the block, so this can not fail.
+        let offset = block.allocate(memory_requirements.size, memory_requirements.alignment).unwrap_or(0);
+        let block_idx = match pool.blocks.iter().position(|block| block.is_none()) {
+            Some(block_idx) => {
+                pool.blocks[block_idx] = Some(block);
+                block_idx
+            },
+            None => {
+                pool.blocks.push(Some(block));
+                pool.blocks.len() - 1
+            }
+        };
+
+        self.heap_stats[heap_idx as usize].used_bytes_by_usage[usage as usize] += memory_requirements.size;
+
+        Ok(Allocation {
+            device_memory,
+            offset,
+            size: memory_requirements.size,
+            usage,
+            mapped_ptr: if mapped_ptr.is_null() {ptr::null_mut()} else {unsafe{mapped_ptr.add(offset as usize)}},
+            heap_idx,
+            location: AllocationLocation::Block { pool_idx, block_idx },
+        })
+    }
+
+    /// The allocation is tied to its resource through VkMemoryDedicatedAllocateInfo, which lets the driver optimize for it.
+    fn allocate_dedicated(&mut self, device: &ash::Device, resource_memory_requirements: &ResourceMemoryRequirements,
+    memory_type_idx: u32, usage: AllocationUsage) -> Result<Allocation, RendererError> {
+        let size = resource_memory_requirements.raw.size;
+        let (image, buffer) = match resource_memory_requirements.resource {
+            MemoryResource::Buffer(buffer) => (vk::Image::null(), buffer),
+            MemoryResource::Image(image) => (image, vk::Buffer::null()),
+        };
+        let dedicated_allocate_info = vk::MemoryDedicatedAllocateInfo {
+            s_type: vk::StructureType::MEMORY_DEDICATED_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            image,
+            buffer,
+        };
+        let (device_memory, mapped_ptr) = self.allocate_heap_memory(device, size, memory_type_idx, Some(&dedicated_allocate_info))?;
+        self.dedicated_allocation_count += 1;
+        let reason = if resource_memory_requirements.requires_dedicated {
+            "required by the driver"
+        } else if resource_memory_requirements.prefers_dedicated {
+            "preferred by the driver"
+        } else {
+            "bigger than half of a block"
+        };
+        println!("Allocator: allocated a dedicated {} KiB allocation from memory type {} for {:?}, {}.", size / 1024,
+            memory_type_idx, usage, reason);
+
+        let heap_idx = self.memory_properties.memory_types[memory_type_idx as usize].heap_index;
+        self.heap_stats[heap_idx as usize].used_bytes_by_usage[usage as usize] += size;
+
+        Ok(Allocation {
+            device_memory,
+            offset: 0,
+            size,
+            usage,
+            mapped_ptr,
+            heap_idx,
+            location: AllocationLocation::Dedicated,
+        })
+    }
+
+    /// Allocates a new VkDeviceMemory for a block or a dedicated allocation, which are the only allocations that grow the
+    /// usage of a heap. Its budget is checked before every one of them.
+    fn allocate_heap_memory(&mut self, device: &ash::Device, size: vk::DeviceSize, memory_type_idx: u32,
+    dedicated_allocate_info: Option<&vk::MemoryDedicatedAllocateInfo>) -> Result<(vk::DeviceMemory, *mut u8), RendererError> {
+        let heap_idx = self.memory_properties.memory_types[memory_type_idx as usize].heap_index;
+        self.check_budget(heap_idx, size);
+        let memory = Allocator::allocate_device_memory(device, &self.memory_properties, size, memory_type_idx,
+            dedicated_allocate_info)?;
+        self.heap_stats[heap_idx as usize].allocated_bytes += size;
+        Ok(memory)
+    }
+
+    /// Also maps the whole memory if it is HOST_VISIBLE. Mapped pointer is null otherwise.
+    fn allocate_device_memory(device: &ash::Device, memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    size: vk::DeviceSize, memory_type_idx: u32, dedicated_allocate_info: Option<&vk::MemoryDedicatedAllocateInfo>)
+    -> Result<(vk::DeviceMemory, *mut u8), RendererError> {
+        // Info: "Each resource may need more memory than the requested size of a resource. It's because drivers may need
+        // some additional meta-data to manage given resource. That's why we need to call vkGet...MemoryRequirements()
+        // functions and allocate enough memory."
+        let memory_alloc_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: dedicated_allocate_info.map_or(ptr::null(), |info| info as *const _ as *const std::ffi::c_void),
+            allocation_size: size,
+            memory_type_index: memory_type_idx,
+        };
+        let device_memory = unsafe{device.allocate_memory(&memory_alloc_info, None)}?;
+
+        let property_flags = memory_properties.memory_types[memory_type_idx as usize].property_flags;
+        if !property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return Ok((device_memory, ptr::null_mut()));
+        }
+        // Info: A VkDeviceMemory can only be mapped once at a time, so the whole memory is mapped for all of its allocations.
+        match unsafe{device.map_memory(device_memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())} {
+            Ok(mapped_ptr) => Ok((device_memory, mapped_ptr as *mut u8)),
+            Err(result) => {
+                unsafe{device.free_memory(device_memory, None)};
+                Err(result.into())
+            }
+        }
+    }
+
+    /// Returns the range of the allocation to its block, or frees its memory if it is dedicated. Blocks that become empty
+    /// are freed as long as their pool has another block.
+    pub fn free(&mut self, device: &ash::Device, allocation: &Allocation) {
+        let heap_stats = &mut self.heap_stats[allocation.heap_idx as usize];
+        heap_stats.used_bytes_by_usage[allocation.usage as usize] -= allocation.size;
+        match allocation.location {
+            AllocationLocation::Dedicated => {
+                // Info: Freeing a mapped memory implicitly unmaps it.
+                unsafe{device.free_memory(allocation.device_memory, None)};
+                heap_stats.allocated_bytes -= allocation.size;
+                self.dedicated_allocation_count -= 1;
+            },
+            AllocationLocation::Block { pool_idx, block_idx } => {
+                let pool = &mut self.pools[pool_idx];
+                let Some(block) = pool.blocks[block_idx].as_mut() else {
+                    println!("Allocator: allocation is freed after its block!");
+                    return;
+                };
+                block.free(allocation.offset, allocation.size);
+                let (is_block_empty, device_memory) = (block.allocation_count == 0, block.device_memory);
+
+                let live_block_count = pool.blocks.iter().filter(|block| block.is_some()).count();
+                if is_block_empty && live_block_count > 1 {
+                    unsafe{device.free_memory(device_memory, None)};
+                    pool.blocks[block_idx] = None;
+                    heap_stats.allocated_bytes -= pool.block_size;
+                }
+            }
+        }
+    }
+
+    /// Returns the usage of every memory heap. Budgets are queried from the driver each time if VK_EXT_memory_budget is enabled.
+    pub fn memory_report(&self) -> MemoryReport {
+        let memory_budget_properties = self.query_memory_budget_properties();
+        let heaps = self.heap_stats.iter().enumerate().map(|(heap_idx, heap_stats)| {
+            let memory_heap = self.memory_properties.memory_heaps[heap_idx];
+            let (budget, process_usage) = match &memory_budget_properties {
+                Some(properties) => (properties.heap_budget[heap_idx], Some(properties.heap_usage[heap_idx])),
+                None => ((memory_heap.size as f64 * FALLBACK_BUDGET_RATIO) as vk::DeviceSize, None),
+            };
+            HeapReport {
+                heap_idx: heap_idx as u32,
+                is_device_local: memory_heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                size: memory_heap.size,
+                budget,
+                process_usage,
+                allocated_bytes: heap_stats.allocated_bytes,
+                used_bytes_by_usage: heap_stats.used_bytes_by_usage,
+            }
+        }).collect();
+
+        MemoryReport { heaps }
+    }
+
+    /// None if VK_EXT_memory_budget is not enabled.
+    fn query_memory_budget_properties(&self) -> Option<vk::PhysicalDeviceMemoryBudgetPropertiesEXT> {
+        let instance = self.instance.as_ref()?;
+        let mut memory_budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties2 = vk::PhysicalDeviceMemoryProperties2::builder()
+            .push_next(&mut memory_budget_properties)
+            .build();
+        unsafe {
+            instance.get_physical_device_memory_properties2(self.physical_device, &mut memory_properties2);
+        }
+        Some(memory_budget_properties)
+    }
+
+    /// Prints a warning if allocating new_size more bytes takes the usage of the heap above BUDGET_WARNING_RATIO of its budget.
+    fn check_budget(&mut self, heap_idx: u32, new_size: vk::DeviceSize) {
+        let report = self.memory_report();
+        let heap = &report.heaps[heap_idx as usize];
+        let usage = heap.usage() + new_size;
+        let is_above_threshold = usage as f64 > heap.budget as f64 * BUDGET_WARNING_RATIO;
+        let heap_stats = &mut self.heap_stats[heap_idx as usize];
+        if is_above_threshold && !heap_stats.is_budget_warned {
+            println!("Warning: memory heap {} is going to be at {:.1}% of its budget ({} MiB of {} MiB)!", heap_idx,
+                usage as f64 / heap.budget as f64 * 100.0, usage / (1024 * 1024), heap.budget / (1024 * 1024));
+        }
+        heap_stats.is_budget_warned = is_above_threshold;
+    }
+
+    /// Frees every block. All of the resources must have been destroyed and their allocations freed before.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        if self.dedicated_allocation_count > 0 {
+            println!("Allocator: {} dedicated allocation(s) are leaked!", self.dedicated_allocation_count);
+        }
+        for pool in &mut self.pools {
+            for block in pool.blocks.drain(..).flatten() {
+                if block.allocation_count > 0 {
+                    println!("Allocator: a block of memory type {} is freed with {} live allocation(s)!", pool.memory_type_idx,
+                        block.allocation_count);
+                }
+                unsafe{device.free_memory(block.device_memory, None)};
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    // Info: Vulkan alignments are always powers of 2.
+    (value + alignment - 1) & !(alignment - 1)
+}
@@ -0,0 +1,1388 @@
+use ash::vk;
+use super::texture_file;
+
+/// Returns the uncompressed format that decompress() turns blocks of format into, None if it cannot decompress format.
+///
+/// Info: Decompressed formats are 8 bits per channel RGBA, except for BC6H blocks which become 16 bit float RGBA. Channels
+/// that the block format lacks are 0, alpha is 1.0. Sampling them gives the same values as sampling the block format. Only
+/// the LDR profile of ASTC is decompressed, HDR ASTC blocks become magenta like on devices without the HDR profile.
+pub fn decompressed_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC2_UNORM_BLOCK |
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC4_UNORM_BLOCK | vk::Format::BC5_UNORM_BLOCK | vk::Format::BC7_UNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK => Some(vk::Format::R8G8B8A8_UNORM),
+        vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC2_SRGB_BLOCK |
+        vk::Format::BC3_SRGB_BLOCK | vk::Format::BC7_SRGB_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK |
+        vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::BC4_SNORM_BLOCK | vk::Format::BC5_SNORM_BLOCK => Some(vk::Format::R8G8B8A8_SNORM),
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => Some(vk::Format::R16G16B16A16_SFLOAT),
+        format => astc_block(format).map(|(_, is_srgb)| if is_srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM }),
+    }
+}
+
+/// Block extent of ASTC formats, and whether the format is SRGB.
+fn astc_block(format: vk::Format) -> Option<((u32, u32), bool)> {
+    // ASTC formats alternate between UNORM and SRGB, from ASTC_4X4_UNORM_BLOCK to ASTC_12X12_SRGB_BLOCK.
+    let first_astc_format = vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw();
+    if !(first_astc_format..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw()).contains(&format.as_raw()) {
+        return None;
+    }
+    let (block_extent, _) = texture_file::block_info(format)?;
+    Some((block_extent, (format.as_raw() - first_astc_format) % 2 == 1))
+}
+
+/// Decompresses a 4x4 block into row major RGBA texels, texels[y * 4 + x].
+type DecompressBlockFn = fn(&[u8], &mut [[u8; 4]]);
+
+/// Decompresses a width x height image of blocks, which must have a decompressed_format(). Returns tightly packed texels of
+/// the decompressed format, or None if blocks is too short.
+pub fn decompress(format: vk::Format, width: u32, height: u32, blocks: &[u8]) -> Option<Vec<u8>> {
+    let (block_size, decompress_block): (usize, DecompressBlockFn) = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK => (8, |block, texels| decompress_bc1(block, texels, false)),
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => (8, |block, texels| decompress_bc1(block, texels, true)),
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => (16, decompress_bc2),
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => (16, decompress_bc3),
+        vk::Format::BC4_UNORM_BLOCK => (8, |block, texels| decompress_bc4(block, texels, false)),
+        vk::Format::BC4_SNORM_BLOCK => (8, |block, texels| decompress_bc4(block, texels, true)),
+        vk::Format::BC5_UNORM_BLOCK => (16, |block, texels| decompress_bc5(block, texels, false)),
+        vk::Format::BC5_SNORM_BLOCK => (16, |block, texels| decompress_bc5(block, texels, true)),
+        vk::Format::BC6H_UFLOAT_BLOCK => return decompress_blocks(width, height, (4, 4), 16, blocks,
+            |block, texels| decompress_bc6h(block, texels, false)),
+        vk::Format::BC6H_SFLOAT_BLOCK => return decompress_blocks(width, height, (4, 4), 16, blocks,
+            |block, texels| decompress_bc6h(block, texels, true)),
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => (16, decompress_bc7),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK =>
+            (8, |block, texels| decompress_etc2_rgb8(block, texels, false)),
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK =>
+            (8, |block, texels| decompress_etc2_rgb8(block, texels, true)),
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => (16, decompress_etc2_rgba8),
+        format => {
+            let (block_extent, is_srgb) = astc_block(format)?;
+            return decompress_blocks(width, height, block_extent, 16, blocks,
+                |block, texels| decompress_astc(block, texels, block_extent, is_srgb));
+        },
+    };
+    decompress_blocks(width, height, (4, 4), block_size, blocks, decompress_block)
+}
+
+/// decompress_block() writes the row major texels of a block, texels[y * block width + x], which are TEXEL_SIZE bytes each.
+fn decompress_blocks<const TEXEL_SIZE: usize>(width: u32, height: u32, block_extent: (u32, u32), block_size: usize, blocks: &[u8],
+decompress_block: impl Fn(&[u8], &mut [[u8; TEXEL_SIZE]])) -> Option<Vec<u8>> {
+    let blocks_x = width.div_ceil(block_extent.0) as usize;
+    let blocks_y = height.div_ceil(block_extent.1) as usize;
+    if blocks.len() < blocks_x * blocks_y * block_size {
+        return None;
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let (block_width, block_height) = (block_extent.0 as usize, block_extent.1 as usize);
+    let mut texels = vec![0u8; width * height * TEXEL_SIZE];
+    let mut block_texels = vec![[0u8; TEXEL_SIZE]; block_width * block_height];
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let block_offset = (block_y * blocks_x + block_x) * block_size;
+            decompress_block(&blocks[block_offset..block_offset + block_size], &mut block_texels);
+            // Blocks on the right and bottom edges may hang over the image.
+            for y in 0..usize::min(block_height, height - block_y * block_height) {
+                for x in 0..usize::min(block_width, width - block_x * block_width) {
+                    let texel_offset = ((block_y * block_height + y) * width + block_x * block_width + x) * TEXEL_SIZE;
+                    texels[texel_offset..texel_offset + TEXEL_SIZE].copy_from_slice(&block_texels[y * block_width + x]);
+                }
+            }
+        }
+    }
+    Some(texels)
+}
+
+fn rgb565_to_rgb888(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1F) as u8;
+    let g = ((color >> 5) & 0x3F) as u8;
+    let b = (color & 0x1F) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+/// Color part of BC1, BC2 and BC3 blocks. Only BC1 blocks have the 3 color mode with transparent black.
+fn decompress_bc1_colors(block: &[u8], texels: &mut [[u8; 4]], has_three_color_mode: bool, has_alpha: bool) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let rgb0 = rgb565_to_rgb888(color0);
+    let rgb1 = rgb565_to_rgb888(color1);
+    let mut palette = [[rgb0[0], rgb0[1], rgb0[2], 255], [rgb1[0], rgb1[1], rgb1[2], 255], [0, 0, 0, 255], [0, 0, 0, 255]];
+    for channel in 0..3 {
+        let (c0, c1) = (rgb0[channel] as u32, rgb1[channel] as u32);
+        if color0 > color1 || !has_three_color_mode {
+            palette[2][channel] = ((2 * c0 + c1) / 3) as u8;
+            palette[3][channel] = ((c0 + 2 * c1) / 3) as u8;
+        } else {
+            palette[2][channel] = ((c0 + c1) / 2) as u8;
+        }
+    }
+    if color0 <= color1 && has_three_color_mode && has_alpha {
+        palette[3][3] = 0;
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (texel_idx, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (texel_idx * 2)) & 0b11) as usize];
+    }
+}
+
+/// BC3 alpha, BC4 and BC5 channel block. Returns the 8 bit values of the 16 texels, which are two's complement SNORM values
+/// for signed blocks.
+fn decompress_bc4_channel(block: &[u8], is_signed: bool) -> [u8; 16] {
+    // Signed blocks decode -128 as -127, so that both are -1.0.
+    let (value0, value1, min, max) = if is_signed {
+        (i32::max(block[0] as i8 as i32, -127), i32::max(block[1] as i8 as i32, -127), -127, 127)
+    } else {
+        (block[0] as i32, block[1] as i32, 0, 255)
+    };
+    let mut palette = [value0, value1, 0, 0, 0, 0, min, max];
+    if value0 > value1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * value0 + i as i32 * value1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * value0 + i as i32 * value1) / 5;
+        }
+    }
+
+    let indices = u64::from_le_bytes([block[2], block[3], block[4], block[5], block[6], block[7], 0, 0]);
+    let mut values = [0u8; 16];
+    for (texel_idx, value) in values.iter_mut().enumerate() {
+        *value = palette[((indices >> (texel_idx * 3)) & 0b111) as usize] as u8;
+    }
+    values
+}
+
+fn decompress_bc1(block: &[u8], texels: &mut [[u8; 4]], has_alpha: bool) {
+    decompress_bc1_colors(block, texels, true, has_alpha);
+}
+
+fn decompress_bc2(block: &[u8], texels: &mut [[u8; 4]]) {
+    decompress_bc1_colors(&block[8..], texels, false, false);
+    let alphas = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    for (texel_idx, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alphas >> (texel_idx * 4)) & 0xF) as u8 * 17;
+    }
+}
+
+fn decompress_bc3(block: &[u8], texels: &mut [[u8; 4]]) {
+    decompress_bc1_colors(&block[8..], texels, false, false);
+    let alphas = decompress_bc4_channel(&block[0..8], false);
+    for (texel, alpha) in texels.iter_mut().zip(alphas) {
+        texel[3] = alpha;
+    }
+}
+
+/// 1.0 alpha of UNORM and SNORM texels.
+fn max_alpha(is_signed: bool) -> u8 {
+    if is_signed { 127 } else { 255 }
+}
+
+fn decompress_bc4(block: &[u8], texels: &mut [[u8; 4]], is_signed: bool) {
+    let reds = decompress_bc4_channel(block, is_signed);
+    for (texel, red) in texels.iter_mut().zip(reds) {
+        *texel = [red, 0, 0, max_alpha(is_signed)];
+    }
+}
+
+fn decompress_bc5(block: &[u8], texels: &mut [[u8; 4]], is_signed: bool) {
+    let reds = decompress_bc4_channel(&block[0..8], is_signed);
+    let greens = decompress_bc4_channel(&block[8..16], is_signed);
+    for (texel_idx, texel) in texels.iter_mut().enumerate() {
+        *texel = [reds[texel_idx], greens[texel_idx], 0, max_alpha(is_signed)];
+    }
+}
+
+const ETC_MODIFIER_TABLES: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+const EAC_MODIFIER_TABLES: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn extend_4_to_8_bits(value: u64) -> i32 {
+    ((value << 4) | value) as i32
+}
+
+fn extend_5_to_8_bits(value: u64) -> i32 {
+    ((value << 3) | (value >> 2)) as i32
+}
+
+fn offset_rgb(rgb: [i32; 3], offset: i32) -> [u8; 4] {
+    [(rgb[0] + offset).clamp(0, 255) as u8, (rgb[1] + offset).clamp(0, 255) as u8, (rgb[2] + offset).clamp(0, 255) as u8, 255]
+}
+
+/// ETC1 compatible individual and differential modes, plus the T, H and planar modes of ETC2. Blocks with punch-through
+/// alpha have no individual mode, their differential bit says whether the block is opaque instead.
+///
+/// Info: ETC blocks are big endian, and their texel indices are in column major order, texel (x, y) has the index bits
+/// 16 + x * 4 + y and x * 4 + y.
+fn decompress_etc2_rgb8(block: &[u8], texels: &mut [[u8; 4]], has_punch_through_alpha: bool) {
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let texel_index = |x: usize, y: usize| {
+        let shift = x * 4 + y;
+        ((((bits >> (16 + shift)) & 1) << 1) | ((bits >> shift) & 1)) as usize
+    };
+    // Texels of index 2 are transparent black in blocks that are not opaque, except in planar mode.
+    let is_transparent_mode = has_punch_through_alpha && (bits >> 33) & 1 == 0;
+    let is_differential = has_punch_through_alpha || (bits >> 33) & 1 == 1;
+
+    if !is_differential {
+        let rgb1 = [extend_4_to_8_bits((bits >> 60) & 0xF), extend_4_to_8_bits((bits >> 52) & 0xF), extend_4_to_8_bits((bits >> 44) & 0xF)];
+        let rgb2 = [extend_4_to_8_bits((bits >> 56) & 0xF), extend_4_to_8_bits((bits >> 48) & 0xF), extend_4_to_8_bits((bits >> 40) & 0xF)];
+        decompress_etc_subblocks(bits, rgb1, rgb2, texels, texel_index, is_transparent_mode);
+        return;
+    }
+
+    let base = [(bits >> 59) & 0x1F, (bits >> 51) & 0x1F, (bits >> 43) & 0x1F];
+    // 3 bit two's complement deltas.
+    let delta = [(bits >> 56) & 0x7, (bits >> 48) & 0x7, (bits >> 40) & 0x7].map(|delta| ((delta as i32) << 29) >> 29);
+    let sums = [0, 1, 2].map(|channel| base[channel] as i32 + delta[channel]);
+
+    if !(0..32).contains(&sums[0]) {
+        // T mode:
+        let rgb1 = [extend_4_to_8_bits((((bits >> 59) & 0x3) << 2) | ((bits >> 56) & 0x3)), extend_4_to_8_bits((bits >> 52) & 0xF),
+            extend_4_to_8_bits((bits >> 48) & 0xF)];
+        let rgb2 = [extend_4_to_8_bits((bits >> 44) & 0xF), extend_4_to_8_bits((bits >> 40) & 0xF), extend_4_to_8_bits((bits >> 36) & 0xF)];
+        let distance = ETC_DISTANCES[((((bits >> 34) & 0x3) << 1) | ((bits >> 32) & 1)) as usize];
+        let mut palette = [offset_rgb(rgb1, 0), offset_rgb(rgb2, distance), offset_rgb(rgb2, 0), offset_rgb(rgb2, -distance)];
+        if is_transparent_mode {
+            palette[2] = [0; 4];
+        }
+        for y in 0..4 {
+            for x in 0..4 {
+                texels[y * 4 + x] = palette[texel_index(x, y)];
+            }
+        }
+    } else if !(0..32).contains(&sums[1]) {
+        // H mode:
+        let rgb1_bits = [(bits >> 59) & 0xF, (((bits >> 56) & 0x7) << 1) | ((bits >> 52) & 1),
+            (((bits >> 51) & 1) << 3) | ((bits >> 47) & 0x7)];
+        let rgb2_bits = [(bits >> 43) & 0xF, (bits >> 39) & 0xF, (bits >> 35) & 0xF];
+        let rgb1_value = (rgb1_bits[0] << 8) | (rgb1_bits[1] << 4) | rgb1_bits[2];
+        let rgb2_value = (rgb2_bits[0] << 8) | (rgb2_bits[1] << 4) | rgb2_bits[2];
+        let distance_idx = (((bits >> 34) & 1) << 2) | (((bits >> 32) & 1) << 1) | (rgb1_value >= rgb2_value) as u64;
+        let distance = ETC_DISTANCES[distance_idx as usize];
+        let rgb1 = rgb1_bits.map(extend_4_to_8_bits);
+        let rgb2 = rgb2_bits.map(extend_4_to_8_bits);
+        let mut palette = [offset_rgb(rgb1, distance), offset_rgb(rgb1, -distance), offset_rgb(rgb2, distance), offset_rgb(rgb2, -distance)];
+        if is_transparent_mode {
+            palette[2] = [0; 4];
+        }
+        for y in 0..4 {
+            for x in 0..4 {
+                texels[y * 4 + x] = palette[texel_index(x, y)];
+            }
+        }
+    } else if !(0..32).contains(&sums[2]) {
+        // Planar mode, colors are interpolated between the origin, horizontal and vertical colors:
+        let extend_6 = |value: u64| ((value << 2) | (value >> 4)) as i32;
+        let extend_7 = |value: u64| ((value << 1) | (value >> 6)) as i32;
+        let origin = [extend_6((bits >> 57) & 0x3F), extend_7((((bits >> 56) & 1) << 6) | ((bits >> 49) & 0x3F)),
+            extend_6((((bits >> 48) & 1) << 5) | (((bits >> 43) & 0x3) << 3) | ((bits >> 39) & 0x7))];
+        let horizontal = [extend_6((((bits >> 34) & 0x1F) << 1) | ((bits >> 32) & 1)), extend_7((bits >> 25) & 0x7F),
+            extend_6((bits >> 19) & 0x3F)];
+        let vertical = [extend_6((bits >> 13) & 0x3F), extend_7((bits >> 6) & 0x7F), extend_6(bits & 0x3F)];
+        for y in 0..4 {
+            for x in 0..4 {
+                let channel = |c: usize| ((x as i32 * (horizontal[c] - origin[c]) + y as i32 * (vertical[c] - origin[c]) +
+                    4 * origin[c] + 2) >> 2).clamp(0, 255) as u8;
+                texels[y * 4 + x] = [channel(0), channel(1), channel(2), 255];
+            }
+        }
+    } else {
+        let rgb1 = base.map(extend_5_to_8_bits);
+        let rgb2 = sums.map(|sum| extend_5_to_8_bits(sum as u64));
+        decompress_etc_subblocks(bits, rgb1, rgb2, texels, texel_index, is_transparent_mode);
+    }
+}
+
+/// Individual and differential modes split the block into two 2x4 or 4x2 subblocks, each with a base color and a modifier table.
+/// In transparent mode, index 0 has no modifier and index 2 is transparent black.
+fn decompress_etc_subblocks(bits: u64, rgb1: [i32; 3], rgb2: [i32; 3], texels: &mut [[u8; 4]],
+texel_index: impl Fn(usize, usize) -> usize, is_transparent_mode: bool) {
+    let is_flipped = (bits >> 32) & 1 == 1;
+    let modifier_tables = [ETC_MODIFIER_TABLES[((bits >> 37) & 0x7) as usize], ETC_MODIFIER_TABLES[((bits >> 34) & 0x7) as usize]];
+    for y in 0..4 {
+        for x in 0..4 {
+            let is_second_subblock = if is_flipped { y >= 2 } else { x >= 2 };
+            let (rgb, modifiers) = if is_second_subblock { (rgb2, modifier_tables[1]) } else { (rgb1, modifier_tables[0]) };
+            texels[y * 4 + x] = match texel_index(x, y) {
+                0 if is_transparent_mode => offset_rgb(rgb, 0),
+                2 if is_transparent_mode => [0; 4],
+                0 => offset_rgb(rgb, modifiers[0]),
+                1 => offset_rgb(rgb, modifiers[1]),
+                2 => offset_rgb(rgb, -modifiers[0]),
+                _ => offset_rgb(rgb, -modifiers[1]),
+            };
+        }
+    }
+}
+
+/// ETC2 RGB block preceded by an EAC alpha block.
+fn decompress_etc2_rgba8(block: &[u8], texels: &mut [[u8; 4]]) {
+    decompress_etc2_rgb8(&block[8..16], texels, false);
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let base = ((bits >> 56) & 0xFF) as i32;
+    let multiplier = ((bits >> 52) & 0xF) as i32;
+    let modifiers = EAC_MODIFIER_TABLES[((bits >> 48) & 0xF) as usize];
+    for y in 0..4 {
+        for x in 0..4 {
+            let modifier_idx = (bits >> (45 - 3 * (x * 4 + y))) & 0x7;
+            texels[y * 4 + x][3] = (base + modifiers[modifier_idx as usize] * multiplier).clamp(0, 255) as u8;
+        }
+    }
+}
+
+/// Reads bit fields of 128 bit little endian blocks, from the lowest bit up.
+struct BlockBits {
+    bits: u128,
+    position: u32,
+}
+impl BlockBits {
+    fn new(block: &[u8]) -> BlockBits {
+        BlockBits { bits: u128::from_le_bytes(block[0..16].try_into().unwrap()), position: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = bits_at(self.bits, self.position, count);
+        self.position += count;
+        value
+    }
+}
+
+/// count bits of bits from position, bits past the end of the block are 0.
+fn bits_at(bits: u128, position: u32, count: u32) -> u32 {
+    if count == 0 || position >= 128 {
+        return 0;
+    }
+    ((bits >> position) & ((1u128 << count) - 1)) as u32
+}
+
+fn sign_extend(value: i32, bit_count: u32) -> i32 {
+    (value << (32 - bit_count)) >> (32 - bit_count)
+}
+
+/// Weights of 2, 3 and 4 bit indices of BC6H and BC7 blocks, out of 64.
+const BPTC_WEIGHTS_2: [i32; 4] = [0, 21, 43, 64];
+const BPTC_WEIGHTS_3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BPTC_WEIGHTS_4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bptc_interpolate(value0: i32, value1: i32, index_bits: u32, index: u32) -> i32 {
+    let weight = match index_bits {
+        2 => BPTC_WEIGHTS_2[index as usize],
+        3 => BPTC_WEIGHTS_3[index as usize],
+        _ => BPTC_WEIGHTS_4[index as usize],
+    };
+    ((64 - weight) * value0 + weight * value1 + 32) >> 6
+}
+
+/// Subset of each texel in the 64 two subset partitions, one bit per texel. BC6H uses the first 32.
+const BPTC_PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+/// Subset of each texel in the 64 three subset partitions of BC7, two bits per texel.
+const BC7_PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+/// Anchor texels of the second subset of two subset partitions, and of the second and third subset of three subset
+/// partitions. The first subset is anchored at texel 0. Anchor texels store their index without its highest bit, which is 0.
+const BPTC_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+const BC7_ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+const BC7_ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+/// Subset of texel texel_idx, and whether it is the anchor of that subset.
+fn bptc_subset(subset_count: usize, partition: usize, texel_idx: usize) -> (usize, bool) {
+    match subset_count {
+        1 => (0, texel_idx == 0),
+        2 => {
+            let subset = ((BPTC_PARTITIONS_2[partition] >> texel_idx) & 1) as usize;
+            (subset, texel_idx == [0, BPTC_ANCHORS_2[partition] as usize][subset])
+        },
+        _ => {
+            let subset = ((BC7_PARTITIONS_3[partition] >> (texel_idx * 2)) & 0b11) as usize;
+            let anchors = [0, BC7_ANCHORS_3_SECOND[partition] as usize, BC7_ANCHORS_3_THIRD[partition] as usize];
+            (subset, texel_idx == anchors[subset])
+        },
+    }
+}
+
+struct Bc7Mode {
+    subset_count: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    /// 0 for modes without alpha, their alpha is 1.0.
+    alpha_bits: u32,
+    /// Every endpoint has a P-bit, the lowest bit of all of its channels.
+    has_endpoint_p_bits: bool,
+    /// Both endpoints of a subset share a P-bit.
+    has_shared_p_bits: bool,
+    index_bits: u32,
+    /// Modes 4 and 5 have separate indices for color and alpha.
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subset_count: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0,
+        has_endpoint_p_bits: true, has_shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0,
+        has_endpoint_p_bits: false, has_shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0,
+        has_endpoint_p_bits: false, has_shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0,
+        has_endpoint_p_bits: true, has_shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6,
+        has_endpoint_p_bits: false, has_shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8,
+        has_endpoint_p_bits: false, has_shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7,
+        has_endpoint_p_bits: true, has_shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5,
+        has_endpoint_p_bits: true, has_shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+/// The mode is the count of 0 bits before the lowest 1 bit. Blocks without a 1 bit in the first byte are reserved and
+/// transparent black.
+fn decompress_bc7(block: &[u8], texels: &mut [[u8; 4]]) {
+    let mode_idx = block[0].trailing_zeros();
+    let Some(mode) = BC7_MODES.get(mode_idx as usize) else {
+        texels.fill([0; 4]);
+        return;
+    };
+    let mut bits = BlockBits::new(block);
+    bits.read(mode_idx + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Every channel is stored for all endpoints before the next channel:
+    let endpoint_count = mode.subset_count * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    let mut channel_bits = [mode.color_bits, mode.color_bits, mode.color_bits, mode.alpha_bits];
+    for (channel, bit_count) in channel_bits.iter().enumerate() {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(*bit_count);
+        }
+    }
+    if mode.has_endpoint_p_bits || mode.has_shared_p_bits {
+        let mut p_bit = 0;
+        for (endpoint_idx, endpoint) in endpoints[..endpoint_count].iter_mut().enumerate() {
+            if mode.has_endpoint_p_bits || endpoint_idx % 2 == 0 {
+                p_bit = bits.read(1);
+            }
+            for (value, bit_count) in endpoint.iter_mut().zip(channel_bits) {
+                if bit_count > 0 {
+                    *value = (*value << 1) | p_bit;
+                }
+            }
+        }
+        channel_bits = channel_bits.map(|bit_count| if bit_count > 0 { bit_count + 1 } else { 0 });
+    }
+    // Endpoints are extended to 8 bits by repeating their highest bits:
+    let endpoints = endpoints.map(|endpoint| [0, 1, 2, 3].map(|channel| {
+        let (value, bit_count) = (endpoint[channel], channel_bits[channel]);
+        if bit_count == 0 { 255 } else { ((value << (8 - bit_count)) | (value >> (2 * bit_count - 8))) as i32 }
+    }));
+
+    let mut indices = [0u32; 16];
+    for (texel_idx, index) in indices.iter_mut().enumerate() {
+        let (_, is_anchor) = bptc_subset(mode.subset_count, partition, texel_idx);
+        *index = bits.read(mode.index_bits - is_anchor as u32);
+    }
+    let mut secondary_indices = indices;
+    if mode.secondary_index_bits > 0 {
+        for (texel_idx, index) in secondary_indices.iter_mut().enumerate() {
+            *index = bits.read(mode.secondary_index_bits - (texel_idx == 0) as u32);
+        }
+    }
+    let ((color_indices, color_index_bits), (alpha_indices, alpha_index_bits)) = match (mode.secondary_index_bits, index_selection) {
+        (0, _) => ((indices, mode.index_bits), (indices, mode.index_bits)),
+        (_, 0) => ((indices, mode.index_bits), (secondary_indices, mode.secondary_index_bits)),
+        _ => ((secondary_indices, mode.secondary_index_bits), (indices, mode.index_bits)),
+    };
+
+    for (texel_idx, texel) in texels.iter_mut().enumerate() {
+        let (subset, _) = bptc_subset(mode.subset_count, partition, texel_idx);
+        let (endpoint0, endpoint1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        *texel = [0, 1, 2, 3].map(|channel| {
+            let (index_bits, index) = if channel < 3 {
+                (color_index_bits, color_indices[texel_idx])
+            } else {
+                (alpha_index_bits, alpha_indices[texel_idx])
+            };
+            bptc_interpolate(endpoint0[channel], endpoint1[channel], index_bits, index) as u8
+        });
+        // The rotation swaps alpha with a color channel, after interpolation.
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+    }
+}
+
+/// Endpoint channels in BC6H mode layouts, red, green and blue of endpoints 0 to 3.
+const R0: usize = 0;
+const G0: usize = 1;
+const B0: usize = 2;
+const R1: usize = 3;
+const G1: usize = 4;
+const B1: usize = 5;
+const R2: usize = 6;
+const G2: usize = 7;
+const B2: usize = 8;
+const R3: usize = 9;
+const G3: usize = 10;
+const B3: usize = 11;
+
+struct Bc6hMode {
+    /// Endpoints other than the first are stored as deltas from it.
+    is_transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// Endpoint channel, first and last bit of the runs of bits after the mode bits. A run is reversed if its first bit is
+    /// higher than its last bit.
+    layout: &'static [(usize, u32, u32)],
+}
+
+/// Modes 1 to 10 have two regions, modes 11 to 14 have one.
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { is_transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], layout: &[(G2, 4, 4), (B2, 4, 4), (B3, 4, 4),
+        (R0, 0, 9), (G0, 0, 9), (B0, 0, 9), (R1, 0, 4), (G3, 4, 4), (G2, 0, 3), (G1, 0, 4), (B3, 0, 0), (G3, 0, 3), (B1, 0, 4),
+        (B3, 1, 1), (B2, 0, 3), (R2, 0, 4), (B3, 2, 2), (R3, 0, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], layout: &[(G2, 5, 5), (G3, 4, 5), (R0, 0, 6),
+        (B3, 0, 1), (B2, 4, 4), (G0, 0, 6), (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 0, 6), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4),
+        (R1, 0, 5), (G2, 0, 3), (G1, 0, 5), (G3, 0, 3), (B1, 0, 5), (B2, 0, 3), (R2, 0, 5), (R3, 0, 5)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 4), (R0, 10, 10), (G2, 0, 3), (G1, 0, 3), (G0, 10, 10), (B3, 0, 0), (G3, 0, 3), (B1, 0, 3), (B0, 10, 10),
+        (B3, 1, 1), (B2, 0, 3), (R2, 0, 4), (B3, 2, 2), (R3, 0, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 3), (R0, 10, 10), (G3, 4, 4), (G2, 0, 3), (G1, 0, 4), (G0, 10, 10), (G3, 0, 3), (B1, 0, 3), (B0, 10, 10),
+        (B3, 1, 1), (B2, 0, 3), (R2, 0, 3), (B3, 0, 0), (B3, 2, 2), (R3, 0, 3), (G2, 4, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 3), (R0, 10, 10), (B2, 4, 4), (G2, 0, 3), (G1, 0, 3), (G0, 10, 10), (B3, 0, 0), (G3, 0, 3), (B1, 0, 4),
+        (B0, 10, 10), (B2, 0, 3), (R2, 0, 3), (B3, 1, 2), (R3, 0, 3), (B3, 4, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], layout: &[(R0, 0, 8), (B2, 4, 4), (G0, 0, 8),
+        (G2, 4, 4), (B0, 0, 8), (B3, 4, 4), (R1, 0, 4), (G3, 4, 4), (G2, 0, 3), (G1, 0, 4), (B3, 0, 0), (G3, 0, 3), (B1, 0, 4),
+        (B3, 1, 1), (B2, 0, 3), (R2, 0, 4), (B3, 2, 2), (R3, 0, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], layout: &[(R0, 0, 7), (G3, 4, 4), (B2, 4, 4),
+        (G0, 0, 7), (B3, 2, 2), (G2, 4, 4), (B0, 0, 7), (B3, 3, 4), (R1, 0, 5), (G2, 0, 3), (G1, 0, 4), (B3, 0, 0), (G3, 0, 3),
+        (B1, 0, 4), (B3, 1, 1), (B2, 0, 3), (R2, 0, 5), (R3, 0, 5)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], layout: &[(R0, 0, 7), (B3, 0, 0), (B2, 4, 4),
+        (G0, 0, 7), (G2, 5, 5), (G2, 4, 4), (B0, 0, 7), (G3, 5, 5), (B3, 4, 4), (R1, 0, 4), (G3, 4, 4), (G2, 0, 3), (G1, 0, 5),
+        (G3, 0, 3), (B1, 0, 4), (B3, 1, 1), (B2, 0, 3), (R2, 0, 4), (B3, 2, 2), (R3, 0, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], layout: &[(R0, 0, 7), (B3, 1, 1), (B2, 4, 4),
+        (G0, 0, 7), (B2, 5, 5), (G2, 4, 4), (B0, 0, 7), (B3, 5, 5), (B3, 4, 4), (R1, 0, 4), (G3, 4, 4), (G2, 0, 3), (G1, 0, 4),
+        (B3, 0, 0), (G3, 0, 3), (B1, 0, 5), (B2, 0, 3), (R2, 0, 4), (B3, 2, 2), (R3, 0, 4), (B3, 3, 3)] },
+    Bc6hMode { is_transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], layout: &[(R0, 0, 5), (G3, 4, 4), (B3, 0, 1),
+        (B2, 4, 4), (G0, 0, 5), (G2, 5, 5), (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 0, 5), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5),
+        (B3, 4, 4), (R1, 0, 5), (G2, 0, 3), (G1, 0, 5), (G3, 0, 3), (B1, 0, 5), (B2, 0, 3), (R2, 0, 5), (R3, 0, 5)] },
+    Bc6hMode { is_transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 9), (G1, 0, 9), (B1, 0, 9)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 8), (R0, 10, 10), (G1, 0, 8), (G0, 10, 10), (B1, 0, 8), (B0, 10, 10)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 7), (R0, 11, 10), (G1, 0, 7), (G0, 11, 10), (B1, 0, 7), (B0, 11, 10)] },
+    Bc6hMode { is_transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], layout: &[(R0, 0, 9), (G0, 0, 9), (B0, 0, 9),
+        (R1, 0, 3), (R0, 15, 10), (G1, 0, 3), (G0, 15, 10), (B1, 0, 3), (B0, 15, 10)] },
+];
+
+/// Index to BC6H_MODES of the 2 or 5 mode bits, None for the reserved modes.
+fn bc6h_mode_idx(mode_bits: u32) -> Option<usize> {
+    match mode_bits {
+        0 | 1 => Some(mode_bits as usize),
+        // Two region modes end in 10, one region modes in 11:
+        _ if mode_bits & 0b11 == 0b10 => Some(2 + (mode_bits >> 2) as usize),
+        _ if mode_bits & 0b11 == 0b11 && mode_bits >> 2 < 0b100 => Some(10 + (mode_bits >> 2) as usize),
+        _ => None,
+    }
+}
+
+/// Scales an endpoint channel to the 16 bit range.
+fn unquantize_bc6h(value: i32, bit_count: u32, is_signed: bool) -> i32 {
+    if !is_signed {
+        if bit_count >= 15 || value == 0 {
+            value
+        } else if value == (1 << bit_count) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bit_count
+        }
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if bit_count >= 16 || magnitude == 0 {
+            magnitude
+        } else if magnitude >= (1 << (bit_count - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bit_count - 1)
+        };
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+/// Bits of the half float of an interpolated channel.
+fn bc6h_to_half(value: i32, is_signed: bool) -> u16 {
+    if !is_signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+/// Decompresses into RGBA texels of native endian half floats. Reserved modes are black.
+fn decompress_bc6h(block: &[u8], texels: &mut [[u8; 8]], is_signed: bool) {
+    let one = half::f16::ONE.to_bits().to_ne_bytes();
+    let mut bits = BlockBits::new(block);
+    let mut mode_bits = bits.read(2);
+    if mode_bits >= 2 {
+        mode_bits |= bits.read(3) << 2;
+    }
+    let Some(mode_idx) = bc6h_mode_idx(mode_bits) else {
+        texels.fill([0, 0, 0, 0, 0, 0, one[0], one[1]]);
+        return;
+    };
+    let mode = &BC6H_MODES[mode_idx];
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for &(channel, first_bit, last_bit) in mode.layout {
+        let (endpoint, channel) = (channel / 3, channel % 3);
+        let bit_range: Vec<u32> = if first_bit <= last_bit { (first_bit..=last_bit).collect() } else { (last_bit..=first_bit).rev().collect() };
+        for bit in bit_range {
+            endpoints[endpoint][channel] |= (bits.read(1) as i32) << bit;
+        }
+    }
+    let region_count = if mode_idx < 10 { 2 } else { 1 };
+    let partition = if region_count == 2 { bits.read(5) as usize } else { 0 };
+
+    let endpoint_count = region_count * 2;
+    if is_signed {
+        endpoints[0] = endpoints[0].map(|value| sign_extend(value, mode.endpoint_bits));
+    }
+    let base_endpoint = endpoints[0];
+    for endpoint in &mut endpoints[1..endpoint_count] {
+        for channel in 0..3 {
+            if mode.is_transformed {
+                // Deltas are signed, even in unsigned blocks.
+                let delta = sign_extend(endpoint[channel], mode.delta_bits[channel]);
+                endpoint[channel] = (base_endpoint[channel] + delta) & ((1 << mode.endpoint_bits) - 1);
+            }
+            if is_signed {
+                endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+            }
+        }
+    }
+    let endpoints = endpoints.map(|endpoint| endpoint.map(|value| unquantize_bc6h(value, mode.endpoint_bits, is_signed)));
+
+    let index_bits = if region_count == 2 { 3 } else { 4 };
+    for (texel_idx, texel) in texels.iter_mut().enumerate() {
+        let (region, is_anchor) = bptc_subset(region_count, partition, texel_idx);
+        let index = bits.read(index_bits - is_anchor as u32);
+        let (endpoint0, endpoint1) = (endpoints[region * 2], endpoints[region * 2 + 1]);
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            bc6h_to_half(bptc_interpolate(endpoint0[channel], endpoint1[channel], index_bits, index), is_signed).to_ne_bytes()
+        });
+        *texel = [r[0], r[1], g[0], g[1], b[0], b[1], one[0], one[1]];
+    }
+}
+
+/// Malformed ASTC blocks, and the texels of ASTC partitions with HDR endpoints decode to magenta.
+const ASTC_ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+/// Quantization levels of ASTC color endpoints, the highest one that fits into the remaining bits of a block is used.
+const ASTC_COLOR_LEVELS: [u32; 17] = [6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256];
+
+fn decompress_astc(block: &[u8], texels: &mut [[u8; 4]], block_extent: (u32, u32), is_srgb: bool) {
+    if decompress_astc_block(block, texels, block_extent, is_srgb).is_none() {
+        texels.fill(ASTC_ERROR_COLOR);
+    }
+}
+
+/// None if the block decodes to the error color.
+fn decompress_astc_block(block: &[u8], texels: &mut [[u8; 4]], block_extent: (u32, u32), is_srgb: bool) -> Option<()> {
+    let bits = u128::from_le_bytes(block[0..16].try_into().unwrap());
+    if bits & 0x1FF == 0x1FC {
+        return decompress_astc_void_extent(bits, texels);
+    }
+    let (block_width, block_height) = block_extent;
+    let ((grid_width, grid_height), weight_levels, is_dual_plane) = astc_block_mode(bits_at(bits, 0, 11))?;
+    let plane_count = if is_dual_plane { 2 } else { 1 };
+    let weight_count = (grid_width * grid_height * plane_count) as usize;
+    if grid_width > block_width || grid_height > block_height || weight_count > 64 {
+        return None;
+    }
+    let weight_bit_count = ise_bit_count(weight_count, weight_levels);
+    if !(24..=96).contains(&weight_bit_count) {
+        return None;
+    }
+    let partition_count = bits_at(bits, 11, 2) as usize + 1;
+    if is_dual_plane && partition_count == 4 {
+        return None;
+    }
+
+    // Endpoint modes, the 4 bit "CEM" of each partition. Extra mode bits and the dual plane channel are stored just below
+    // the weights, which fill the block from the top:
+    let mut endpoint_modes = [0u32; 4];
+    let mut partition_idx = 0;
+    let mut below_weights = 128 - weight_bit_count;
+    let color_start = if partition_count == 1 {
+        endpoint_modes[0] = bits_at(bits, 13, 4);
+        17
+    } else {
+        partition_idx = bits_at(bits, 13, 10);
+        let mode_bits = bits_at(bits, 23, 6);
+        if mode_bits & 0b11 == 0 {
+            endpoint_modes = [mode_bits >> 2; 4];
+        } else {
+            // Every partition has a class bit which is added to the base class, and 2 bits of the mode within the class.
+            let extra_bit_count = 3 * partition_count as u32 - 4;
+            below_weights -= extra_bit_count;
+            let class_and_mode_bits = (mode_bits >> 2) | (bits_at(bits, below_weights, extra_bit_count) << 4);
+            for (partition, endpoint_mode) in endpoint_modes[..partition_count].iter_mut().enumerate() {
+                let class = (mode_bits & 0b11) - 1 + ((class_and_mode_bits >> partition) & 1);
+                *endpoint_mode = (class << 2) | ((class_and_mode_bits >> (partition_count + partition * 2)) & 0b11);
+            }
+        }
+        29
+    };
+    let dual_plane_channel = if is_dual_plane {
+        below_weights -= 2;
+        bits_at(bits, below_weights, 2) as usize
+    } else {
+        4
+    };
+
+    let color_value_count: usize = endpoint_modes[..partition_count].iter().map(|mode| ((mode >> 2) as usize + 1) * 2).sum();
+    if color_value_count > 18 {
+        return None;
+    }
+    let color_bit_count = below_weights.checked_sub(color_start)?;
+    let color_levels = *ASTC_COLOR_LEVELS.iter().rev().find(|levels| ise_bit_count(color_value_count, **levels) <= color_bit_count)?;
+    let color_values: Vec<i32> = decode_ise(bits, color_start, color_value_count, color_levels).into_iter()
+        .map(|value| unquantize_astc_color(value, color_levels)).collect();
+    let mut endpoints = [None; 4];
+    let mut color_values = color_values.as_slice();
+    for (partition_endpoints, mode) in endpoints.iter_mut().zip(&endpoint_modes[..partition_count]) {
+        *partition_endpoints = astc_endpoints(*mode, color_values);
+        color_values = &color_values[((mode >> 2) as usize + 1) * 2..];
+    }
+
+    // Weights are stored bit reversed, from the top of the block down:
+    let weights: Vec<u32> = decode_ise(bits.reverse_bits(), 0, weight_count, weight_levels).into_iter()
+        .map(|value| unquantize_astc_weight(value, weight_levels)).collect();
+    let is_small_block = block_width * block_height < 31;
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let partition = if partition_count > 1 {
+                astc_partition(partition_idx, x, y, partition_count as u32, is_small_block)
+            } else {
+                0
+            };
+            // Only the texels of partitions with HDR endpoints get the error color.
+            let Some([endpoint0, endpoint1]) = endpoints[partition] else {
+                texels[(y * block_width + x) as usize] = ASTC_ERROR_COLOR;
+                continue;
+            };
+            let plane_weights = [0, plane_count as usize - 1].map(|plane| {
+                infill_astc_weight(&weights, plane, plane_count, (grid_width, grid_height), (block_width, block_height), (x, y)) as i32
+            });
+            texels[(y * block_width + x) as usize] = [0, 1, 2, 3].map(|channel| {
+                let weight = plane_weights[(channel == dual_plane_channel) as usize];
+                // Endpoints are expanded to 16 bits before interpolation, the top 8 bits of the result are kept.
+                let expand = |value: i32| if is_srgb { (value << 8) | 0x80 } else { (value << 8) | value };
+                let value = (expand(endpoint0[channel]) * (64 - weight) + expand(endpoint1[channel]) * weight + 32) >> 6;
+                (value >> 8) as u8
+            });
+        }
+    }
+    Some(())
+}
+
+/// Blocks of a single 16 bit UNORM color.
+fn decompress_astc_void_extent(bits: u128, texels: &mut [[u8; 4]]) -> Option<()> {
+    let is_hdr = bits_at(bits, 9, 1) == 1;
+    if is_hdr {
+        return None;
+    }
+    // The extent of texels with the same color is only a hint, but it must be valid.
+    let extent = [12, 25, 38, 51].map(|position| bits_at(bits, position, 13));
+    if extent != [0x1FFF; 4] && (extent[0] >= extent[1] || extent[2] >= extent[3]) {
+        return None;
+    }
+    let color = [64, 80, 96, 112].map(|position| (bits_at(bits, position, 16) >> 8) as u8);
+    texels.fill(color);
+    Some(())
+}
+
+/// Weight grid width and height, weight quantization levels and whether there are two weight planes, None for reserved
+/// block modes.
+fn astc_block_mode(block_mode: u32) -> Option<((u32, u32), u32, bool)> {
+    let bit = |idx: u32| (block_mode >> idx) & 1;
+    let a = (block_mode >> 5) & 0b11;
+    let (grid, range_bits, is_dual_plane, is_high_precision) = if block_mode & 0b11 != 0 {
+        let b = (block_mode >> 7) & 0b11;
+        let grid = match (block_mode >> 2) & 0b11 {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+            _ => (bit(7) + 2, a + 2),
+        };
+        (grid, ((block_mode & 0b11) << 1) | bit(4), bit(10) == 1, bit(9) == 1)
+    } else {
+        let range_bits = (((block_mode >> 2) & 0b11) << 1) | bit(4);
+        if range_bits < 2 {
+            return None;
+        }
+        let b = (block_mode >> 9) & 0b11;
+        match ((block_mode >> 7) & 0b11, a) {
+            (0, _) => ((12, a + 2), range_bits, bit(10) == 1, bit(9) == 1),
+            (1, _) => ((a + 2, 12), range_bits, bit(10) == 1, bit(9) == 1),
+            (2, _) => ((a + 6, b + 6), range_bits, false, false),
+            (_, 0) => ((6, 10), range_bits, bit(10) == 1, bit(9) == 1),
+            (_, 1) => ((10, 6), range_bits, bit(10) == 1, bit(9) == 1),
+            _ => return None,
+        }
+    };
+    let levels = if is_high_precision { [10, 12, 16, 20, 24, 32] } else { [2, 3, 4, 5, 6, 8] };
+    Some((grid, levels[range_bits as usize - 2], is_dual_plane))
+}
+
+/// Bit count and trit (3) or quint (5) count of integer sequence encoded values with levels quantization levels, the base
+/// is 1 for values that are only bits.
+fn ise_encoding(levels: u32) -> (u32, u32) {
+    if levels % 3 == 0 {
+        ((levels / 3).trailing_zeros(), 3)
+    } else if levels % 5 == 0 {
+        ((levels / 5).trailing_zeros(), 5)
+    } else {
+        (levels.trailing_zeros(), 1)
+    }
+}
+
+fn ise_bit_count(value_count: usize, levels: u32) -> u32 {
+    let (bit_count, base) = ise_encoding(levels);
+    let value_count = value_count as u32;
+    value_count * bit_count + match base {
+        3 => (value_count * 8).div_ceil(5),
+        5 => (value_count * 7).div_ceil(3),
+        _ => 0,
+    }
+}
+
+/// Decodes the integer sequence encoded values from position start of bits. 5 values share 8 bits of trits, 3 values share
+/// 7 bits of quints, which are interleaved with the bits of the values.
+fn decode_ise(bits: u128, start: u32, value_count: usize, levels: u32) -> Vec<u32> {
+    let (bit_count, base) = ise_encoding(levels);
+    let end = start + ise_bit_count(value_count, levels);
+    let mut position = start;
+    // The last group of values may be cut short, its missing bits are 0.
+    let mut read = |count: u32| {
+        let value = bits_at(bits, position, u32::min(count, end.saturating_sub(position)));
+        position += count;
+        value
+    };
+    let mut values = Vec::with_capacity(value_count + 4);
+    while values.len() < value_count {
+        match base {
+            3 => {
+                let mut low_bits = [0; 5];
+                let mut trit_bits = 0;
+                for (value_idx, (shift, count)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].into_iter().enumerate() {
+                    low_bits[value_idx] = read(bit_count);
+                    trit_bits |= read(count) << shift;
+                }
+                values.extend(decode_trits(trit_bits).into_iter().zip(low_bits).map(|(trit, low_bits)| (trit << bit_count) | low_bits));
+            },
+            5 => {
+                let mut low_bits = [0; 3];
+                let mut quint_bits = 0;
+                for (value_idx, (shift, count)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate() {
+                    low_bits[value_idx] = read(bit_count);
+                    quint_bits |= read(count) << shift;
+                }
+                values.extend(decode_quints(quint_bits).into_iter().zip(low_bits).map(|(quint, low_bits)| (quint << bit_count) | low_bits));
+            },
+            _ => values.push(read(bit_count)),
+        }
+    }
+    values.truncate(value_count);
+    values
+}
+
+fn decode_trits(bits: u32) -> [u32; 5] {
+    let bit = |value: u32, idx: u32| (value >> idx) & 1;
+    let (c, t3, t4) = if (bits >> 2) & 0b111 == 0b111 {
+        ((((bits >> 5) & 0b111) << 2) | (bits & 0b11), 2, 2)
+    } else if (bits >> 5) & 0b11 == 0b11 {
+        (bits & 0x1F, bit(bits, 7), 2)
+    } else {
+        (bits & 0x1F, (bits >> 5) & 0b11, bit(bits, 7))
+    };
+    let (t0, t1, t2) = if c & 0b11 == 0b11 {
+        ((bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1), bit(c, 4), 2)
+    } else if (c >> 2) & 0b11 == 0b11 {
+        (c & 0b11, 2, 2)
+    } else {
+        ((bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1), (c >> 2) & 0b11, bit(c, 4))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(bits: u32) -> [u32; 3] {
+    let bit = |value: u32, idx: u32| (value >> idx) & 1;
+    if (bits >> 1) & 0b11 == 0b11 && (bits >> 5) & 0b11 == 0 {
+        let not_bit0 = !bit(bits, 0) & 1;
+        return [4, 4, (bit(bits, 0) << 2) | ((bit(bits, 4) & not_bit0) << 1) | (bit(bits, 3) & not_bit0)];
+    }
+    let (c, q2) = if (bits >> 1) & 0b11 == 0b11 {
+        ((((bits >> 3) & 0b11) << 3) | ((!(bits >> 5) & 0b11) << 1) | bit(bits, 0), 4)
+    } else {
+        (bits & 0x1F, (bits >> 5) & 0b11)
+    };
+    if c & 0b111 == 0b101 {
+        [(c >> 3) & 0b11, 4, q2]
+    } else {
+        [c & 0b111, (c >> 3) & 0b11, q2]
+    }
+}
+
+/// Extends value to target_bit_count bits by repeating its bits.
+fn replicate_bits(value: u32, bit_count: u32, target_bit_count: u32) -> u32 {
+    let mut replicated = 0;
+    let mut replicated_bit_count = 0;
+    while replicated_bit_count < target_bit_count {
+        replicated = (replicated << bit_count) | value;
+        replicated_bit_count += bit_count;
+    }
+    replicated >> (replicated_bit_count - target_bit_count)
+}
+
+/// Scales a color endpoint value to 0-255. Values with trits or quints are ordered so that their lowest bit mirrors them.
+fn unquantize_astc_color(value: u32, levels: u32) -> i32 {
+    let (bit_count, base) = ise_encoding(levels);
+    let low_bits = value & ((1 << bit_count) - 1);
+    if base == 1 {
+        return replicate_bits(low_bits, bit_count, 8) as i32;
+    }
+    let high_bits = low_bits >> 1;
+    let (c, b) = match (base, bit_count) {
+        (3, 1) => (204, 0),
+        (5, 1) => (113, 0),
+        (3, 2) => (93, (high_bits << 8) | (high_bits << 4) | (high_bits << 2) | (high_bits << 1)),
+        (5, 2) => (54, (high_bits << 8) | (high_bits << 3) | (high_bits << 2)),
+        (3, 3) => (44, (high_bits << 7) | (high_bits << 2) | high_bits),
+        (5, 3) => (26, (high_bits << 7) | (high_bits << 1) | (high_bits >> 1)),
+        (3, 4) => (22, (high_bits << 6) | high_bits),
+        (5, 4) => (13, (high_bits << 6) | (high_bits >> 1)),
+        (3, 5) => (11, (high_bits << 5) | (high_bits >> 2)),
+        (5, 5) => (6, (high_bits << 5) | (high_bits >> 3)),
+        _ => (5, (high_bits << 4) | (high_bits >> 4)),
+    };
+    let a = if low_bits & 1 == 1 { 0x1FF } else { 0 };
+    let t = ((value >> bit_count) * c + b) ^ a;
+    ((a & 0x80) | (t >> 2)) as i32
+}
+
+/// Scales a weight to 0-64.
+fn unquantize_astc_weight(value: u32, levels: u32) -> u32 {
+    let (bit_count, base) = ise_encoding(levels);
+    let low_bits = value & ((1 << bit_count) - 1);
+    let high_bits = low_bits >> 1;
+    let unquantized = match (base, bit_count) {
+        (1, _) => replicate_bits(low_bits, bit_count, 6),
+        (3, 0) => [0, 32, 63][value as usize],
+        (5, 0) => [0, 16, 32, 47, 63][value as usize],
+        _ => {
+            let (c, b) = match (base, bit_count) {
+                (3, 1) => (50, 0),
+                (5, 1) => (28, 0),
+                (3, 2) => (23, (high_bits << 6) | (high_bits << 2) | high_bits),
+                (5, 2) => (13, (high_bits << 6) | (high_bits << 1)),
+                _ => (11, (high_bits << 5) | high_bits),
+            };
+            let a = if low_bits & 1 == 1 { 0x7F } else { 0 };
+            let t = ((value >> bit_count) * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        },
+    };
+    if unquantized > 32 { unquantized + 1 } else { unquantized }
+}
+
+/// Moves the top bit of b into a, which becomes a signed 6 bit offset.
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3F;
+    (if a & 0x20 != 0 { a - 0x40 } else { a }, b)
+}
+
+/// Pulls red and green halfway to blue, when the endpoints are stored in reverse order.
+fn blue_contract(r: i32, g: i32, b: i32, a: i32) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// RGBA endpoints of the LDR endpoint modes, None for HDR ones.
+fn astc_endpoints(mode: u32, values: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let v = |idx: usize| values[idx];
+    let endpoints = match mode {
+        // Luminance:
+        0 => [[v(0), v(0), v(0), 255], [v(1), v(1), v(1), 255]],
+        1 => {
+            let l0 = (v(0) >> 2) | (v(1) & 0xC0);
+            let l1 = i32::min(l0 + (v(1) & 0x3F), 255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        },
+        // Luminance and alpha:
+        4 => [[v(0), v(0), v(0), v(2)], [v(1), v(1), v(1), v(3)]],
+        5 => {
+            let (l_offset, l) = bit_transfer_signed(v(1), v(0));
+            let (a_offset, a) = bit_transfer_signed(v(3), v(2));
+            [[l, l, l, a], [l + l_offset, l + l_offset, l + l_offset, a + a_offset]]
+        },
+        // RGB and a scale for the first endpoint, with alpha:
+        6 | 10 => {
+            let (a0, a1) = if mode == 10 { (v(4), v(5)) } else { (255, 255) };
+            [[(v(0) * v(3)) >> 8, (v(1) * v(3)) >> 8, (v(2) * v(3)) >> 8, a0], [v(0), v(1), v(2), a1]]
+        },
+        // RGB with alpha:
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v(6), v(7)) } else { (255, 255) };
+            if v(1) + v(3) + v(5) >= v(0) + v(2) + v(4) {
+                [[v(0), v(2), v(4), a0], [v(1), v(3), v(5), a1]]
+            } else {
+                [blue_contract(v(1), v(3), v(5), a1), blue_contract(v(0), v(2), v(4), a0)]
+            }
+        },
+        // RGB base and offset, with alpha:
+        9 | 13 => {
+            let (r_offset, r) = bit_transfer_signed(v(1), v(0));
+            let (g_offset, g) = bit_transfer_signed(v(3), v(2));
+            let (b_offset, b) = bit_transfer_signed(v(5), v(4));
+            let (a_offset, a) = if mode == 13 { bit_transfer_signed(v(7), v(6)) } else { (0, 255) };
+            if r_offset + g_offset + b_offset >= 0 {
+                [[r, g, b, a], [r + r_offset, g + g_offset, b + b_offset, a + a_offset]]
+            } else {
+                [blue_contract(r + r_offset, g + g_offset, b + b_offset, a + a_offset), blue_contract(r, g, b, a)]
+            }
+        },
+        _ => return None,
+    };
+    Some(endpoints.map(|endpoint| endpoint.map(|value| value.clamp(0, 255))))
+}
+
+/// Partition of texel (x, y) in partition pattern partition_idx, chosen by a hash of the pattern and the texel.
+fn astc_partition(partition_idx: u32, x: u32, y: u32, partition_count: u32, is_small_block: bool) -> usize {
+    let (x, y) = if is_small_block { (x << 1, y << 1) } else { (x, y) };
+    let seed = partition_idx + (partition_count - 1) * 1024;
+    let mut random = seed;
+    random ^= random >> 15;
+    random = random.wrapping_sub(random << 17);
+    random = random.wrapping_add(random << 7);
+    random = random.wrapping_add(random << 4);
+    random ^= random >> 5;
+    random = random.wrapping_add(random << 16);
+    random ^= random >> 7;
+    random ^= random >> 3;
+    random ^= random << 6;
+    random ^= random >> 17;
+
+    let seeds = [0, 4, 8, 12, 16, 20, 24, 28].map(|shift| {
+        let seed = (random >> shift) & 0xF;
+        seed * seed
+    });
+    let (shift1, shift2) = if seed & 1 == 1 {
+        (if seed & 2 == 2 { 4 } else { 5 }, if partition_count == 3 { 6 } else { 5 })
+    } else {
+        (if partition_count == 3 { 6 } else { 5 }, if seed & 2 == 2 { 4 } else { 5 })
+    };
+    let seeds: [u32; 8] = std::array::from_fn(|idx| seeds[idx] >> if idx % 2 == 0 { shift1 } else { shift2 });
+
+    // The z coordinate of 3D blocks is 0, so the seeds that it is multiplied by are left out.
+    let mut values = [
+        seeds[0] * x + seeds[1] * y + (random >> 14),
+        seeds[2] * x + seeds[3] * y + (random >> 10),
+        seeds[4] * x + seeds[5] * y + (random >> 6),
+        seeds[6] * x + seeds[7] * y + (random >> 2),
+    ].map(|value| value & 0x3F);
+    for value in &mut values[partition_count as usize..] {
+        *value = 0;
+    }
+    // The first of the highest values wins:
+    let max_value = values.iter().max().unwrap();
+    values.iter().position(|value| value == max_value).unwrap()
+}
+
+/// Bilinearly interpolates the weight of texel (x, y) from the weight grid, in 1/16 steps.
+fn infill_astc_weight(weights: &[u32], plane: usize, plane_count: u32, grid_extent: (u32, u32), block_extent: (u32, u32),
+texel: (u32, u32)) -> u32 {
+    let (grid_width, grid_height) = grid_extent;
+    let scale = |texel_coordinate: u32, block_size: u32, grid_size: u32| {
+        let block_scale = (1024 + block_size / 2) / (block_size - 1);
+        (block_scale * texel_coordinate * (grid_size - 1) + 32) >> 6
+    };
+    let grid_s = scale(texel.0, block_extent.0, grid_width);
+    let grid_t = scale(texel.1, block_extent.1, grid_height);
+    let (s, fraction_s) = (grid_s >> 4, grid_s & 0xF);
+    let (t, fraction_t) = (grid_t >> 4, grid_t & 0xF);
+    // Weights past the edge of the grid have a factor of 0.
+    let weight = |s: u32, t: u32| if s < grid_width && t < grid_height {
+        weights[((t * grid_width + s) * plane_count) as usize + plane]
+    } else {
+        0
+    };
+    let factor11 = (fraction_s * fraction_t + 8) >> 4;
+    let factor10 = fraction_t - factor11;
+    let factor01 = fraction_s - factor11;
+    let factor00 = 16 + factor11 - fraction_s - fraction_t;
+    (weight(s, t) * factor00 + weight(s + 1, t) * factor01 + weight(s, t + 1) * factor10 + weight(s + 1, t + 1) * factor11 + 8) >> 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress_block(format: vk::Format, block: &[u8]) -> Vec<[u8; 4]> {
+        decompress(format, 4, 4, block).unwrap().chunks_exact(4).map(|texel| texel.try_into().unwrap()).collect()
+    }
+
+    /// Half float bits of RGBA texels.
+    fn decompress_half_block(format: vk::Format, block: &[u8]) -> Vec<[u16; 4]> {
+        decompress(format, 4, 4, block).unwrap().chunks_exact(8)
+            .map(|texel| [0, 2, 4, 6].map(|offset| u16::from_ne_bytes([texel[offset], texel[offset + 1]]))).collect()
+    }
+
+    #[test]
+    fn bc1_four_color_mode_interpolates_thirds() {
+        let texels = decompress_block(vk::Format::BC1_RGB_UNORM_BLOCK, &[0x00, 0xF8, 0x1F, 0x00, 0xE4, 0, 0, 0]);
+        assert_eq!(texels[0..4], [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]]);
+    }
+
+    #[test]
+    fn bc1_three_color_mode_has_black_or_transparent_texels() {
+        let block = [0x1F, 0x00, 0x00, 0xF8, 0xE4, 0, 0, 0];
+        let texels = decompress_block(vk::Format::BC1_RGB_UNORM_BLOCK, &block);
+        assert_eq!(texels[2], [127, 0, 127, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 255]);
+        let texels = decompress_block(vk::Format::BC1_RGBA_UNORM_BLOCK, &block);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc2_expands_explicit_alpha() {
+        let texels = decompress_block(vk::Format::BC2_UNORM_BLOCK,
+            &[0x8F, 0, 0, 0, 0, 0, 0, 0, 0x00, 0xF8, 0x1F, 0x00, 0, 0, 0, 0]);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [255, 0, 0, 136]);
+        assert_eq!(texels[2][3], 0);
+    }
+
+    #[test]
+    fn bc3_eight_value_alpha_mode() {
+        let texels = decompress_block(vk::Format::BC3_UNORM_BLOCK,
+            &[255, 0, 0x88, 0, 0, 0, 0, 0, 0x00, 0xF8, 0x1F, 0x00, 0, 0, 0, 0]);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1][3], 0);
+        assert_eq!(texels[2][3], 218);
+    }
+
+    #[test]
+    fn bc4_unorm_six_value_mode_has_min_and_max() {
+        let texels = decompress_block(vk::Format::BC4_UNORM_BLOCK, &[0, 255, 0xF2, 0x01, 0, 0, 0, 0]);
+        assert_eq!(texels[0..4], [[51, 0, 0, 255], [0, 0, 0, 255], [255, 0, 0, 255], [0, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn bc4_snorm_clamps_minus_128_and_stays_signed() {
+        let texels = decompress_block(vk::Format::BC4_SNORM_BLOCK, &[0x80, 0x7F, 0xB8, 0, 0, 0, 0, 0]);
+        assert_eq!(texels[0..3], [[0x81, 0, 0, 127], [127, 0, 0, 127], [0xB4, 0, 0, 127]]);
+    }
+
+    #[test]
+    fn bc5_decompresses_red_and_green() {
+        let texels = decompress_block(vk::Format::BC5_UNORM_BLOCK,
+            &[255, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0xF2, 0x01, 0, 0, 0, 0]);
+        assert_eq!(texels[0..3], [[255, 51, 0, 255], [255, 0, 0, 255], [255, 255, 0, 255]]);
+    }
+
+    #[test]
+    fn etc2_differential_mode() {
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &[0x80, 0x00, 0x00, 0x02, 0, 0, 0, 0]);
+        assert!(texels.iter().all(|texel| *texel == [134, 2, 2, 255]));
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red overflows: 2 + -3.
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &[0x15, 0x3C, 0x18, 0xF7, 0x11, 0x00, 0x10, 0x10]);
+        assert_eq!(texels[0..5], [[153, 51, 204, 255], [33, 152, 255, 255], [17, 136, 255, 255], [1, 120, 239, 255],
+            [153, 51, 204, 255]]);
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green overflows: 29 + 3.
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &[0x42, 0xEB, 0x15, 0x2E, 0x11, 0x00, 0x10, 0x10]);
+        assert_eq!(texels[0..4], [[168, 100, 255, 255], [104, 36, 206, 255], [66, 202, 117, 255], [2, 138, 53, 255]]);
+    }
+
+    #[test]
+    fn etc2_planar_mode() {
+        // Blue overflows: 0 + -4.
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &[0x40, 0x00, 0x04, 0x7F, 0x00, 0x04, 0x1F, 0xFF]);
+        assert_eq!(texels[0..4], [[130, 0, 0, 255], [161, 0, 0, 255], [193, 0, 0, 255], [224, 0, 0, 255]]);
+        assert_eq!(texels[12..16], [[130, 191, 191, 255], [161, 191, 191, 255], [193, 191, 191, 255], [224, 191, 191, 255]]);
+    }
+
+    #[test]
+    fn etc2_rgba_eac_alpha() {
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            &[0x80, 0x10, 0x60, 0x0E, 0, 0, 0, 0, 0x80, 0x00, 0x00, 0x02, 0, 0, 0, 0]);
+        assert_eq!(texels[0], [134, 2, 2, 113]);
+        assert_eq!(texels[1], [134, 2, 2, 142]);
+        assert_eq!(texels[4], [134, 2, 2, 125]);
+    }
+
+    #[test]
+    fn etc2_punch_through_alpha_has_transparent_texels() {
+        // Not opaque, texel 0 has index 2 and the others index 0, which has no modifier.
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, &[0x80, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(texels[0..2], [[0, 0, 0, 0], [132, 0, 0, 255]]);
+        // Opaque blocks are ETC2 RGB blocks.
+        let texels = decompress_block(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, &[0x80, 0x00, 0x00, 0x02, 0, 0, 0, 0]);
+        assert!(texels.iter().all(|texel| *texel == [134, 2, 2, 255]));
+    }
+
+    #[test]
+    fn bc7_mode_6_interpolates_4_bit_indices() {
+        // Endpoints 0 and 127 with P-bits 0 and 1, texel i has index i.
+        let texels = decompress_block(vk::Format::BC7_UNORM_BLOCK,
+            &[0x40, 0xC0, 0x1F, 0xF0, 0x07, 0xFC, 0x01, 0x7F, 0x11, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE]);
+        assert_eq!(texels[0..4], [[0, 0, 0, 0], [16, 16, 16, 16], [36, 36, 36, 36], [52, 52, 52, 52]]);
+        assert_eq!(texels[15], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn bc7_two_subset_partition() {
+        // Mode 7 partition 13 puts the bottom two rows in the second subset. Red and blue endpoints, P-bits are 1.
+        let texels = decompress_block(vk::Format::BC7_UNORM_BLOCK,
+            &[0x80, 0xCD, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x00, 0x00, 0x00]);
+        assert!(texels[0..8].iter().all(|texel| *texel == [255, 4, 4, 255]));
+        assert!(texels[8..16].iter().all(|texel| *texel == [4, 4, 255, 255]));
+    }
+
+    #[test]
+    fn bc7_mode_5_rotation_swaps_red_and_alpha() {
+        let texels = decompress_block(vk::Format::BC7_UNORM_BLOCK,
+            &[0x60, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(texels[0], [64, 255, 255, 255]);
+        assert_eq!(decompress_block(vk::Format::BC7_UNORM_BLOCK, &[0; 16])[0], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc6h_one_region_mode_unquantizes_to_half_floats() {
+        // Mode 11 endpoints 0 and (512, 256, 1023), texel i has index i.
+        let block = [0x03, 0x00, 0x00, 0x00, 0x00, 0x10, 0xA0, 0xFF, 0x11, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE];
+        let texels = decompress_half_block(vk::Format::BC6H_UFLOAT_BLOCK, &block);
+        assert_eq!(texels[0], [0, 0, 0, 0x3C00]);
+        assert_eq!(texels[8], [0x20F8, 0x1080, 0x41DF, 0x3C00]);
+        assert_eq!(texels[15], [0x3E0F, 0x1F0F, 0x7BFF, 0x3C00]);
+    }
+
+    #[test]
+    fn bc6h_signed_endpoints() {
+        // Red of endpoint 1 is -512, the lowest 10 bit value, which becomes the lowest half float.
+        let texels = decompress_half_block(vk::Format::BC6H_SFLOAT_BLOCK,
+            &[0x03, 0x00, 0x00, 0x00, 0x00, 0x10, 0xA0, 0xFF, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(texels[1], [0xFBFF, 0x3E1F, 0x7BFF, 0x3C00]);
+    }
+
+    #[test]
+    fn bc6h_transformed_mode_adds_deltas() {
+        // Mode 12, red of endpoint 1 is 1000 + -8.
+        let texels = decompress_half_block(vk::Format::BC6H_UFLOAT_BLOCK,
+            &[0x07, 0x7D, 0x00, 0x00, 0xC0, 0x0F, 0x00, 0x00, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(texels[0], [0x3C59, 0, 0, 0x3C00]);
+        // Reserved modes are black.
+        assert_eq!(decompress_half_block(vk::Format::BC6H_UFLOAT_BLOCK, &[0x13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])[0],
+            [0, 0, 0, 0x3C00]);
+    }
+
+    #[test]
+    fn astc_rgb_endpoints_with_weight_grid() {
+        // 4x4 grid of 2 bit weights, which are the x of the texel. Direct RGB endpoints, red and blue.
+        let block = [0x42, 0x00, 0xFF, 0x01, 0x00, 0x00, 0x00, 0xFE, 0x01, 0x00, 0x00, 0x00, 0x27, 0x27, 0x27, 0x27];
+        for format in [vk::Format::ASTC_4X4_UNORM_BLOCK, vk::Format::ASTC_4X4_SRGB_BLOCK] {
+            let texels = decompress_block(format, &block);
+            assert_eq!(texels[0..4], [[255, 0, 0, 255], [171, 0, 84, 255], [84, 0, 171, 255], [0, 0, 255, 255]]);
+        }
+    }
+
+    #[test]
+    fn astc_partitions_are_chosen_by_hash() {
+        // Partition pattern 5 of two partitions, black and white luminance endpoints.
+        let texels = decompress_block(vk::Format::ASTC_4X4_UNORM_BLOCK,
+            &[0x42, 0xA8, 0x00, 0x00, 0x00, 0xE0, 0xFF, 0x1F, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let reds: Vec<u8> = texels.iter().map(|texel| texel[0]).collect();
+        assert_eq!(reds, [255, 255, 255, 0, 255, 255, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn astc_void_extent_is_one_color() {
+        let texels = decompress(vk::Format::ASTC_5X5_UNORM_BLOCK, 5, 5,
+            &[0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0x00, 0x80, 0x00, 0x40, 0xFF, 0xFF]).unwrap();
+        assert_eq!(texels.len(), 5 * 5 * 4);
+        assert!(texels.chunks_exact(4).all(|texel| texel == [255, 128, 64, 255]));
+    }
+
+    #[test]
+    fn astc_hdr_and_reserved_blocks_are_magenta() {
+        let hdr_block = [0x42, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(decompress_block(vk::Format::ASTC_4X4_UNORM_BLOCK, &hdr_block)[0], ASTC_ERROR_COLOR);
+        assert_eq!(decompress_block(vk::Format::ASTC_4X4_UNORM_BLOCK, &[0; 16])[0], ASTC_ERROR_COLOR);
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        let texels = decompress(vk::Format::BC1_RGB_UNORM_BLOCK, 2, 1, &[0x00, 0xF8, 0x1F, 0x00, 0xE4, 0, 0, 0]).unwrap();
+        assert_eq!(texels, [255, 0, 0, 255, 0, 0, 255, 255]);
+        assert!(decompress(vk::Format::BC1_RGB_UNORM_BLOCK, 8, 4, &[0; 8]).is_none());
+    }
+
+}
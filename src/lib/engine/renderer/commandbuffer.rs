@@ -1,57 +1,9 @@
 use ash::vk;
 use std::ptr;
 
-pub fn single_time_cmdbuffer_start(device: &ash::Device, queue_family_index: u32) -> (vk::CommandBuffer, vk::CommandPool) {
-    let cmd_pool_ci = vk::CommandPoolCreateInfo {
-        s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
-        p_next: ptr::null(),
-        flags: vk::CommandPoolCreateFlags::TRANSIENT,
-        queue_family_index, // GRAPHICS implicitly supports TRANSFER.
-    };
-    let cmd_pool = unsafe{device.create_command_pool(&cmd_pool_ci, None)}.unwrap();
-    
-    let cmd_buffer_alloc_info = vk::CommandBufferAllocateInfo{
-        s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
-        p_next: ptr::null(),
-        command_pool: cmd_pool,
-        level: vk::CommandBufferLevel::PRIMARY,
-        command_buffer_count: 1,
-    };
-    let cmd_buffer = unsafe{device.allocate_command_buffers(&cmd_buffer_alloc_info)}.unwrap()[0];
-    
-    let cmd_buffer_begin_info = vk::CommandBufferBeginInfo {
-        s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-        p_next: ptr::null(),
-        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
-        p_inheritance_info: ptr::null(),
-    };
-    unsafe{device.begin_command_buffer(cmd_buffer, &cmd_buffer_begin_info).unwrap()};
-    
-    (cmd_buffer, cmd_pool)
-}
-
-pub fn single_time_cmdbuffer_end (device: &ash::Device, submittion_queue: vk::Queue, cmd_buffer: vk::CommandBuffer, cmd_pool: vk::CommandPool) {
-    unsafe{
-    device.end_command_buffer(cmd_buffer).unwrap();
-    }
-    let submit_info = vk::SubmitInfo {
-        s_type: vk::StructureType::SUBMIT_INFO,
-        p_next: ptr::null(),
-        wait_semaphore_count: 0,
-        p_wait_semaphores: ptr::null(),
-        p_wait_dst_stage_mask: ptr::null(),
-        command_buffer_count: 1,
-        p_command_buffers: &cmd_buffer,
-        signal_semaphore_count: 0,
-        p_signal_semaphores: ptr::null(),
-    };
-    unsafe{device.queue_submit(submittion_queue, &[submit_info], vk::Fence::null()).unwrap()};
-    
-    // Wait idle before destroying to let it be done with its job.
-    unsafe{device.queue_wait_idle(submittion_queue).unwrap()};
-    unsafe{device.destroy_command_pool(cmd_pool, None)};
-}
-
+// Note: Arguments are kept as the src/dst pairs of VkImageMemoryBarrier and vkCmdPipelineBarrier, so that call sites
+// line them up in two columns.
+#[allow(clippy::too_many_arguments)]
 pub fn transition_image_layout (device: &ash::Device, cmd_buffer: vk::CommandBuffer, transition_image: vk::Image,
 image_subresource_range: vk::ImageSubresourceRange, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout,
 src_access_mask: vk::AccessFlags, dst_access_mask: vk::AccessFlags, src_stage_mask: vk::PipelineStageFlags,
@@ -78,11 +30,12 @@ dst_stage_mask: vk::PipelineStageFlags) {
     )};
 }
 
-pub fn copy_device_buffer_to_device_image(device: &ash::Device, cmd_buffer: vk::CommandBuffer, src_buffer: vk::Buffer, dst_image: vk::Image,
+/// Copies mip level 0 of a COLOR image in TRANSFER_SRC_OPTIMAL layout into a buffer as tightly packed rows.
+pub fn copy_device_image_to_device_buffer(device: &ash::Device, cmd_buffer: vk::CommandBuffer, src_image: vk::Image, dst_buffer: vk::Buffer,
 extent: &vk::Extent3D) {
     let buffer_image_copy = vk::BufferImageCopy{
         buffer_offset: 0,
-        buffer_row_length: 0,
+        buffer_row_length: 0, // 0 means tightly packed according to image_extent.
         buffer_image_height: 0,
         image_subresource: vk::ImageSubresourceLayers {
             aspect_mask: vk::ImageAspectFlags::COLOR,
@@ -96,6 +49,29 @@ extent: &vk::Extent3D) {
         image_extent: *extent
     };
     unsafe {
-        device.cmd_copy_buffer_to_image(cmd_buffer, src_buffer, dst_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[buffer_image_copy])
+        device.cmd_copy_image_to_buffer(cmd_buffer, src_image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, dst_buffer, &[buffer_image_copy])
     };
-}
\ No newline at end of file
+}
+
+/// Makes transfer writes into the whole buffer visible to host reads that happen after waiting on the submission fence.
+pub fn host_read_buffer_barrier(device: &ash::Device, cmd_buffer: vk::CommandBuffer, buffer: vk::Buffer) {
+    let buffer_memory_barrier = vk::BufferMemoryBarrier {
+        s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+        p_next: ptr::null(),
+        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        dst_access_mask: vk::AccessFlags::HOST_READ,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        buffer,
+        offset: 0,
+        size: vk::WHOLE_SIZE,
+    };
+    unsafe{device.cmd_pipeline_barrier(cmd_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::HOST,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[buffer_memory_barrier],
+        &[]
+    )};
+}
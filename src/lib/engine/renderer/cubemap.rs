@@ -0,0 +1,108 @@
+/// Number of array layers of a cube map, its faces are in +X, -X, +Y, -Y, +Z, -Z order.
+pub const CUBE_FACE_COUNT: u32 = 6;
+
+/// Direction from the cube center through (u, v) of a face, with u and v in [-1, 1] from the left and top of the face as
+/// Vulkan selects cube map texels. Not normalized.
+fn cube_face_direction(face: u32, u: f32, v: f32) -> glam::Vec3 {
+    match face {
+        0 => glam::Vec3::new(1.0, -v, -u),
+        1 => glam::Vec3::new(-1.0, -v, u),
+        2 => glam::Vec3::new(u, 1.0, v),
+        3 => glam::Vec3::new(u, -1.0, -v),
+        4 => glam::Vec3::new(u, -v, 1.0),
+        _ => glam::Vec3::new(-u, -v, -1.0),
+    }
+}
+
+/// Projects a longitude/latitude panorama of linear RGBA texels onto the 6 faces of a cube map, which are returned one after
+/// another as face_size x face_size linear RGBA texels.
+///
+/// Info: The top row of the panorama is +Y, its horizontal center is -Z and longitude grows towards +X. Texels are
+/// bilinearly filtered, wrapping around horizontally.
+pub fn equirectangular_to_cube_faces(width: u32, height: u32, texels: &[f32], face_size: u32) -> Vec<f32> {
+    let (width, height, face_size) = (width as usize, height as usize, face_size as usize);
+    let texel = |x: isize, y: isize| {
+        let x = x.rem_euclid(width as isize) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        let offset = (y * width + x) * 4;
+        glam::Vec4::from_slice(&texels[offset..offset + 4])
+    };
+
+    let mut faces = Vec::with_capacity(CUBE_FACE_COUNT as usize * face_size * face_size * 4);
+    for face in 0..CUBE_FACE_COUNT {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                let direction = cube_face_direction(face, u, v).normalize();
+                let longitude = direction.x.atan2(-direction.z); // [-PI, PI], 0 at -Z.
+                let latitude = direction.y.clamp(-1.0, 1.0).acos(); // [0, PI], 0 at +Y.
+                // Texel centers are at half texel offsets.
+                let src_x = (longitude / std::f32::consts::TAU + 0.5) * width as f32 - 0.5;
+                let src_y = latitude / std::f32::consts::PI * height as f32 - 0.5;
+                let (x0, y0) = (src_x.floor() as isize, src_y.floor() as isize);
+                let (fraction_x, fraction_y) = (src_x - src_x.floor(), src_y - src_y.floor());
+                let top = texel(x0, y0).lerp(texel(x0 + 1, y0), fraction_x);
+                let bottom = texel(x0, y0 + 1).lerp(texel(x0 + 1, y0 + 1), fraction_x);
+                faces.extend_from_slice(&top.lerp(bottom, fraction_y).to_array());
+            }
+        }
+    }
+    faces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Face and (u, v) that Vulkan samples for direction, by the major axis table of the specification.
+    fn vulkan_cube_face(direction: glam::Vec3) -> (u32, f32, f32) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (face, sc, tc, major) = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+            if x > 0.0 { (0, -z, -y, x.abs()) } else { (1, z, -y, x.abs()) }
+        } else if y.abs() >= z.abs() {
+            if y > 0.0 { (2, x, z, y.abs()) } else { (3, x, -z, y.abs()) }
+        } else if z > 0.0 {
+            (4, x, -y, z.abs())
+        } else {
+            (5, -x, -y, z.abs())
+        };
+        (face, sc / major, tc / major)
+    }
+
+    #[test]
+    fn cube_face_direction_matches_vulkan_face_selection() {
+        for face in 0..CUBE_FACE_COUNT {
+            for (u, v) in [(0.0, 0.0), (-0.75, -0.5), (0.5, 0.9), (0.9, -0.25)] {
+                let (sampled_face, sampled_u, sampled_v) = vulkan_cube_face(cube_face_direction(face, u, v));
+                assert_eq!(sampled_face, face);
+                assert!((sampled_u - u).abs() < 1e-6 && (sampled_v - v).abs() < 1e-6, "face {} ({}, {})", face, u, v);
+            }
+        }
+    }
+
+    #[test]
+    fn equirectangular_projection_samples_the_direction_of_each_face_texel() {
+        // Every panorama texel holds the direction through its center.
+        let (width, height, face_size) = (128, 64, 8);
+        let texels: Vec<f32> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).flat_map(|(x, y)| {
+            let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+            let latitude = (y as f32 + 0.5) / height as f32 * std::f32::consts::PI;
+            [latitude.sin() * longitude.sin(), latitude.cos(), -latitude.sin() * longitude.cos(), 1.0]
+        }).collect();
+
+        let faces = equirectangular_to_cube_faces(width, height, &texels, face_size);
+        assert_eq!(faces.len(), (CUBE_FACE_COUNT * face_size * face_size * 4) as usize);
+        let face_size = face_size as usize;
+        for (texel_idx, texel) in faces.chunks_exact(4).enumerate() {
+            let face = (texel_idx / (face_size * face_size)) as u32;
+            let (x, y) = (texel_idx % face_size, texel_idx / face_size % face_size);
+            let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+            let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+            let expected = cube_face_direction(face, u, v).normalize();
+            let sampled = glam::Vec3::from_slice(&texel[0..3]);
+            assert!((sampled - expected).length() < 0.05, "face {} texel ({}, {}): {} != {}", face, x, y, sampled, expected);
+            assert_eq!(texel[3], 1.0);
+        }
+    }
+}
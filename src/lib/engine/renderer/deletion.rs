@@ -0,0 +1,130 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+/// Holds retired resources until every frame that might use them has finished, then drops them, which frees them through
+/// their own Drop. This way resources can be replaced while frames are in flight, without waiting for the device to be idle.
+///
+/// Info: Frames are numbered in submission order. A signaled fence also means that every batch submitted before it on the
+/// same queue has finished, so once the fence of frame N signals every frame up to N has finished.
+pub struct DeletionQueue {
+    /// Number of frames submitted so far, which is also the number of the next submitted frame.
+    submitted_frame_count: u64,
+    /// Every frame with a lower number than this has finished.
+    finished_frame_count: u64,
+    /// Number of the frame that was last submitted with the fence of each frame in flight, None if it has finished.
+    frame_numbers_in_flight: Vec<Option<u64>>,
+    /// Retired resources in retirement order, with the number of frames that must finish before they are dropped.
+    retired_resources: VecDeque<(u64, Box<dyn Any>)>,
+}
+
+impl DeletionQueue {
+    pub fn new(frames_in_flight_count: u32) -> DeletionQueue {
+        DeletionQueue {
+            submitted_frame_count: 0,
+            finished_frame_count: 0,
+            frame_numbers_in_flight: vec![None; frames_in_flight_count as usize],
+            retired_resources: VecDeque::new(),
+        }
+    }
+
+    /// Drops resource once every frame submitted so far has finished, or right away if they all have. resource must not
+    /// be used by frames that are submitted after this.
+    pub fn retire<T: 'static>(&mut self, resource: T) {
+        if self.submitted_frame_count == self.finished_frame_count {
+            return; // Nothing is in flight, so it is dropped right away.
+        }
+        self.retired_resources.push_back((self.submitted_frame_count, Box::new(resource)));
+    }
+
+    /// Must be called after a frame is submitted with the fence of frame_in_flight_idx.
+    pub fn on_frame_submitted(&mut self, frame_in_flight_idx: usize) {
+        self.frame_numbers_in_flight[frame_in_flight_idx] = Some(self.submitted_frame_count);
+        self.submitted_frame_count += 1;
+    }
+
+    /// Must be called once the fence of frame_in_flight_idx has been waited for. Drops the resources that are not used by
+    /// any unfinished frame anymore.
+    pub fn on_frame_finished(&mut self, frame_in_flight_idx: usize) {
+        if let Some(frame_number) = self.frame_numbers_in_flight[frame_in_flight_idx].take() {
+            self.finished_frame_count = u64::max(self.finished_frame_count, frame_number + 1);
+        }
+        // Resources are retired in frame order, so the first one that is still in use ends the search.
+        while self.retired_resources.front().is_some_and(|(frame_count, _)| *frame_count <= self.finished_frame_count) {
+            self.retired_resources.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Sets its flag when dropped.
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    fn drop_flag() -> (DropFlag, Rc<Cell<bool>>) {
+        let is_dropped = Rc::new(Cell::new(false));
+        (DropFlag(is_dropped.clone()), is_dropped)
+    }
+
+    #[test]
+    fn drops_right_away_without_frames_in_flight() {
+        let mut deletion_queue = DeletionQueue::new(2);
+        let (resource, is_dropped) = drop_flag();
+        deletion_queue.retire(resource);
+        assert!(is_dropped.get());
+
+        deletion_queue.on_frame_submitted(0);
+        deletion_queue.on_frame_finished(0);
+        // Waiting for a fence that was never submitted changes nothing.
+        deletion_queue.on_frame_finished(1);
+        let (resource, is_dropped) = drop_flag();
+        deletion_queue.retire(resource);
+        assert!(is_dropped.get());
+    }
+
+    #[test]
+    fn waits_for_every_frame_submitted_before_retiring() {
+        let mut deletion_queue = DeletionQueue::new(2);
+        deletion_queue.on_frame_submitted(0);
+        deletion_queue.on_frame_submitted(1);
+        let (resource, is_dropped) = drop_flag();
+        deletion_queue.retire(resource);
+        // Frames submitted after retiring do not hold it back.
+        deletion_queue.on_frame_finished(0);
+        deletion_queue.on_frame_submitted(0);
+        assert!(!is_dropped.get());
+        deletion_queue.on_frame_finished(1);
+        assert!(is_dropped.get());
+    }
+
+    #[test]
+    fn later_fences_also_finish_earlier_frames() {
+        let mut deletion_queue = DeletionQueue::new(3);
+        deletion_queue.on_frame_submitted(0);
+        let (first_resource, is_first_dropped) = drop_flag();
+        deletion_queue.retire(first_resource);
+        deletion_queue.on_frame_submitted(1);
+        let (second_resource, is_second_dropped) = drop_flag();
+        deletion_queue.retire(second_resource);
+        deletion_queue.on_frame_submitted(2);
+
+        deletion_queue.on_frame_finished(1);
+        assert!(is_first_dropped.get() && is_second_dropped.get());
+        let (resource, is_dropped) = drop_flag();
+        deletion_queue.retire(resource);
+        assert!(!is_dropped.get());
+        deletion_queue.on_frame_finished(0);
+        assert!(!is_dropped.get());
+        deletion_queue.on_frame_finished(2);
+        assert!(is_dropped.get());
+    }
+}
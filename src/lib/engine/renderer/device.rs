@@ -0,0 +1,184 @@
+use ash::vk;
+use std::cell::{RefCell, RefMut};
+use std::ops::Deref;
+use std::rc::Rc;
+use super::allocator;
+
+/// Owns the Vulkan instance, which is destroyed once every surface and device created from it is dropped.
+pub struct Instance {
+    entry: ash::Entry, // Keeps the Vulkan loader alive for as long as the instance lives.
+    raw: ash::Instance,
+}
+
+impl Instance {
+    pub fn new(entry: ash::Entry, raw: ash::Instance) -> Rc<Instance> {
+        Rc::new(Instance {
+            entry,
+            raw,
+        })
+    }
+
+    pub fn entry(&self) -> &ash::Entry {
+        &self.entry
+    }
+}
+
+impl Deref for Instance {
+    type Target = ash::Instance;
+
+    fn deref(&self) -> &ash::Instance {
+        &self.raw
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            self.raw.destroy_instance(None);
+        }
+    }
+}
+
+/// Owns the logical device and the allocator that every buffer and image memory is sub-allocated from.
+///
+/// Info: Every resource created from the device holds an Rc<Device>, so the device is destroyed after all of them; it
+/// can neither be destroyed too early nor leak them.
+pub struct Device {
+    raw: ash::Device,
+    physical_device: vk::PhysicalDevice,
+    /// None if the sampler anisotropy feature is not enabled.
+    max_sampler_anisotropy: Option<f32>,
+    /// True if the image cube array feature is enabled.
+    has_image_cube_array: bool,
+    allocator: RefCell<allocator::Allocator>,
+    instance: Rc<Instance>,
+}
+
+impl Device {
+    pub fn new(instance: Rc<Instance>, physical_device: vk::PhysicalDevice, raw: ash::Device, max_sampler_anisotropy: Option<f32>,
+    has_image_cube_array: bool, allocator: allocator::Allocator) -> Rc<Device> {
+        Rc::new(Device {
+            raw,
+            physical_device,
+            max_sampler_anisotropy,
+            has_image_cube_array,
+            allocator: RefCell::new(allocator),
+            instance,
+        })
+    }
+
+    pub fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    pub fn physical_device(&self) -> vk::PhysicalDevice {
+        self.physical_device
+    }
+
+    pub fn max_sampler_anisotropy(&self) -> Option<f32> {
+        self.max_sampler_anisotropy
+    }
+
+    pub fn has_image_cube_array(&self) -> bool {
+        self.has_image_cube_array
+    }
+
+    /// Panics if the allocator is already borrowed, so the returned borrow must not be held while creating or dropping
+    /// buffers and images.
+    pub fn allocator(&self) -> RefMut<'_, allocator::Allocator> {
+        self.allocator.borrow_mut()
+    }
+}
+
+impl Deref for Device {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &ash::Device {
+        &self.raw
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.allocator.get_mut().destroy(&self.raw);
+        unsafe {
+            self.raw.destroy_device(None);
+        }
+    }
+}
+
+/// Vulkan handles that are created from a device and destroyed with a single call, see Owned.
+pub trait DeviceHandle: Copy {
+    /// # Safety
+    /// Handle must be created from device and must not be in use by the device anymore.
+    unsafe fn destroy(self, device: &ash::Device);
+}
+
+macro_rules! impl_device_handle {
+    ($($handle:ty => $destroy_fn:ident),* $(,)?) => {
+        $(
+            impl DeviceHandle for $handle {
+                unsafe fn destroy(self, device: &ash::Device) {
+                    device.$destroy_fn(self, None);
+                }
+            }
+        )*
+    };
+}
+
+impl_device_handle!(
+    vk::ShaderModule => destroy_shader_module,
+    vk::RenderPass => destroy_render_pass,
+    vk::Framebuffer => destroy_framebuffer,
+    vk::PipelineLayout => destroy_pipeline_layout,
+    vk::Pipeline => destroy_pipeline,
+    vk::DescriptorSetLayout => destroy_descriptor_set_layout,
+    vk::DescriptorPool => destroy_descriptor_pool,
+    vk::Sampler => destroy_sampler,
+    vk::CommandPool => destroy_command_pool,
+    vk::Semaphore => destroy_semaphore,
+    vk::Fence => destroy_fence,
+);
+
+/// Destroys its handle when dropped, and keeps the device alive until then. Caller must make sure that the device is not
+/// using the handle anymore when it is dropped, e.g. by waiting for the fences of the submissions that use it.
+pub struct Owned<T: DeviceHandle> {
+    device: Rc<Device>,
+    raw: T,
+}
+
+impl<T: DeviceHandle> Owned<T> {
+    /// raw must be created from device.
+    pub fn new(device: &Rc<Device>, raw: T) -> Owned<T> {
+        Owned {
+            device: device.clone(),
+            raw,
+        }
+    }
+
+    pub fn raw(&self) -> T {
+        self.raw
+    }
+}
+
+impl<T: DeviceHandle> Drop for Owned<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.raw.destroy(&self.device);
+        }
+    }
+}
+
+pub type ShaderModule = Owned<vk::ShaderModule>;
+pub type RenderPass = Owned<vk::RenderPass>;
+pub type Framebuffer = Owned<vk::Framebuffer>;
+pub type PipelineLayout = Owned<vk::PipelineLayout>;
+pub type Pipeline = Owned<vk::Pipeline>;
+pub type DescriptorSetLayout = Owned<vk::DescriptorSetLayout>;
+/// Descriptor sets allocated from the pool are freed with it.
+pub type DescriptorPool = Owned<vk::DescriptorPool>;
+pub type Sampler = Owned<vk::Sampler>;
+/// Command buffers allocated from the pool are freed with it.
+pub type CommandPool = Owned<vk::CommandPool>;
+pub type Semaphore = Owned<vk::Semaphore>;
+pub type Fence = Owned<vk::Fence>;
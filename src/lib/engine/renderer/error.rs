@@ -0,0 +1,127 @@
+use ash::vk;
+extern crate image as img;
+
+/// Everything that can go wrong while creating or running the renderer.
+#[derive(Debug)]
+pub enum RendererError {
+    /// Vulkan loader library could not be found or loaded.
+    Loading(ash::LoadingError),
+    MissingInstanceLayer(String),
+    MissingInstanceExtension(String),
+    MissingDeviceExtension(String),
+    /// The window system winit runs on has no Vulkan surface support in this renderer.
+    UnsupportedWindowSystem(String),
+    /// No physical device passed the checks in queries::get_physical_device_score().
+    NoSuitablePhysicalDevice,
+    /// No queue family has the required queue flags, presentation support included.
+    MissingQueueFamily(&'static str),
+    /// None of the memory types allowed by memory_type_bits have all of the required property flags.
+    NoSuitableMemoryType { memory_type_bits: u32, required_flags: vk::MemoryPropertyFlags },
+    UnsupportedSwapchainImageCount { requested: u32, min: u32, max: u32 },
+    /// None of the surface formats has the sRGB color space, which is the only SDR color space the shaders encode for.
+    UnsupportedSurfaceFormats(Vec<vk::SurfaceFormatKHR>),
+    ShaderFile { path: String, error: std::io::Error },
+    ModelFile { path: String, error: std::io::Error },
+    /// Model file could not be parsed, line is 1 based.
+    InvalidModelFile { path: String, line: usize, reason: String },
+    TextureFile { path: String, error: img::ImageError },
+    KtxFile { path: String, error: ktx2::ParseError },
+    DdsFile { path: String, error: ddsfile::Error },
+    /// Texture file could not be read, or its contents do not match its header.
+    InvalidTextureFile { path: String, reason: String },
+    /// Texture file is valid but uses a feature or format that cannot be loaded.
+    UnsupportedTextureFile { path: String, reason: String },
+    /// The operation needs the other kind of render target, e.g. render_offscreen() on a renderer with a window.
+    WrongRenderTarget(&'static str),
+    ScreenshotFile { path: String, error: img::ImageError },
+    /// Swapchain images of the surface do not support TRANSFER_SRC, so they can not be copied into screenshots.
+    UncopyableSwapchainImages,
+    /// Screenshots can only be saved from 8 bits per channel RGBA and BGRA images.
+    UnsupportedScreenshotFormat(vk::Format),
+    /// The model, or the material of the model called material_name, has no base color texture for the fragment shader to
+    /// sample.
+    MissingModelTexture { material_name: Option<String> },
+    /// VK_ERROR_OUT_OF_HOST_MEMORY or VK_ERROR_OUT_OF_DEVICE_MEMORY.
+    OutOfMemory(vk::Result),
+    DeviceLost,
+    SurfaceLost,
+    /// Swapchain does not match the surface anymore and has to be recreated.
+    SwapchainOutOfDate,
+    /// Any other VkResult failure.
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for RendererError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => RendererError::OutOfMemory(result),
+            vk::Result::ERROR_DEVICE_LOST => RendererError::DeviceLost,
+            vk::Result::ERROR_SURFACE_LOST_KHR => RendererError::SurfaceLost,
+            vk::Result::ERROR_OUT_OF_DATE_KHR => RendererError::SwapchainOutOfDate,
+            _ => RendererError::Vulkan(result),
+        }
+    }
+}
+
+impl From<ash::LoadingError> for RendererError {
+    fn from(error: ash::LoadingError) -> Self {
+        RendererError::Loading(error)
+    }
+}
+
+impl std::fmt::Display for RendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendererError::Loading(error) => write!(f, "Could not load the Vulkan library: {}", error),
+            RendererError::MissingInstanceLayer(name) => write!(f, "The required instance layer: '{}' is not found!", name),
+            RendererError::MissingInstanceExtension(name) => write!(f, "The required instance extension: '{}' is not found!", name),
+            RendererError::MissingDeviceExtension(name) => write!(f, "The required device extension: '{}' is not found!", name),
+            RendererError::UnsupportedWindowSystem(handles) => write!(f, "Unsupported window system! {}", handles),
+            RendererError::NoSuitablePhysicalDevice => write!(f, "Could not find any suitable physical device!"),
+            RendererError::MissingQueueFamily(queue_family) => write!(f, "Could not find a {} queue family!", queue_family),
+            RendererError::NoSuitableMemoryType { memory_type_bits, required_flags } =>
+                write!(f, "Could not find a memory type with {:?} in memory type bits: {:b}", required_flags, memory_type_bits),
+            RendererError::UnsupportedSwapchainImageCount { requested, min, max } =>
+                write!(f, "The requested swapchain min image count: '{}' is out of supported range: [{}, {}]", requested, min, max),
+            RendererError::UnsupportedSurfaceFormats(surface_formats) =>
+                write!(f, "None of the supported surface formats has the sRGB color space: {:?}", surface_formats),
+            RendererError::ShaderFile { path, error } => write!(f, "Could not read shader file: '{}', error: {}", path, error),
+            RendererError::ModelFile { path, error } => write!(f, "Could not read model file: '{}', error: {}", path, error),
+            RendererError::InvalidModelFile { path, line, reason } => write!(f, "Invalid model file: '{}', line {}: {}", path, line, reason),
+            RendererError::TextureFile { path, error } => write!(f, "Could not load texture file: '{}', error: {}", path, error),
+            RendererError::KtxFile { path, error } => write!(f, "Could not parse KTX2 file: '{}', error: {}", path, error),
+            RendererError::DdsFile { path, error } => write!(f, "Could not parse DDS file: '{}', error: {}", path, error),
+            RendererError::InvalidTextureFile { path, reason } => write!(f, "Invalid texture file: '{}', {}", path, reason),
+            RendererError::UnsupportedTextureFile { path, reason } => write!(f, "Unsupported texture file: '{}', {}", path, reason),
+            RendererError::WrongRenderTarget(reason) => write!(f, "Wrong render target: {}", reason),
+            RendererError::ScreenshotFile { path, error } => write!(f, "Could not save screenshot to: '{}', error: {}", path, error),
+            RendererError::UncopyableSwapchainImages =>
+                write!(f, "Could not capture screenshot, swapchain images of this surface can not be copied from!"),
+            RendererError::UnsupportedScreenshotFormat(format) =>
+                write!(f, "Could not capture screenshot, format: {:?} can not be saved as PNG!", format),
+            RendererError::MissingModelTexture { material_name: None } => write!(f, "The model has no base color texture!"),
+            RendererError::MissingModelTexture { material_name: Some(material_name) } =>
+                write!(f, "Material: '{}' of the model has no base color texture!", material_name),
+            RendererError::OutOfMemory(result) => write!(f, "Out of memory: {}", result),
+            RendererError::DeviceLost => write!(f, "The logical device has been lost!"),
+            RendererError::SurfaceLost => write!(f, "The window surface has been lost!"),
+            RendererError::SwapchainOutOfDate => write!(f, "The swapchain is out of date!"),
+            RendererError::Vulkan(result) => write!(f, "Vulkan call failed: {}", result),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::Loading(error) => Some(error),
+            RendererError::ShaderFile { error, .. } => Some(error),
+            RendererError::ModelFile { error, .. } => Some(error),
+            RendererError::TextureFile { error, .. } => Some(error),
+            RendererError::KtxFile { error, .. } => Some(error),
+            RendererError::DdsFile { error, .. } => Some(error),
+            RendererError::ScreenshotFile { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
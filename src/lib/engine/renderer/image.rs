@@ -1,29 +1,37 @@
 use ash::vk;
 use std::ptr;
+use std::rc::Rc;
+use super::{allocator, device};
+use super::error::RendererError;
 
+/// Destroys the raw image and frees its allocation when dropped.
 pub struct Image {
+    device: Rc<device::Device>,
     pub raw: vk::Image,
-    pub device_memory: vk::DeviceMemory,
+    pub allocation: allocator::Allocation,
 }
 
 impl Image {
-    /// Also binds image to device memory.
-    pub fn new(device: &ash::Device, instance: &ash::Instance, physical_device: vk::PhysicalDevice, width: u32, height: u32, 
-    mip_levels: u32, sample_count: vk::SampleCountFlags, format: vk::Format, tiling: vk::ImageTiling, usage: vk::ImageUsageFlags, 
-    mem_props: vk::MemoryPropertyFlags) -> Image {
+    /// Also binds image to device memory. Cube maps need CUBE_COMPATIBLE flags and 6 array layers per cube, in +X, -X, +Y, -Y,
+    /// +Z, -Z face order.
+    // Note: Arguments mirror the fields of VkImageCreateInfo that differ between the images of the renderer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &Rc<device::Device>, allocation_usage: allocator::AllocationUsage, flags: vk::ImageCreateFlags, width: u32,
+    height: u32, mip_levels: u32, array_layers: u32, sample_count: vk::SampleCountFlags, format: vk::Format, tiling: vk::ImageTiling, usage: vk::ImageUsageFlags,
+    mem_props: vk::MemoryPropertyFlags) -> Result<Image, RendererError> {
         let image_ci = vk::ImageCreateInfo {
             s_type: vk::StructureType::IMAGE_CREATE_INFO,
             p_next: ptr::null(),
-            flags: vk::ImageCreateFlags::empty(),
+            flags,
             image_type: vk::ImageType::TYPE_2D,
             format,
             extent: vk::Extent3D{
-                width: width,
-                height: height,
+                width,
+                height,
                 depth: 1
             },
             mip_levels,
-            array_layers: 1,
+            array_layers,
             samples: sample_count,
             tiling, 
             usage,
@@ -32,47 +40,44 @@ impl Image {
             p_queue_family_indices: ptr::null(), // Ignored if image sharing is not CONCURRENT.
             initial_layout: vk::ImageLayout::UNDEFINED,
         };
-        let image = unsafe{device.create_image(&image_ci, None).unwrap()};
+        let image = unsafe{device.create_image(&image_ci, None)}?;
         
-        let physical_device_memory_properties = 
-            unsafe{instance.get_physical_device_memory_properties(physical_device)};
-        let required_memory_flags = mem_props;
-        let mut memory_type_idx = 0;
-        let image_memory_requirements = unsafe{device.get_image_memory_requirements(image)};
-        // println!("Image supported memory type bits: {:b}", image_memory_requirements.memory_type_bits);
-        for (idx, physical_device_memory_type) in physical_device_memory_properties.memory_types.iter().enumerate() {
-            if physical_device_memory_type.property_flags.contains(required_memory_flags) &&
-            ((1 << idx) & image_memory_requirements.memory_type_bits) == (1 << idx) {
-                memory_type_idx = idx;
-                break;
+        let image_memory_requirements = allocator::ResourceMemoryRequirements::query(device, allocator::MemoryResource::Image(image));
+        // println!("Image supported memory type bits: {:b}", image_memory_requirements.raw.memory_type_bits);
+        let resource_tiling = if tiling == vk::ImageTiling::LINEAR {
+            allocator::ResourceTiling::Linear
+        } else {
+            allocator::ResourceTiling::Optimal
+        };
+        let allocation = match device.allocator().allocate(device, &image_memory_requirements, mem_props, resource_tiling, allocation_usage) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                unsafe{device.destroy_image(image, None)};
+                return Err(error);
             }
-        }
-        // println!("Texture Image Allocation: found memory_type_idx: {}", memory_type_idx);
-        
-
-        let image_mem_alloc_info = vk::MemoryAllocateInfo {
-            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-            p_next: ptr::null(),
-            allocation_size: image_memory_requirements.size,
-            memory_type_index: memory_type_idx as u32,
         };
-        let image_device_memory = unsafe{device.allocate_memory(&image_mem_alloc_info, None)}.unwrap();
     
-        unsafe{device.bind_image_memory(image, image_device_memory, 0)}.unwrap();
-
-        Image {
+        let image = Image {
+            device: device.clone(),
             raw: image,
-            device_memory: image_device_memory
-        }
+            allocation,
+        };
+        unsafe{device.bind_image_memory(image.raw, image.allocation.device_memory, image.allocation.offset)}?;
+
+        Ok(image)
     }
-    pub fn create_image_view(&self, device: &ash::Device, surface_format: vk::Format, mip_levels: u32, aspect_mask: vk::ImageAspectFlags)
-    -> vk::ImageView {
+
+    /// The view keeps this image alive, so it is always destroyed before the image. Views layer_count layers from
+    /// base_array_layer, e.g. a single layer of an array as TYPE_2D for rendering into it, or all of them as an array or
+    /// cube view_type for sampling. CUBE views need 6 layers and CUBE_ARRAY views a multiple of 6.
+    pub fn create_image_view(self: &Rc<Image>, surface_format: vk::Format, view_type: vk::ImageViewType, mip_levels: u32,
+    base_array_layer: u32, layer_count: u32, aspect_mask: vk::ImageAspectFlags) -> Result<ImageView, RendererError> {
         let image_view_ci = vk::ImageViewCreateInfo {
             s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::ImageViewCreateFlags::empty(),
             image: self.raw,
-            view_type: vk::ImageViewType::TYPE_2D,
+            view_type,
             format: surface_format,
             components: vk::ComponentMapping {
                 r: vk::ComponentSwizzle::IDENTITY,
@@ -84,21 +89,52 @@ impl Image {
                 aspect_mask,
                 base_mip_level: 0,
                 level_count: mip_levels,
-                base_array_layer: 0,
-                layer_count: 1,
+                base_array_layer,
+                layer_count,
             }
         };
         
+        ImageView::new(&self.device, &image_view_ci, Some(self.clone()))
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
         unsafe {
-            device.create_image_view(&image_view_ci, None).unwrap()
+            self.device.destroy_image(self.raw, None);
         }
+        self.device.allocator().free(&self.device, &self.allocation);
+    }
+}
+
+/// Destroys the raw image view when dropped.
+pub struct ImageView {
+    device: Rc<device::Device>,
+    pub raw: vk::ImageView,
+    /// Dropped after the view. None if the viewed image is not owned by an Image.
+    _image: Option<Rc<Image>>,
+}
+
+impl ImageView {
+    /// image is kept alive until the view is dropped. Views of images that are not owned by an Image, like swapchain
+    /// images, pass None, then the caller must drop the view before the image is destroyed.
+    pub fn new(device: &Rc<device::Device>, image_view_ci: &vk::ImageViewCreateInfo, image: Option<Rc<Image>>)
+    -> Result<ImageView, RendererError> {
+        let image_view = unsafe {
+            device.create_image_view(image_view_ci, None)
+        }?;
+        Ok(ImageView {
+            device: device.clone(),
+            raw: image_view,
+            _image: image,
+        })
     }
+}
 
-    /// Destroys raw image and frees device memory.
-    pub fn destroy(&self, device: &ash::Device) {
+impl Drop for ImageView {
+    fn drop(&mut self) {
         unsafe {
-            device.destroy_image(self.raw, None);
-            device.free_memory(self.device_memory, None);
+            self.device.destroy_image_view(self.raw, None);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,221 @@
+/// Filter that the mip levels of textures are downsampled with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipmapFilter {
+    /// Averages the texels that each texel of the next level covers. Uses linear blits on the device if the texture format
+    /// supports them, otherwise downsamples on the CPU.
+    #[default]
+    Box,
+    /// Kaiser windowed sinc, which keeps smaller levels sharper than Box. Always downsamples on the CPU.
+    Kaiser,
+}
+
+/// Number of mip levels down to 1x1. Each level is half the size of the previous one, rounded down, but never smaller than 1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - u32::max(u32::max(width, height), 1).leading_zeros()
+}
+
+/// Size of a mip level, see mip_level_count().
+pub fn mip_level_extent(width: u32, height: u32, mip_level: u32) -> (u32, u32) {
+    (u32::max(width >> mip_level, 1), u32::max(height >> mip_level, 1))
+}
+
+/// Converts 8 bits per channel RGBA texels to linear floats. The color channels of sRGB texels are decoded, alpha is always
+/// linear.
+pub fn decode_rgba8(texels: &[u8], is_srgb: bool) -> Vec<f32> {
+    let srgb_to_linear: Vec<f32> = (0..=255u8).map(|value| {
+        let value = value as f32 / 255.0;
+        if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    }).collect();
+    texels.iter().enumerate().map(|(idx, value)| {
+        if is_srgb && idx % 4 < 3 { srgb_to_linear[*value as usize] } else { *value as f32 / 255.0 }
+    }).collect()
+}
+
+/// Inverse of decode_rgba8(), values are clamped to [0, 1].
+pub fn encode_rgba8(values: &[f32], is_srgb: bool) -> Vec<u8> {
+    values.iter().enumerate().map(|(idx, value)| {
+        let value = value.clamp(0.0, 1.0);
+        let value = if is_srgb && idx % 4 < 3 {
+            if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+        } else {
+            value
+        };
+        (value * 255.0 + 0.5) as u8
+    }).collect()
+}
+
+/// Downsamples tightly packed 8 bits per channel RGBA texels into every other mip level, level 1 first. Filtering happens
+/// on linear values, so the color channels of sRGB texels are decoded first and encoded back after.
+pub fn generate_rgba8_mip_levels(width: u32, height: u32, texels: &[u8], is_srgb: bool, filter: MipmapFilter) -> Vec<Vec<u8>> {
+    generate_rgba32f_mip_levels(width, height, &decode_rgba8(texels, is_srgb), filter).iter()
+        .map(|level| encode_rgba8(level, is_srgb))
+        .collect()
+}
+
+/// Downsamples tightly packed linear float RGBA texels into every other mip level, level 1 first.
+pub fn generate_rgba32f_mip_levels(width: u32, height: u32, texels: &[f32], filter: MipmapFilter) -> Vec<Vec<f32>> {
+    // Every level is downsampled from the previous one, which keeps the work per level proportional to its size.
+    let mut levels: Vec<Vec<f32>> = Vec::new();
+    for mip_level in 1..mip_level_count(width, height) {
+        let previous_level = levels.last().map_or(texels, Vec::as_slice);
+        let (src_width, src_height) = mip_level_extent(width, height, mip_level - 1);
+        let (dst_width, dst_height) = mip_level_extent(width, height, mip_level);
+        // Separable, so rows are filtered first and then the columns of the result.
+        let horizontal = resample_axis(previous_level, src_width, src_height, dst_width, true, filter);
+        levels.push(resample_axis(&horizontal, dst_width, src_height, dst_height, false, filter));
+    }
+    levels
+}
+
+/// Resamples RGBA texels along one axis, from src_size to dst_size texels.
+fn resample_axis(texels: &[f32], width: u32, height: u32, dst_size: u32, is_horizontal: bool, filter: MipmapFilter) -> Vec<f32> {
+    let (width, height, dst_size) = (width as usize, height as usize, dst_size as usize);
+    let (src_size, line_count) = if is_horizontal { (width, height) } else { (height, width) };
+    let weights = filter_weights(src_size, dst_size, filter);
+    let (dst_width, dst_height) = if is_horizontal { (dst_size, height) } else { (width, dst_size) };
+
+    let mut resampled = vec![0.0f32; dst_width * dst_height * 4];
+    for line in 0..line_count {
+        for (dst_idx, dst_weights) in weights.iter().enumerate() {
+            let mut sum = [0.0f32; 4];
+            for (src_idx, weight) in dst_weights {
+                let (x, y) = if is_horizontal { (*src_idx, line) } else { (line, *src_idx) };
+                let texel_offset = (y * width + x) * 4;
+                for channel in 0..4 {
+                    sum[channel] += texels[texel_offset + channel] * weight;
+                }
+            }
+            let (x, y) = if is_horizontal { (dst_idx, line) } else { (line, dst_idx) };
+            let texel_offset = (y * dst_width + x) * 4;
+            resampled[texel_offset..texel_offset + 4].copy_from_slice(&sum);
+        }
+    }
+    resampled
+}
+
+/// Source texel indices and normalized weights of every destination texel.
+///
+/// Info: Filters are evaluated in destination texel units, so a filter covers the same part of the image on every level,
+/// also when a level is not exactly half of the previous one. Taps outside of the image are clamped to its edge.
+fn filter_weights(src_size: usize, dst_size: usize, filter: MipmapFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_size as f32 / dst_size as f32;
+    let radius = match filter {
+        MipmapFilter::Box => 0.5,
+        MipmapFilter::Kaiser => KAISER_WIDTH,
+    };
+    (0..dst_size).map(|dst_idx| {
+        let center = (dst_idx as f32 + 0.5) * scale;
+        let first = (center - radius * scale).floor() as isize;
+        let last = (center + radius * scale).ceil() as isize;
+        let mut weights: Vec<(usize, f32)> = Vec::new();
+        for src_idx in first..last {
+            let weight = match filter {
+                // Part of the source texel that is covered by the destination texel.
+                MipmapFilter::Box => {
+                    let start = f32::max(src_idx as f32, center - 0.5 * scale);
+                    let end = f32::min(src_idx as f32 + 1.0, center + 0.5 * scale);
+                    f32::max(end - start, 0.0)
+                },
+                MipmapFilter::Kaiser => kaiser((src_idx as f32 + 0.5 - center) / scale),
+            };
+            if weight == 0.0 {
+                continue;
+            }
+            let src_idx = src_idx.clamp(0, src_size as isize - 1) as usize;
+            match weights.iter_mut().find(|(idx, _)| *idx == src_idx) {
+                Some((_, existing_weight)) => *existing_weight += weight,
+                None => weights.push((src_idx, weight)),
+            }
+        }
+        let weight_sum: f32 = weights.iter().map(|(_, weight)| weight).sum();
+        weights.iter_mut().for_each(|(_, weight)| *weight /= weight_sum);
+        weights
+    }).collect()
+}
+
+const KAISER_WIDTH: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+/// Kaiser windowed sinc at x destination texels from the center.
+fn kaiser(x: f32) -> f32 {
+    let t = x / KAISER_WIDTH;
+    if t.abs() >= 1.0 {
+        return 0.0;
+    }
+    let sinc = if x == 0.0 { 1.0 } else { (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x) };
+    sinc * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+/// Zeroth order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0f32;
+    let mut term = 1.0f32;
+    let quarter_x_squared = x * x / 4.0;
+    for k in 1..32 {
+        term *= quarter_x_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_level_count_goes_down_to_1x1() {
+        assert_eq!(mip_level_count(0, 0), 1);
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 17), 9);
+        assert_eq!(mip_level_count(1, 1024), 11);
+    }
+
+    #[test]
+    fn npot_extents_round_down_and_stop_at_1() {
+        assert_eq!(mip_level_extent(5, 3, 0), (5, 3));
+        assert_eq!(mip_level_extent(5, 3, 1), (2, 1));
+        assert_eq!(mip_level_extent(5, 3, 2), (1, 1));
+        assert_eq!(mip_level_extent(300, 17, 5), (9, 1));
+
+        let levels = generate_rgba32f_mip_levels(5, 3, &[0.5; 5 * 3 * 4], MipmapFilter::Box);
+        let level_sizes: Vec<usize> = levels.iter().map(Vec::len).collect();
+        assert_eq!(level_sizes, [2 * 4, 4]);
+    }
+
+    #[test]
+    fn box_weights_cover_npot_source_texels() {
+        assert_eq!(filter_weights(2, 1, MipmapFilter::Box), [vec![(0, 0.5), (1, 0.5)]]);
+        let weights = filter_weights(5, 2, MipmapFilter::Box);
+        assert_eq!(weights[0], [(0, 0.4), (1, 0.4), (2, 0.2)]);
+        assert_eq!(weights[1], [(2, 0.2), (3, 0.4), (4, 0.4)]);
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        for filter in [MipmapFilter::Box, MipmapFilter::Kaiser] {
+            for (src_size, dst_size) in [(2, 1), (3, 1), (4, 2), (5, 2), (7, 3), (256, 128)] {
+                for dst_weights in filter_weights(src_size, dst_size, filter) {
+                    let weight_sum: f32 = dst_weights.iter().map(|(_, weight)| weight).sum();
+                    assert!((weight_sum - 1.0).abs() < 1e-5, "{:?} {} -> {}: {}", filter, src_size, dst_size, weight_sum);
+                    assert!(dst_weights.iter().all(|(src_idx, _)| *src_idx < src_size));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kaiser_keeps_constant_images_constant() {
+        let levels = generate_rgba32f_mip_levels(7, 5, &[0.25; 7 * 5 * 4], MipmapFilter::Kaiser);
+        assert!(levels.iter().flatten().all(|value| (value - 0.25).abs() < 1e-5));
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        let texels: Vec<u8> = (0..=255u8).collect();
+        assert_eq!(encode_rgba8(&decode_rgba8(&texels, true), true), texels);
+    }
+}
@@ -0,0 +1,94 @@
+use ash::vk;
+use std::rc::Rc;
+use super::{allocator, buffer, commandbuffer, device, image};
+use super::error::RendererError;
+
+/// Color images that the render pass resolves into when there is no window to present to.
+/// After every frame, the resolved image is copied into a host visible readback buffer.
+pub struct Offscreen {
+    images: Vec<Rc<image::Image>>,
+    pub image_views: Vec<image::ImageView>,
+    pub image_count: u32,
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+
+    readback_buffer: buffer::Buffer,
+}
+
+impl Offscreen {
+    /// Pixels are read back as tightly packed rows, so format should be a 4 bytes per pixel color format.
+    pub fn new(device: &Rc<device::Device>, format: vk::Format, width: u32, height: u32,
+    image_count: u32, queue_family_idx: u32) -> Result<Offscreen, RendererError> {
+        let mut images = Vec::with_capacity(image_count as usize);
+        let mut image_views = Vec::with_capacity(image_count as usize);
+        for _ in 0..image_count {
+            // TRANSFER_SRC is needed to copy the resolved image into the readback buffer.
+            let image = Rc::new(image::Image::new(device, allocator::AllocationUsage::RenderTarget, vk::ImageCreateFlags::empty(),
+                width, height, 1, 1, vk::SampleCountFlags::TYPE_1, format, vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::DEVICE_LOCAL)?);
+            let image_view = image.create_image_view(format, vk::ImageViewType::TYPE_2D, 1, 0, 1, vk::ImageAspectFlags::COLOR)?;
+            images.push(image);
+            image_views.push(image_view);
+        }
+
+        let readback_buffer_size = Offscreen::get_readback_buffer_size(width, height);
+        // Stays mapped until it is dropped, since it is read after every frame.
+        let readback_buffer = buffer::Buffer::new(device, allocator::AllocationUsage::Readback, readback_buffer_size, vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, &queue_family_idx)?;
+
+        Ok(Offscreen {
+            images,
+            image_views,
+            image_count,
+            format,
+            width,
+            height,
+            readback_buffer,
+        })
+    }
+
+    #[inline(always)]
+    fn get_readback_buffer_size(width: u32, height: u32) -> vk::DeviceSize {
+        width as vk::DeviceSize * height as vk::DeviceSize * 4
+    }
+
+    /// Records the copy of the resolved image at image_idx into the readback buffer. Must be recorded after the render pass
+    /// that left the image in TRANSFER_SRC_OPTIMAL layout.
+    pub fn record_copy_to_readback_buffer(&self, device: &ash::Device, cmd_buffer: vk::CommandBuffer, image_idx: usize) {
+        let image_subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        // The render pass already transitioned the layout; this only makes the resolve writes visible to the copy.
+        commandbuffer::transition_image_layout(device, cmd_buffer, self.images[image_idx].raw, image_subresource_range,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,              vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,      vk::PipelineStageFlags::TRANSFER);
+
+        let extent = vk::Extent3D {
+            width: self.width,
+            height: self.height,
+            depth: 1
+        };
+        commandbuffer::copy_device_image_to_device_buffer(device, cmd_buffer, self.images[image_idx].raw, self.readback_buffer.raw,
+            &extent);
+
+        // Host reads the buffer after waiting on the submission fence, so the transfer writes must be made visible to HOST.
+        commandbuffer::host_read_buffer_barrier(device, cmd_buffer, self.readback_buffer.raw);
+    }
+
+    /// Returns the last copied image as tightly packed rows. Caller must make sure the copy has finished on the device.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let readback_buffer_size = Offscreen::get_readback_buffer_size(self.width, self.height) as usize;
+        let readback_mapped_memory_ptr = self.readback_buffer.mapped_ptr().expect("Readback buffer must be HOST_VISIBLE!");
+        let mut pixels = vec![0u8; readback_buffer_size];
+        unsafe {
+            std::ptr::copy_nonoverlapping(readback_mapped_memory_ptr, pixels.as_mut_ptr(), readback_buffer_size);
+        }
+        pixels
+    }
+}
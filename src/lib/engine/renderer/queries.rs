@@ -1,13 +1,185 @@
 use ash::{vk, extensions};
+use raw_window_handle::RawDisplayHandle;
 use std::ffi::{CStr, CString};
 use super::surface;
+use super::error::RendererError;
 
-pub fn get_physical_device(instance: &ash::Instance) -> vk::PhysicalDevice {
-    let physical_devices = unsafe{instance.enumerate_physical_devices()}.unwrap();
+/// Name of the environment variable that overrides the physical device selection. Its value is parsed as an index into
+/// enumerated physical devices if it is a number, otherwise as a part of the device name.
+pub const PHYSICAL_DEVICE_OVERRIDE_ENV_VAR: &str = "HANOKEI_GPU";
+
+/// Picks a physical device instead of the highest scored one.
+#[derive(Clone, Debug)]
+pub enum PhysicalDeviceOverride {
+    /// Index into the physical devices in the order vkEnumeratePhysicalDevices returns them.
+    Index(usize),
+    /// Case insensitive part of the device name, like "nvidia" or "llvmpipe".
+    Name(String),
+}
+
+impl PhysicalDeviceOverride {
+    /// Returns the override set in PHYSICAL_DEVICE_OVERRIDE_ENV_VAR, if there is any.
+    pub fn from_env() -> Option<PhysicalDeviceOverride> {
+        let value = std::env::var(PHYSICAL_DEVICE_OVERRIDE_ENV_VAR).ok()?;
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        match value.parse::<usize>() {
+            Ok(index) => Some(PhysicalDeviceOverride::Index(index)),
+            Err(_) => Some(PhysicalDeviceOverride::Name(value.to_owned())),
+        }
+    }
+
+    fn matches(&self, idx: usize, device_name: &str) -> bool {
+        match self {
+            PhysicalDeviceOverride::Index(index) => *index == idx,
+            PhysicalDeviceOverride::Name(name) => device_name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+/// Picks the physical device with the highest score. Devices that can not run the renderer are rejected; see
+/// get_physical_device_score(). If there is an override, the overridden device is picked as long as it is not rejected.
+/// PHYSICAL_DEVICE_OVERRIDE_ENV_VAR takes precedence over physical_device_override.
+/// 
+/// surface is None for headless renderers, then presentation and swapchain support are not checked.
+pub fn get_physical_device(instance: &ash::Instance, surface: Option<(&extensions::khr::Surface, vk::SurfaceKHR)>,
+physical_device_override: Option<&PhysicalDeviceOverride>) -> Result<vk::PhysicalDevice, RendererError> {
+    let physical_devices = unsafe{instance.enumerate_physical_devices()}?;
     println!("\nThere is '{}' physical device(s).", physical_devices.len());
-    let physical_device = physical_devices[0]; // TODO: Just using the first one for now. Change it later.
-    // list_physical_device_properties_and_features(&instance, &physical_device);
-    physical_device
+
+    let env_override = PhysicalDeviceOverride::from_env();
+    let physical_device_override = env_override.as_ref().or(physical_device_override);
+    if let Some(physical_device_override) = physical_device_override {
+        println!("Physical device override is: {:?}", physical_device_override);
+    }
+
+    let mut best_physical_device: Option<(vk::PhysicalDevice, u64, String)> = None;
+    let mut overridden_physical_device: Option<(vk::PhysicalDevice, String)> = None;
+    for (idx, physical_device) in physical_devices.iter().enumerate() {
+        let properties = unsafe{instance.get_physical_device_properties(*physical_device)};
+        let device_name = unsafe{CStr::from_ptr(properties.device_name.as_ptr())}.to_string_lossy().into_owned();
+        
+        match get_physical_device_score(instance, *physical_device, surface)? {
+            Ok(score) => {
+                println!("Physical device [{}] '{}' ({:?}) is suitable with score: {}", idx, device_name, properties.device_type, score);
+                if physical_device_override.is_some_and(|device_override| device_override.matches(idx, &device_name)) &&
+                overridden_physical_device.is_none() {
+                    overridden_physical_device = Some((*physical_device, device_name.clone()));
+                }
+                if best_physical_device.as_ref().map_or(true, |(_, best_score, _)| score > *best_score) {
+                    best_physical_device = Some((*physical_device, score, device_name));
+                }
+            },
+            Err(reason) => {
+                println!("Physical device [{}] '{}' ({:?}) is rejected: {}", idx, device_name, properties.device_type, reason);
+            }
+        }
+    }
+
+    if let Some((physical_device, device_name)) = overridden_physical_device {
+        println!("Picked physical device '{}' because of the override.", device_name);
+        return Ok(physical_device);
+    }
+    if physical_device_override.is_some() {
+        println!("No suitable physical device matches the override, falling back to the highest scored one.");
+    }
+    match best_physical_device {
+        Some((physical_device, score, device_name)) => {
+            println!("Picked physical device '{}' because it has the highest score: {}", device_name, score);
+            Ok(physical_device)
+        },
+        None => Err(RendererError::NoSuitablePhysicalDevice)
+    }
+}
+
+/// Scores a physical device by its type first, then by sampler anisotropy support and then by its device local memory size
+/// in MiB. Returns the rejection reason as Err if the device lacks Vulkan 1.1, a GRAPHICS queue family, presentation
+/// support or the swapchain extension. Outer Err is for failed Vulkan calls.
+fn get_physical_device_score(instance: &ash::Instance, physical_device: vk::PhysicalDevice,
+surface: Option<(&extensions::khr::Surface, vk::SurfaceKHR)>) -> Result<Result<u64, String>, RendererError> {
+    let properties = unsafe{instance.get_physical_device_properties(physical_device)};
+    let memory_properties = unsafe{instance.get_physical_device_memory_properties(physical_device)};
+
+    // Memory requirements of every allocation are queried with the Vulkan 1.1 vkGet...MemoryRequirements2 functions.
+    if properties.api_version < vk::API_VERSION_1_1 {
+        return Ok(Err(format!("Vulkan {}.{} is supported, but 1.1 is required", vk::api_version_major(properties.api_version),
+            vk::api_version_minor(properties.api_version))));
+    }
+    if find_queue_family_indices(instance, physical_device, surface)?.is_none() {
+        return Ok(Err(if surface.is_some() {
+            "there is no GRAPHICS queue family with transfer support, or no queue family with presentation support".to_owned()
+        } else {
+            "there is no GRAPHICS queue family with transfer support".to_owned()
+        }));
+    }
+    if surface.is_some() && !has_device_extension(instance, physical_device, extensions::khr::Swapchain::name())? {
+        return Ok(Err(format!("device extension '{}' is not supported", extensions::khr::Swapchain::name().to_string_lossy())));
+    }
+
+    let device_type_score: u64 = match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    };
+    // Anisotropic filtering is optional, but devices without it render every texture of the model blurrier at grazing angles.
+    let sampler_anisotropy_score: u64 = if get_max_sampler_anisotropy(instance, physical_device).is_some() {1} else {0};
+    let device_local_memory_size: u64 = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize].iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+    // Device type always wins over memory size; an integrated GPU sharing a big system memory must not beat a discrete GPU.
+    // Sampler anisotropy comes in between, it only decides between devices of the same type.
+    Ok(Ok(device_type_score * 1_000_000_000 + sampler_anisotropy_score * 100_000_000 + device_local_memory_size / (1024 * 1024)))
+}
+
+fn has_device_extension(instance: &ash::Instance, physical_device: vk::PhysicalDevice, extension_name: &CStr)
+-> Result<bool, RendererError> {
+    let available_device_ext_props = 
+        unsafe{instance.enumerate_device_extension_properties(physical_device)}?;
+    Ok(available_device_ext_props.iter().any(|device_ext_props| {
+        let device_ext_name = unsafe{CStr::from_ptr(device_ext_props.extension_name.as_ptr())};
+        device_ext_name == extension_name
+    }))
+}
+
+/// Returns true if VK_EXT_memory_budget can be enabled. It is queried with vkGetPhysicalDeviceMemoryProperties2, which needs
+/// the device to support Vulkan 1.1.
+pub fn has_memory_budget_support(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Result<bool, RendererError> {
+    let properties = unsafe{instance.get_physical_device_properties(physical_device)};
+    if properties.api_version < vk::API_VERSION_1_1 {
+        return Ok(false);
+    }
+    has_device_extension(instance, physical_device, vk::ExtMemoryBudgetFn::name())
+}
+
+/// Returns the maximum sampler anisotropy if the sampler anisotropy feature is supported, used for the optional feature.
+pub fn get_max_sampler_anisotropy(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Option<f32> {
+    let features = unsafe{instance.get_physical_device_features(physical_device)};
+    if features.sampler_anisotropy == vk::FALSE {
+        return None;
+    }
+    let properties = unsafe{instance.get_physical_device_properties(physical_device)};
+    Some(properties.limits.max_sampler_anisotropy)
+}
+
+/// Returns true if the image cube array feature is supported, used for the optional feature. Cube map arrays can only be
+/// viewed as CUBE_ARRAY with it.
+pub fn has_image_cube_array_support(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    let features = unsafe{instance.get_physical_device_features(physical_device)};
+    features.image_cube_array == vk::TRUE
+}
+
+/// Returns true if the instance extension is available, used for optional instance extensions.
+pub fn has_instance_extension(entry: &ash::Entry, extension_name: &CStr) -> Result<bool, RendererError> {
+    let available_instance_ext_props = entry.enumerate_instance_extension_properties(None)?;
+    Ok(available_instance_ext_props.iter().any(|instance_ext_props| {
+        let instance_ext_name = unsafe{CStr::from_ptr(instance_ext_props.extension_name.as_ptr())};
+        instance_ext_name == extension_name
+    }))
 }
 
 /// Returns **required** instance layer names.
@@ -16,125 +188,261 @@ pub fn get_physical_device(instance: &ash::Instance) -> vk::PhysicalDevice {
 /// pointers becomes dangling because CString created inside this function does not live long enough. 
 /// 
 /// Note: There are only layers for Instances; Device layers are deprecated.
-pub fn get_instance_layer_names(entry: &ash::Entry) -> Vec<CString> {
+pub fn get_instance_layer_names(entry: &ash::Entry) -> Result<Vec<CString>, RendererError> {
     let wanted_layer_names: Vec<CString> = vec![
         #[cfg(debug_assertions)]
         CString::new("VK_LAYER_KHRONOS_validation").unwrap()
     ];
     
     // Check supporting:
-    let available_instance_layer_properties = entry.enumerate_instance_layer_properties().unwrap();
+    let available_instance_layer_properties = entry.enumerate_instance_layer_properties()?;
     for wanted_layer_name in &wanted_layer_names {
         let mut found = false;
         for layer_property in &available_instance_layer_properties {
             let layer_property_name = unsafe{CStr::from_ptr(layer_property.layer_name.as_ptr())};
             if wanted_layer_name.as_c_str() == layer_property_name {
-                println!("Wanted Layer: '{}' is available.", layer_property_name.to_str().unwrap());
+                println!("Wanted Layer: '{}' is available.", layer_property_name.to_string_lossy());
                 found = true;
                 break;
             }
         }
         if !found {
-            panic!("The required instance layer: '{}' is not found!", wanted_layer_name.to_str().unwrap());
+            return Err(RendererError::MissingInstanceLayer(wanted_layer_name.to_string_lossy().into_owned()));
         }
     }    
  
-    wanted_layer_names
+    Ok(wanted_layer_names)
 }
 
  /// Returns **required** instance extension names.
 /// Note: There are 2 types of extensions: Device and Instance. You pass extensions to 
 /// corresponding type in DeviceCreateInfo or InstanceCreateInfo.
-pub fn get_instance_extension_names(entry: &ash::Entry) -> Vec<*const i8> {
-    let wanted_extension_names = vec![
-       extensions::khr::Surface::name(),
-       #[cfg(all(windows))]
-       extensions::khr::Win32Surface::name(),
+/// Note: Only the surface extension of the window system that display_handle belongs to is requested. Headless renderers
+/// pass None and do not request any surface extensions.
+/// Note: VK_EXT_swapchain_colorspace is only requested if with_swapchain_colorspace is true, callers should check it with
+/// has_instance_extension() first since it is optional.
+pub fn get_instance_extension_names(entry: &ash::Entry, display_handle: Option<RawDisplayHandle>, with_swapchain_colorspace: bool)
+-> Result<Vec<*const i8>, RendererError> {
+    let mut wanted_extension_names = vec![
        #[cfg(debug_assertions)]
        extensions::ext::DebugUtils::name(), 
    ];
+   if let Some(display_handle) = display_handle {
+       wanted_extension_names.push(extensions::khr::Surface::name());
+       wanted_extension_names.push(get_platform_surface_extension_name(display_handle)?);
+       if with_swapchain_colorspace {
+           wanted_extension_names.push(vk::ExtSwapchainColorspaceFn::name());
+       }
+   }
    
    // Check supporting:
-   let available_instance_ext_props = entry.enumerate_instance_extension_properties(None).unwrap();
+   let available_instance_ext_props = entry.enumerate_instance_extension_properties(None)?;
    for wanted_ext_name in &wanted_extension_names {
        let mut found = false;
        for ext_property in &available_instance_ext_props {
            let ext_property_name = unsafe{CStr::from_ptr(ext_property.extension_name.as_ptr())};
            if *wanted_ext_name == ext_property_name {
-               println!("Wanted Instance extension: '{}' is available.", ext_property_name.to_str().unwrap());
+               println!("Wanted Instance extension: '{}' is available.", ext_property_name.to_string_lossy());
                found = true;
                break;
            }
        }
        if !found {
-           panic!("The required instance extension: '{}' is not found!", wanted_ext_name.to_str().unwrap());
+           return Err(RendererError::MissingInstanceExtension(wanted_ext_name.to_string_lossy().into_owned()));
        }
    }
 
    // Convert CStr elements to *const i8 elements.
    let p_wanted_extension_names = wanted_extension_names.iter().map(|e| {e.as_ptr()}).collect();
-   p_wanted_extension_names
+   Ok(p_wanted_extension_names)
 }
 
-pub fn get_device_extension_names(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Vec<*const i8> {
-    let wanted_device_ext_names = vec![
-        extensions::khr::Swapchain::name().as_ptr()
-        ];
+/// Returns the platform specific surface instance extension name for the window system of display_handle.
+fn get_platform_surface_extension_name(display_handle: RawDisplayHandle) -> Result<&'static CStr, RendererError> {
+    match display_handle {
+        #[cfg(target_os = "windows")]
+        RawDisplayHandle::Windows(_) => Ok(extensions::khr::Win32Surface::name()),
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        RawDisplayHandle::Xlib(_) => Ok(extensions::khr::XlibSurface::name()),
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        RawDisplayHandle::Xcb(_) => Ok(extensions::khr::XcbSurface::name()),
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        RawDisplayHandle::Wayland(_) => Ok(extensions::khr::WaylandSurface::name()),
+        _ => Err(RendererError::UnsupportedWindowSystem(format!("display handle: {:?}", display_handle)))
+    }
+}
+
+/// Swapchain extension is only requested when with_swapchain is true, headless renderers do not need it.
+/// with_memory_budget adds VK_EXT_memory_budget, caller must check has_memory_budget_support() first since it is optional.
+pub fn get_device_extension_names(instance: &ash::Instance, physical_device: vk::PhysicalDevice, with_swapchain: bool,
+with_memory_budget: bool) -> Result<Vec<*const i8>, RendererError> {
+    let mut wanted_device_ext_names = Vec::new();
+    if with_swapchain {
+        wanted_device_ext_names.push(extensions::khr::Swapchain::name().as_ptr());
+    }
+    if with_memory_budget {
+        wanted_device_ext_names.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+    }
  
     let available_device_ext_props = 
-        unsafe{instance.enumerate_device_extension_properties(physical_device)}.unwrap();
+        unsafe{instance.enumerate_device_extension_properties(physical_device)}?;
     for wanted_device_ext_name in &wanted_device_ext_names {
         let mut found = false;
         let wanted_device_ext_name_cstr = unsafe{CStr::from_ptr(*wanted_device_ext_name)};
         for device_ext_props in &available_device_ext_props {
             let layer_property_name = unsafe{CStr::from_ptr(device_ext_props.extension_name.as_ptr())};
             if wanted_device_ext_name_cstr == layer_property_name {
-                println!("Wanted Device Extension: '{}' is available.", layer_property_name.to_str().unwrap());
+                println!("Wanted Device Extension: '{}' is available.", layer_property_name.to_string_lossy());
                 found = true;
                 break;
             }
         }
         if !found {
-            panic!("The required device extension: '{}' is not found!", wanted_device_ext_name_cstr.to_str().unwrap());
+            return Err(RendererError::MissingDeviceExtension(wanted_device_ext_name_cstr.to_string_lossy().into_owned()));
         }
     }
  
-    wanted_device_ext_names
+    Ok(wanted_device_ext_names)
+}
+
+/// Queue families the renderer submits to. They are all the same family unless the device has a transfer family without
+/// GRAPHICS, or the graphics family can not present.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueFamilyIndices {
+    pub graphics: u32,
+    /// None for headless renderers.
+    pub present: Option<u32>,
+    /// Uploads are submitted to this family.
+    pub transfer: u32,
 }
 
-/// This is the **index** of graphics queue family inside the array returned from vkGetPhysicalDeviceQueueFamilyProperties.
+impl QueueFamilyIndices {
+    /// Returns each family once, in the order of graphics, present and transfer.
+    pub fn unique(&self) -> Vec<u32> {
+        let mut unique_indices = vec![self.graphics];
+        for queue_family_idx in self.present.into_iter().chain([self.transfer]) {
+            if !unique_indices.contains(&queue_family_idx) {
+                unique_indices.push(queue_family_idx);
+            }
+        }
+        unique_indices
+    }
+}
+
+/// These are **indices** of queue families inside the array returned from vkGetPhysicalDeviceQueueFamilyProperties.
 /// GRAPHICS QUEUE always can do TRANSFER operations, even if it does not say the GRAPHICS QUEUE has TRANSFER_BIT.
-pub fn get_graphics_queue_family_idx(instance: &ash::Instance, physical_device: vk::PhysicalDevice, surface: &surface::Surface) -> u32 {
+/// Presentation support is only checked if there is a surface.
+pub fn get_queue_family_indices(instance: &ash::Instance, physical_device: vk::PhysicalDevice, surface: Option<&surface::Surface>)
+-> Result<QueueFamilyIndices, RendererError> {
     let queue_family_props = unsafe{
         instance.get_physical_device_queue_family_properties(physical_device)
     };
     println!("\nPhysical device queue family properties:\n\t{queue_family_props:?}");
 
-    for (queue_family_idx, queue_family_prop) in queue_family_props.iter().enumerate() {
-        if queue_family_prop.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-            let has_presentation_support = unsafe {
-                surface.loader.get_physical_device_surface_support(physical_device, queue_family_idx as u32, surface.surface_khr).unwrap()
-            };
-            let has_transfer_support = queue_family_prop.queue_flags.contains(vk::QueueFlags::TRANSFER);
-            if has_presentation_support && has_transfer_support {
-                println!("Found the GRAPHICS queue family with presentation support and transfer support at index: '{}'", queue_family_idx);
-                return queue_family_idx as u32;
+    match find_queue_family_indices(instance, physical_device, surface.map(|surface| (&surface.loader, surface.surface_khr)))? {
+        Some(queue_family_indices) => {
+            println!("Found the GRAPHICS queue family at index: '{}', the PRESENT queue family at index: '{:?}' and the TRANSFER \
+                queue family at index: '{}'", queue_family_indices.graphics, queue_family_indices.present, queue_family_indices.transfer);
+            Ok(queue_family_indices)
+        },
+        None => Err(RendererError::MissingQueueFamily(if surface.is_some() {"GRAPHICS or PRESENT"} else {"GRAPHICS"}))
+    }
+}
+
+/// Picks a GRAPHICS family that can present if there is one, otherwise any GRAPHICS family and a separate present family.
+/// The transfer family is a family without GRAPHICS if there is one, falling back to the graphics family.
+fn find_queue_family_indices(instance: &ash::Instance, physical_device: vk::PhysicalDevice,
+surface: Option<(&extensions::khr::Surface, vk::SurfaceKHR)>) -> Result<Option<QueueFamilyIndices>, RendererError> {
+    let queue_family_props = unsafe{
+        instance.get_physical_device_queue_family_properties(physical_device)
+    };
+    let has_presentation_support = |queue_family_idx: u32| -> Result<bool, RendererError> {
+        match surface {
+            Some((surface_loader, surface_khr)) => Ok(unsafe {
+                surface_loader.get_physical_device_surface_support(physical_device, queue_family_idx, surface_khr)?
+            }),
+            None => Ok(true)
+        }
+    };
+
+    // Graphics families must say that they support TRANSFER, since they are the fallback for uploads.
+    let graphics_queue_family_indices: Vec<u32> = queue_family_props.iter().enumerate()
+        .filter(|(_, queue_family_prop)| queue_family_prop.queue_flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::TRANSFER))
+        .map(|(queue_family_idx, _)| queue_family_idx as u32)
+        .collect();
+    let Some(first_graphics_queue_family_idx) = graphics_queue_family_indices.first().copied() else {
+        return Ok(None);
+    };
+
+    let mut graphics_and_present = None;
+    for queue_family_idx in &graphics_queue_family_indices {
+        if has_presentation_support(*queue_family_idx)? {
+            graphics_and_present = Some((*queue_family_idx, *queue_family_idx));
+            break;
+        }
+    }
+    if graphics_and_present.is_none() {
+        for queue_family_idx in 0..queue_family_props.len() as u32 {
+            if has_presentation_support(queue_family_idx)? {
+                graphics_and_present = Some((first_graphics_queue_family_idx, queue_family_idx));
+                break;
             }
         }
     }
+    let Some((graphics, present)) = graphics_and_present else {
+        return Ok(None);
+    };
+
+    // Info: Families with only TRANSFER (and SPARSE_BINDING) are usually backed by DMA engines, which copy in parallel with
+    // rendering. Their minImageTransferGranularity might not be (1, 1, 1), which is fine since whole mip levels are copied.
+    let transfer_only = queue_family_props.iter().position(|queue_family_prop| {
+        queue_family_prop.queue_flags.contains(vk::QueueFlags::TRANSFER) &&
+            !queue_family_prop.queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+    });
+    let transfer_without_graphics = queue_family_props.iter().position(|queue_family_prop| {
+        queue_family_prop.queue_flags.contains(vk::QueueFlags::TRANSFER) &&
+            !queue_family_prop.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+    });
+    let transfer = transfer_only.or(transfer_without_graphics).map_or(graphics, |queue_family_idx| queue_family_idx as u32);
 
-    panic!("Could not found GRAPHICS queue family index!");
+    Ok(Some(QueueFamilyIndices {
+        graphics,
+        present: surface.map(|_| present),
+        transfer,
+    }))
 }
 
-pub fn get_swapchain_min_image_count(wanted_image_count: u32, surface_loader: &extensions::khr::Surface, 
-physical_device: vk::PhysicalDevice, surface: vk::SurfaceKHR, ) -> u32 {
-    let capabilities = 
-        unsafe{surface_loader.get_physical_device_surface_capabilities(physical_device, surface)}.unwrap();
-    if wanted_image_count >= capabilities.min_image_count && wanted_image_count <= capabilities.max_image_count {
-        println!("swapchain minimum required image count is: '{}'", wanted_image_count);
-        return wanted_image_count;
+/// Returns the first memory type that is allowed by memory_type_bits and has all of the required property flags.
+/// Info: Host coherent memory does not need flushing or invalidating.
+pub fn find_memory_type_idx(physical_device_memory_properties: &vk::PhysicalDeviceMemoryProperties, memory_type_bits: u32,
+required_flags: vk::MemoryPropertyFlags) -> Result<u32, RendererError> {
+    let memory_type_count = physical_device_memory_properties.memory_type_count as usize;
+    physical_device_memory_properties.memory_types[..memory_type_count].iter().enumerate()
+        .position(|(idx, memory_type)| {
+            memory_type.property_flags.contains(required_flags) && ((1 << idx) & memory_type_bits) == (1 << idx)
+        })
+        .map(|idx| idx as u32)
+        .ok_or(RendererError::NoSuitableMemoryType { memory_type_bits, required_flags })
+}
+
+/// Returns the highest sample count that is not higher than wanted_sample_count and supported by both color and depth
+/// framebuffer attachments. Software drivers like lavapipe do not support more than 4 samples.
+pub fn get_max_usable_sample_count(instance: &ash::Instance, physical_device: vk::PhysicalDevice, wanted_sample_count: vk::SampleCountFlags)
+-> vk::SampleCountFlags {
+    let limits = unsafe{instance.get_physical_device_properties(physical_device)}.limits;
+    let supported_sample_counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    let sample_counts = [
+        vk::SampleCountFlags::TYPE_64, vk::SampleCountFlags::TYPE_32, vk::SampleCountFlags::TYPE_16, vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4, vk::SampleCountFlags::TYPE_2
+    ];
+    for sample_count in sample_counts {
+        if sample_count.as_raw() <= wanted_sample_count.as_raw() && supported_sample_counts.contains(sample_count) {
+            println!("MSAA sample count is: {:?}", sample_count);
+            return sample_count;
+        }
     }
-    panic!("Wanted minimum swapchain image count is out of bounds of swapchain can have! max is: {}", capabilities.max_image_count);
+    println!("MSAA is not supported, sample count is: {:?}", vk::SampleCountFlags::TYPE_1);
+    vk::SampleCountFlags::TYPE_1
 }
 
 /// This function will be called back by debug_utils_messenger.
@@ -157,7 +465,7 @@ p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT, _p_user_data: *m
         _ => {"[UNKNOWN TYPE]"}
     };
 
-    let msg_str = CStr::from_ptr((*p_callback_data).p_message).to_str().unwrap();
+    let msg_str = CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
     println!("{}{}:{}", severity_str, type_str, msg_str);
 
     ash::vk::FALSE // Should always return false by app, other values are reserved for vulkan layer development.
@@ -0,0 +1,163 @@
+use ash::vk;
+use std::collections::HashMap;
+use std::ptr;
+use std::rc::Rc;
+use super::device;
+use super::error::RendererError;
+
+/// Describes how a texture is sampled. Identical descriptions share a sampler through SamplerCache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// Only used by the CLAMP_TO_BORDER address mode.
+    pub border_color: vk::BorderColor,
+    /// None disables anisotropic filtering. Clamped to the device limit, and ignored if the device does not support it.
+    pub max_anisotropy: Option<f32>,
+    /// Some makes a depth compare sampler, e.g. for shadow maps, which returns the result of the compare op.
+    pub compare_op: Option<vk::CompareOp>,
+    pub mip_lod_bias: f32,
+    pub min_lod: f32,
+    /// vk::LOD_CLAMP_NONE samples every mip level of any texture.
+    pub max_lod: f32,
+}
+
+impl Default for SamplerDesc {
+    /// Trilinear and anisotropic filtering with REPEAT addressing, for the textures of models.
+    fn default() -> SamplerDesc {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+            max_anisotropy: Some(16.0),
+            compare_op: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// Nearest filtering without anisotropy, e.g. for pixel art or lookup textures.
+    pub fn nearest() -> SamplerDesc {
+        SamplerDesc {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            max_anisotropy: None,
+            ..SamplerDesc::default()
+        }
+    }
+
+    /// Default filtering with CLAMP_TO_EDGE addressing, e.g. for cube maps and screen space textures.
+    pub fn clamped() -> SamplerDesc {
+        SamplerDesc {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..SamplerDesc::default()
+        }
+    }
+
+    /// Linear filtered depth comparison for shadow maps. Everything outside of the map is lit, since the border depth is 1.
+    pub fn shadow_compare() -> SamplerDesc {
+        SamplerDesc {
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            max_anisotropy: None,
+            compare_op: Some(vk::CompareOp::LESS_OR_EQUAL),
+            ..SamplerDesc::default()
+        }
+    }
+
+    /// Info: Floats are compared by their bits, so descriptions only share a sampler if they are exactly the same.
+    fn key(&self) -> SamplerKey {
+        SamplerKey {
+            filters: (self.mag_filter, self.min_filter, self.mipmap_mode),
+            address_modes: [self.address_mode_u, self.address_mode_v, self.address_mode_w],
+            border_color: self.border_color,
+            max_anisotropy: self.max_anisotropy.map(f32::to_bits),
+            compare_op: self.compare_op,
+            lods: [self.mip_lod_bias.to_bits(), self.min_lod.to_bits(), self.max_lod.to_bits()],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    filters: (vk::Filter, vk::Filter, vk::SamplerMipmapMode),
+    address_modes: [vk::SamplerAddressMode; 3],
+    border_color: vk::BorderColor,
+    max_anisotropy: Option<u32>,
+    compare_op: Option<vk::CompareOp>,
+    lods: [u32; 3],
+}
+
+fn create_sampler(device: &Rc<device::Device>, desc: &SamplerDesc) -> Result<device::Sampler, RendererError> {
+    let max_anisotropy = match (desc.max_anisotropy, device.max_sampler_anisotropy()) {
+        (Some(max_anisotropy), Some(device_max_anisotropy)) => Some(f32::min(max_anisotropy, device_max_anisotropy)),
+        (Some(_), None) => {
+            println!("Sampler: Anisotropic filtering is not supported by the device, it is disabled for {:?}.", desc);
+            None
+        },
+        (None, _) => None,
+    };
+    let sampler_ci = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        p_next: ptr::null(),
+        flags: vk::SamplerCreateFlags::empty(),
+        mag_filter: desc.mag_filter,
+        min_filter: desc.min_filter,
+        mipmap_mode: desc.mipmap_mode,
+        address_mode_u: desc.address_mode_u,
+        address_mode_v: desc.address_mode_v,
+        address_mode_w: desc.address_mode_w,
+        mip_lod_bias: desc.mip_lod_bias,
+        anisotropy_enable: max_anisotropy.is_some() as vk::Bool32,
+        max_anisotropy: max_anisotropy.unwrap_or(1.0),
+        compare_enable: desc.compare_op.is_some() as vk::Bool32,
+        compare_op: desc.compare_op.unwrap_or(vk::CompareOp::ALWAYS),
+        min_lod: desc.min_lod,
+        max_lod: desc.max_lod,
+        border_color: desc.border_color,
+        unnormalized_coordinates: vk::FALSE,
+    };
+    Ok(device::Owned::new(device, unsafe {
+        device.create_sampler(&sampler_ci, None)
+    }?))
+}
+
+/// Creates a sampler once per distinct SamplerDesc, and hands out the same one for identical descriptions after that.
+pub struct SamplerCache {
+    samplers: HashMap<SamplerKey, Rc<device::Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn new() -> SamplerCache {
+        SamplerCache {
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn get_or_create(&mut self, device: &Rc<device::Device>, desc: &SamplerDesc) -> Result<Rc<device::Sampler>, RendererError> {
+        let key = desc.key();
+        if let Some(sampler) = self.samplers.get(&key) {
+            return Ok(sampler.clone());
+        }
+        let sampler = Rc::new(create_sampler(device, desc)?);
+        self.samplers.insert(key, sampler.clone());
+        Ok(sampler)
+    }
+}
@@ -0,0 +1,101 @@
+use ash::vk;
+use std::rc::Rc;
+use super::{allocator, buffer, commandbuffer, device};
+use super::error::RendererError;
+extern crate image as img;
+
+/// Host visible copy of a swapchain image that is saved as PNG after the frame it was recorded into has finished.
+pub struct Screenshot {
+    pub path: String,
+    buffer: buffer::Buffer,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+}
+
+impl Screenshot {
+    pub fn new(device: &Rc<device::Device>, path: String, width: u32, height: u32, format: vk::Format,
+    queue_family_idx: u32) -> Result<Screenshot, RendererError> {
+        let buffer_size = width as vk::DeviceSize * height as vk::DeviceSize * 4;
+        let buffer = buffer::Buffer::new(device, allocator::AllocationUsage::Readback, buffer_size, vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, &queue_family_idx)?;
+
+        Ok(Screenshot {
+            path,
+            buffer,
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Records the copy of a swapchain image that the render pass left in PRESENT_SRC_KHR layout. The image is transitioned
+    /// back to PRESENT_SRC_KHR, so it can still be presented afterwards.
+    pub fn record_copy_from_swapchain_image(&self, device: &ash::Device, cmd_buffer: vk::CommandBuffer, swapchain_image: vk::Image) {
+        let image_subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        commandbuffer::transition_image_layout(device, cmd_buffer, swapchain_image, image_subresource_range,
+            vk::ImageLayout::PRESENT_SRC_KHR,                 vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,          vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,  vk::PipelineStageFlags::TRANSFER);
+
+        let extent = vk::Extent3D {
+            width: self.width,
+            height: self.height,
+            depth: 1
+        };
+        commandbuffer::copy_device_image_to_device_buffer(device, cmd_buffer, swapchain_image, self.buffer.raw, &extent);
+
+        // Presentation does not need any access flags, queue_present waits on the render finished semaphore anyway.
+        commandbuffer::transition_image_layout(device, cmd_buffer, swapchain_image, image_subresource_range,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,  vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::AccessFlags::TRANSFER_READ,         vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::TRANSFER,       vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        commandbuffer::host_read_buffer_barrier(device, cmd_buffer, self.buffer.raw);
+    }
+
+    /// Caller must make sure the recorded copy has finished on the device.
+    pub fn save(&self) -> Result<(), RendererError> {
+        let buffer_size = self.width as usize * self.height as usize * 4;
+        let mapped_memory_ptr = self.buffer.mapped_ptr().expect("Screenshot buffer must be HOST_VISIBLE!");
+        let pixels = unsafe {
+            std::slice::from_raw_parts(mapped_memory_ptr, buffer_size)
+        };
+        save_png(&self.path, pixels, self.width, self.height, self.format)
+    }
+}
+
+/// Returns an error if images of format can not be saved by save_png().
+///
+/// Info: Only 8 bits per channel formats are supported. 10 bits and float formats are only used by the HDR output color
+/// spaces, whose values are not sRGB and would have to be tone mapped to be saved as PNG.
+pub fn check_format(format: vk::Format) -> Result<(), RendererError> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM | vk::Format::A8B8G8R8_SRGB_PACK32 | vk::Format::A8B8G8R8_UNORM_PACK32 |
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Ok(()),
+        _ => Err(RendererError::UnsupportedScreenshotFormat(format)),
+    }
+}
+
+/// Saves tightly packed 4 bytes per pixel rows as a RGBA PNG file. BGRA formats are swizzled to RGBA.
+pub fn save_png(path: &str, pixels: &[u8], width: u32, height: u32, format: vk::Format) -> Result<(), RendererError> {
+    check_format(format)?;
+    // Info: PACK32 formats are packed in little endian 32 bit words, which puts R in the first byte like R8G8B8A8.
+    let rgba_pixels = match format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => {
+            pixels.chunks_exact(4).flat_map(|bgra| {[bgra[2], bgra[1], bgra[0], bgra[3]]}).collect()
+        },
+        _ => pixels.to_vec(),
+    };
+
+    img::save_buffer(path, &rgba_pixels, width, height, img::ColorType::Rgba8).map_err(|error| {
+        RendererError::ScreenshotFile { path: path.to_owned(), error }
+    })?;
+    println!("Screenshot is saved to: '{}'", path);
+    Ok(())
+}
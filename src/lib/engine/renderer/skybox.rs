@@ -0,0 +1,124 @@
+use ash::vk;
+use std::ptr;
+use std::rc::Rc;
+use super::{device, texture, vk_creations};
+use super::error::RendererError;
+
+/// Size of the push constants of the skybox vertex shader.
+const PUSH_CONSTANTS_SIZE: u32 = std::mem::size_of::<glam::Mat4>() as u32;
+
+/// Draws the environment cube map behind the model, with a triangle at the far plane that covers the whole viewport.
+/// The renderer creates its pipeline with pipeline_layout, the skybox shaders and depth writes disabled.
+pub struct Skybox {
+    pub pipeline_layout: device::PipelineLayout,
+    _descriptor_pool: device::DescriptorPool, // Descriptor set is freed with it.
+    descriptor_set: vk::DescriptorSet,
+    _descriptor_set_layout: device::DescriptorSetLayout,
+    // Only referenced by the descriptor set.
+    _environment_map: Rc<texture::Texture>,
+    _sampler: Rc<device::Sampler>,
+}
+
+impl Skybox {
+    /// environment_map must be a cube map in SHADER_READ_ONLY_OPTIMAL layout.
+    pub fn new(device: &Rc<device::Device>, environment_map: Rc<texture::Texture>, sampler: Rc<device::Sampler>)
+    -> Result<Skybox, RendererError> {
+        let sampler_descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            p_immutable_samplers: ptr::null(),
+        };
+        let descriptor_set_layout = vk_creations::create_descriptor_set_layout(device, &[sampler_descriptor_set_layout_binding])?;
+
+        let sampler_descriptor_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        };
+        let descriptor_pool_ci = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DescriptorPoolCreateFlags::empty(),
+            max_sets: 1,
+            pool_size_count: 1,
+            p_pool_sizes: &sampler_descriptor_pool_size,
+        };
+        let descriptor_pool = device::Owned::new(device, unsafe {
+            device.create_descriptor_pool(&descriptor_pool_ci, None)
+        }?);
+        let descriptor_set = vk_creations::allocate_descriptor_sets(device, descriptor_pool.raw(), descriptor_set_layout.raw(), 1)?[0];
+
+        let descriptor_image_info = vk::DescriptorImageInfo {
+            sampler: sampler.raw(),
+            image_view: environment_map.view.raw,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let write_descriptor_set_image_sampler = vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            p_next: ptr::null(),
+            dst_set: descriptor_set,
+            dst_binding: 0,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: &descriptor_image_info,
+            p_buffer_info: ptr::null(),
+            p_texel_buffer_view: ptr::null(),
+        };
+        unsafe {
+            device.update_descriptor_sets(&[write_descriptor_set_image_sampler], &[]);
+        }
+
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: PUSH_CONSTANTS_SIZE,
+        };
+        let set_layouts = [descriptor_set_layout.raw()];
+        let pipeline_layout_ci = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+        };
+        let pipeline_layout = device::Owned::new(device, unsafe {
+            device.create_pipeline_layout(&pipeline_layout_ci, None)
+        }?);
+
+        Ok(Skybox {
+            pipeline_layout,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set,
+            _descriptor_set_layout: descriptor_set_layout,
+            _environment_map: environment_map,
+            _sampler: sampler,
+        })
+    }
+
+    /// Records the draw inside the render pass, after the model, so that the depth test skips the pixels it covers.
+    /// pipeline must be created with pipeline_layout. Only the rotation of view is used.
+    pub fn record_draw(&self, device: &ash::Device, cmd_buffer: vk::CommandBuffer, pipeline: vk::Pipeline, view: glam::Mat4,
+    projection: glam::Mat4) {
+        let view_rotation = glam::Mat4::from_mat3(glam::Mat3::from_mat4(view));
+        let inverse_view_projection = (projection * view_rotation).inverse();
+        unsafe {
+            device.cmd_bind_pipeline(cmd_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(cmd_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline_layout.raw(), 0,
+                &[self.descriptor_set], &[]);
+            device.cmd_push_constants(cmd_buffer, self.pipeline_layout.raw(), vk::ShaderStageFlags::VERTEX, 0,
+                mat4_as_bytes(&inverse_view_projection));
+            device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+/// Bytes of a column major matrix, as GLSL reads mat4 push constants.
+fn mat4_as_bytes(matrix: &glam::Mat4) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(matrix as *const glam::Mat4 as *const u8, PUSH_CONSTANTS_SIZE as usize)
+    }
+}
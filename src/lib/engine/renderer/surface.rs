@@ -1,75 +1,354 @@
 use ash::{vk, extensions};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
 use std::ptr;
+use std::rc::Rc;
+use super::device;
+use super::error::RendererError;
 
-#[derive(Clone)]
+/// Color space that swapchain images are presented in. HDR color spaces need VK_EXT_swapchain_colorspace and a surface that
+/// exposes them, otherwise Srgb is used.
+/// 
+/// Info: Fragment shaders encode their linear output for the color space, which is passed to them as their OUTPUT_COLOR_SPACE
+/// specialization constant; see shader_constant(). SDR white is shown with 203 nits on HDR outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    /// 8 bits per channel sRGB. _SRGB formats do the sRGB encoding, otherwise the fragment shaders do it.
+    #[default]
+    Srgb,
+    /// 10 bits per channel BT.2020 primaries with SMPTE ST 2084 (PQ) transfer function.
+    Hdr10,
+    /// 16 bits float per channel scRGB: sRGB primaries with linear values that can go beyond [0, 1].
+    ExtendedSrgb,
+}
+
+impl OutputColorSpace {
+    /// Output color space of a surface color space that get_preferred_surface_formats() can pick.
+    pub fn from_surface_color_space(color_space: vk::ColorSpaceKHR) -> OutputColorSpace {
+        match color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputColorSpace::Hdr10,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputColorSpace::ExtendedSrgb,
+            _ => OutputColorSpace::Srgb,
+        }
+    }
+
+    /// Value of the OUTPUT_COLOR_SPACE specialization constant of the fragment shaders that render into format.
+    pub fn shader_constant(self, format: vk::Format) -> u32 {
+        match self {
+            OutputColorSpace::Srgb if is_srgb_format(format) => 0,
+            OutputColorSpace::Hdr10 => 1,
+            OutputColorSpace::ExtendedSrgb => 2,
+            // Linear formats store what the shaders write, so they apply the sRGB curve themselves.
+            OutputColorSpace::Srgb => 3,
+        }
+    }
+
+    /// Surface formats in preference order. Srgb ones are the fallback of every other color space.
+    fn get_preferred_surface_formats(self) -> &'static [(vk::Format, vk::ColorSpaceKHR)] {
+        match self {
+            OutputColorSpace::Srgb => &[
+                (vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::A8B8G8R8_SRGB_PACK32, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::A8B8G8R8_UNORM_PACK32, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            OutputColorSpace::Hdr10 => &[
+                (vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT),
+                (vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT),
+                (vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::HDR10_ST2084_EXT),
+            ],
+            OutputColorSpace::ExtendedSrgb => &[
+                (vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            ],
+        }
+    }
+}
+
+/// True if format encodes the linear values that are written into it with the sRGB curve.
+fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(format, vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32 |
+        vk::Format::B8G8R8_SRGB | vk::Format::R8G8B8_SRGB)
+}
+
+/// Destroys the surface when dropped, which must be after every swapchain created from it.
 pub struct Surface {
+    _instance: Rc<device::Instance>,
     pub loader: ash::extensions::khr::Surface,
     pub surface_khr: vk::SurfaceKHR,
+    /// Capabilities and supported formats of the surface are queried for this physical device.
+    pub physical_device: vk::PhysicalDevice,
     pub format: vk::Format,
     pub color_space: vk::ColorSpaceKHR,
     pub pre_transform: vk::SurfaceTransformFlagsKHR,
     pub composite_alpha: vk::CompositeAlphaFlagsKHR,
+    /// Usages that swapchain images created from this surface can have. COLOR_ATTACHMENT is always supported.
+    pub supported_usage_flags: vk::ImageUsageFlags,
+    /// FIFO is always supported.
+    pub supported_present_modes: Vec<vk::PresentModeKHR>,
 }
 
 impl Surface {
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance, hinstance: vk::HINSTANCE, hwnd: vk::HWND, physical_device: vk::PhysicalDevice)
-    -> Surface {
-        let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
-
-        // Info: VkSurface object is a platform agnostic high-level object. You need a platform specific loader in order to create 
-        // a VkSurface like Win32Surface_loader. Then you can create a VkSwapchain from this VkSurface.
-        let win32_surface = create_win32_surface(&entry, &instance, hinstance, hwnd);
+    /// surface_khr is created by create_surface_khr(). It is created before this, because physical device selection needs
+    /// to check presentation support of the surface.
+    /// 
+    /// Format and color space are picked from the supported surface formats by output_color_space preference, falling back to
+    /// Srgb formats and then to the first supported format with the sRGB color space. Takes the ownership of platform_surface,
+    /// it is destroyed on errors too.
+    pub fn new(instance: &Rc<device::Instance>, surface_loader: ash::extensions::khr::Surface, platform_surface: vk::SurfaceKHR,
+    physical_device: vk::PhysicalDevice, output_color_space: OutputColorSpace) -> Result<Surface, RendererError> {
+        let (capabilities, supported_present_modes, supported_surface_formats) =
+        match Surface::query_support(&surface_loader, platform_surface, physical_device) {
+            Ok(support) => support,
+            Err(result) => {
+                unsafe{surface_loader.destroy_surface(platform_surface, None)};
+                return Err(result.into());
+            }
+        };
+        let Some(surface_format) = Surface::choose_surface_format(&supported_surface_formats, output_color_space) else {
+            unsafe{surface_loader.destroy_surface(platform_surface, None)};
+            return Err(RendererError::UnsupportedSurfaceFormats(supported_surface_formats));
+        };
         
-        let capabilities = unsafe {
-            surface_loader.get_physical_device_surface_capabilities(physical_device, win32_surface).unwrap()
+        // Info: Presentation engine transforms the image if pre transform is not the current transform, which might cost
+        // performance on rotated displays. Identity is still preferred, since the projection does not account for rotations.
+        let pre_transform = if capabilities.supported_transforms.contains(vk::SurfaceTransformFlagsKHR::IDENTITY) {
+            vk::SurfaceTransformFlagsKHR::IDENTITY
+        } else {
+            capabilities.current_transform
         };
+        // Exactly one composite alpha bit must be passed to the swapchain. At least one of them is always supported.
+        let composite_alpha = [
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::INHERIT,
+        ].into_iter().find(|composite_alpha| capabilities.supported_composite_alpha.contains(*composite_alpha))
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
         
         let surface = Surface {
+            _instance: instance.clone(),
             loader: surface_loader,
-            surface_khr: win32_surface,
-            format: vk::Format::B8G8R8A8_SRGB,                          // TODO: query it.
-            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,             // TODO: query it.
-            pre_transform: capabilities.current_transform,              // TODO: query it.
-            composite_alpha: capabilities.supported_composite_alpha,    // TODO: query it.
+            surface_khr: platform_surface,
+            physical_device,
+            format: surface_format.format,
+            color_space: surface_format.color_space,
+            pre_transform,
+            composite_alpha,
+            supported_usage_flags: capabilities.supported_usage_flags,
+            supported_present_modes,
         };
         println!("{}", surface);
         
-        surface
+        Ok(surface)
+    }
+
+    /// Returns the capabilities, present modes and formats that physical_device supports for platform_surface.
+    fn query_support(surface_loader: &ash::extensions::khr::Surface, platform_surface: vk::SurfaceKHR,
+    physical_device: vk::PhysicalDevice)
+    -> Result<(vk::SurfaceCapabilitiesKHR, Vec<vk::PresentModeKHR>, Vec<vk::SurfaceFormatKHR>), vk::Result> {
+        unsafe {
+            Ok((
+                surface_loader.get_physical_device_surface_capabilities(physical_device, platform_surface)?,
+                surface_loader.get_physical_device_surface_present_modes(physical_device, platform_surface)?,
+                surface_loader.get_physical_device_surface_formats(physical_device, platform_surface)?,
+            ))
+        }
     }
     
-    // Returns true if the min image count is between capabilities of this surface's min image count and max image count.
-    pub fn get_min_image_support(&self, min_image_count: u32, physical_device: vk::PhysicalDevice) -> bool {
-        let capabilities = unsafe {
-            self.loader.get_physical_device_surface_capabilities(physical_device, self.surface_khr).unwrap()
+    /// Returns None if no supported format has a color space that the fragment shaders can encode for.
+    fn choose_surface_format(supported_surface_formats: &[vk::SurfaceFormatKHR], output_color_space: OutputColorSpace)
+    -> Option<vk::SurfaceFormatKHR> {
+        // Info: A single UNDEFINED format means that the surface has no preferred format, so any format can be used.
+        if let [vk::SurfaceFormatKHR { format: vk::Format::UNDEFINED, .. }] = supported_surface_formats {
+            let (format, color_space) = OutputColorSpace::Srgb.get_preferred_surface_formats()[0];
+            return Some(vk::SurfaceFormatKHR { format, color_space });
+        }
+        
+        let find_preferred_surface_format = |output_color_space: OutputColorSpace| {
+            output_color_space.get_preferred_surface_formats().iter().find_map(|(format, color_space)| {
+                supported_surface_formats.iter().find(|surface_format| {
+                    surface_format.format == *format && surface_format.color_space == *color_space
+                }).copied()
+            })
         };
+        if let Some(surface_format) = find_preferred_surface_format(output_color_space) {
+            return Some(surface_format);
+        }
+        if output_color_space != OutputColorSpace::Srgb {
+            println!("Output color space: {:?} is not supported on this surface, falling back to Srgb.", output_color_space);
+            if let Some(surface_format) = find_preferred_surface_format(OutputColorSpace::Srgb) {
+                return Some(surface_format);
+            }
+        }
+
+        // Shaders encode Srgb output themselves for formats that do not, see OutputColorSpace::shader_constant().
+        let surface_format = supported_surface_formats.iter()
+            .find(|surface_format| surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
+            .copied()?;
+        println!("None of the preferred surface formats are supported, using: {:?}", surface_format);
+        Some(surface_format)
+    }
+    
+    /// Returns the requested present mode if this surface supports it, otherwise FIFO which every surface supports.
+    pub fn get_present_mode(&self, requested_present_mode: vk::PresentModeKHR) -> vk::PresentModeKHR {
+        if self.supported_present_modes.contains(&requested_present_mode) {
+            requested_present_mode
+        } else {
+            println!("Present mode: {:?} is not supported on this surface, falling back to FIFO.", requested_present_mode);
+            vk::PresentModeKHR::FIFO
+        }
+    }
+    
+    /// Returns the extent that a swapchain must be created with. It is the current extent of the surface if the window system
+    /// dictates it, otherwise width and height clamped into the supported extents. Might be 0 while the window is minimized.
+    pub fn get_extent(&self, width: u32, height: u32) -> Result<vk::Extent2D, RendererError> {
+        let capabilities = unsafe {
+            self.loader.get_physical_device_surface_capabilities(self.physical_device, self.surface_khr)
+        }?;
+        
+        // Info: current_extent of (u32::MAX, u32::MAX) means that the swapchain extent decides the surface size.
+        if capabilities.current_extent.width != u32::MAX {
+            return Ok(capabilities.current_extent);
+        }
+        Ok(vk::Extent2D {
+            width: width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+            height: height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+        })
+    }
+    
+    // Returns Err if the min image count is not between capabilities of this surface's min image count and max image count.
+    // Info: max image count of 0 means there is no limit.
+    pub fn check_min_image_support(&self, min_image_count: u32, physical_device: vk::PhysicalDevice) -> Result<(), RendererError> {
+        let capabilities = unsafe {
+            self.loader.get_physical_device_surface_capabilities(physical_device, self.surface_khr)
+        }?;
         
-        if min_image_count >= capabilities.min_image_count && min_image_count <= capabilities.max_image_count {
-            true
+        if min_image_count >= capabilities.min_image_count &&
+        (capabilities.max_image_count == 0 || min_image_count <= capabilities.max_image_count) {
+            Ok(())
         } else {
-            false
+            Err(RendererError::UnsupportedSwapchainImageCount {
+                requested: min_image_count,
+                min: capabilities.min_image_count,
+                max: capabilities.max_image_count,
+            })
+        }
+    }
+}
+
+/// Creates the platform specific VkSurfaceKHR of the window, that matches the window system winit is running on.
+/// 
+/// Info: VkSurface object is a platform agnostic high-level object. You need a platform specific loader in order to create 
+/// a VkSurface like Win32Surface_loader. Then you can create a VkSwapchain from this VkSurface.
+pub fn create_surface_khr(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window)
+-> Result<vk::SurfaceKHR, RendererError> {
+    match (window.raw_display_handle(), window.raw_window_handle()) {
+        #[cfg(target_os = "windows")]
+        (RawDisplayHandle::Windows(_), RawWindowHandle::Win32(window)) => {
+            create_win32_surface(entry, instance, window.hinstance as vk::HINSTANCE, window.hwnd as vk::HWND)
+        },
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(window)) => {
+            create_xlib_surface(entry, instance, display.display as *mut vk::Display, window.window as vk::Window)
+        },
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(window)) => {
+            create_xcb_surface(entry, instance, display.connection as *mut vk::xcb_connection_t, window.window as vk::xcb_window_t)
+        },
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+        (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(window)) => {
+            create_wayland_surface(entry, instance, display.display as *mut vk::wl_display, window.surface as *mut vk::wl_surface)
+        },
+        (display_handle, window_handle) => {
+            Err(RendererError::UnsupportedWindowSystem(
+                format!("display handle: {:?}, window handle: {:?}", display_handle, window_handle)))
         }
     }
 }
 
 #[cfg(target_os = "windows")]
-fn create_win32_surface(entry: &ash::Entry, instance: &ash::Instance, hinstance: vk::HINSTANCE, hwnd: vk::HWND) -> vk::SurfaceKHR {
-    let win32_surface_loader = extensions::khr::Win32Surface::new(&entry, &instance);
+fn create_win32_surface(entry: &ash::Entry, instance: &ash::Instance, hinstance: vk::HINSTANCE, hwnd: vk::HWND)
+-> Result<vk::SurfaceKHR, RendererError> {
+    let win32_surface_loader = extensions::khr::Win32Surface::new(entry, instance);
     let win32_surface_ci = vk::Win32SurfaceCreateInfoKHR {
         s_type: vk::StructureType::WIN32_SURFACE_CREATE_INFO_KHR,
         p_next: ptr::null(),
         flags: vk::Win32SurfaceCreateFlagsKHR::empty(),
-        hinstance: hinstance,
-        hwnd: hwnd,
+        hinstance,
+        hwnd,
     };
 
-    unsafe {
-        win32_surface_loader.create_win32_surface(&win32_surface_ci, None).unwrap()
-    }
+    let surface_khr = unsafe {
+        win32_surface_loader.create_win32_surface(&win32_surface_ci, None)
+    }?;
+    Ok(surface_khr)
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+fn create_xlib_surface(entry: &ash::Entry, instance: &ash::Instance, dpy: *mut vk::Display, window: vk::Window)
+-> Result<vk::SurfaceKHR, RendererError> {
+    let xlib_surface_loader = extensions::khr::XlibSurface::new(entry, instance);
+    let xlib_surface_ci = vk::XlibSurfaceCreateInfoKHR {
+        s_type: vk::StructureType::XLIB_SURFACE_CREATE_INFO_KHR,
+        p_next: ptr::null(),
+        flags: vk::XlibSurfaceCreateFlagsKHR::empty(),
+        dpy,
+        window,
+    };
+
+    let surface_khr = unsafe {
+        xlib_surface_loader.create_xlib_surface(&xlib_surface_ci, None)
+    }?;
+    Ok(surface_khr)
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+fn create_xcb_surface(entry: &ash::Entry, instance: &ash::Instance, connection: *mut vk::xcb_connection_t, window: vk::xcb_window_t)
+-> Result<vk::SurfaceKHR, RendererError> {
+    let xcb_surface_loader = extensions::khr::XcbSurface::new(entry, instance);
+    let xcb_surface_ci = vk::XcbSurfaceCreateInfoKHR {
+        s_type: vk::StructureType::XCB_SURFACE_CREATE_INFO_KHR,
+        p_next: ptr::null(),
+        flags: vk::XcbSurfaceCreateFlagsKHR::empty(),
+        connection,
+        window,
+    };
+
+    let surface_khr = unsafe {
+        xcb_surface_loader.create_xcb_surface(&xcb_surface_ci, None)
+    }?;
+    Ok(surface_khr)
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+fn create_wayland_surface(entry: &ash::Entry, instance: &ash::Instance, display: *mut vk::wl_display, surface: *mut vk::wl_surface)
+-> Result<vk::SurfaceKHR, RendererError> {
+    let wayland_surface_loader = extensions::khr::WaylandSurface::new(entry, instance);
+    let wayland_surface_ci = vk::WaylandSurfaceCreateInfoKHR {
+        s_type: vk::StructureType::WAYLAND_SURFACE_CREATE_INFO_KHR,
+        p_next: ptr::null(),
+        flags: vk::WaylandSurfaceCreateFlagsKHR::empty(),
+        display,
+        surface,
+    };
+
+    let surface_khr = unsafe {
+        wayland_surface_loader.create_wayland_surface(&wayland_surface_ci, None)
+    }?;
+    Ok(surface_khr)
 }
 
 impl std::fmt::Display for Surface {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "\nSURFACE:\n\tformat: {:?}\n\tcolor space: {:?}\n\tpre transform: {:?}\n\tcomposite alpha: {:?}",
-            self.format, self.color_space, self.pre_transform, self.composite_alpha)
+        writeln!(f, "\nSURFACE:\n\tformat: {:?}\n\tcolor space: {:?}\n\tpre transform: {:?}\n\tcomposite alpha: {:?}\n\tsupported usage: {:?}\n\tsupported present modes: {:?}",
+            self.format, self.color_space, self.pre_transform, self.composite_alpha, self.supported_usage_flags, self.supported_present_modes)
     }
-}
\ No newline at end of file
+}
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_surface(self.surface_khr, None);
+        }
+    }
+}
@@ -1,48 +1,120 @@
 use ash::{vk, extensions::khr};
 use std::ptr;
-use super::surface;
+use std::rc::Rc;
+use super::{deletion, device, image, surface};
+use super::error::RendererError;
 
+/// How presented images are queued to the display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vsync: waits for the vertical blank, never tears. The only mode every surface supports.
+    #[default]
+    Fifo,
+    /// Vsync, but a late image is presented right away instead of waiting for the next vertical blank, which may tear.
+    FifoRelaxed,
+    /// Vsync without blocking: the queued image is replaced by newer ones, so the latest image is presented.
+    Mailbox,
+    /// No vsync: images are presented right away, which may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    /// Returns the next mode in the order: Fifo, FifoRelaxed, Mailbox, Immediate and then Fifo again.
+    pub fn next(self) -> PresentMode {
+        match self {
+            PresentMode::Fifo => PresentMode::FifoRelaxed,
+            PresentMode::FifoRelaxed => PresentMode::Mailbox,
+            PresentMode::Mailbox => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::Fifo,
+        }
+    }
+
+    fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+/// Destroys the image views and the swapchain when dropped, before the surface.
 pub struct Swapchain {
-    pub device: ash::Device,
+    device: Rc<device::Device>,
     pub surface: surface::Surface,
     pub loader: khr::Swapchain,
     pub raw: vk::SwapchainKHR,
-    images: Vec<vk::Image>,
-    pub image_views: Vec<vk::ImageView>,
+    pub images: Vec<vk::Image>,
+    pub image_views: Vec<image::ImageView>,
     /// **Real** amount of image count swapchain has.
-    pub image_count: u32
+    pub image_count: u32,
+    /// Image count that was asked for, which is asked again on recreation.
+    pub min_image_count: u32,
+    pub extent: vk::Extent2D,
+    /// Present mode asked for, which might not be supported by the surface.
+    pub requested_present_mode: PresentMode,
+    /// Present mode the swapchain is created with.
+    pub present_mode: vk::PresentModeKHR,
+    /// Graphics and present queue families if they are different, then images are shared CONCURRENTly between them.
+    /// Empty if they are the same family.
+    concurrent_queue_family_indices: Vec<u32>,
 }
 
 impl Swapchain {
-    pub fn new(instance: &ash::Instance, device: ash::Device, surface: surface::Surface, min_image_count: u32, width: u32, height: u32)
-    -> Swapchain {
-        let swapchain_loader = khr::Swapchain::new(instance, &device);
-        let swapchain_khr = Swapchain::create_swapchain(&swapchain_loader, &surface, min_image_count, width, height);
+    /// width and height are only used if the surface does not dictate its extent; see surface::Surface::get_extent().
+    // Note: Queue families are passed separately, since they only decide the sharing mode of the images.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: &Rc<device::Device>, surface: surface::Surface, min_image_count: u32, width: u32, height: u32,
+    requested_present_mode: PresentMode, graphics_queue_family_idx: u32, present_queue_family_idx: u32)
+    -> Result<Swapchain, RendererError> {
+        let swapchain_loader = khr::Swapchain::new(device.instance(), device);
+        let present_mode = surface.get_present_mode(requested_present_mode.to_vk());
+        println!("Swapchain present mode: {:?}", present_mode);
+        let extent = surface.get_extent(width, height)?;
+        let concurrent_queue_family_indices = if graphics_queue_family_idx == present_queue_family_idx {
+            Vec::new()
+        } else {
+            vec![graphics_queue_family_idx, present_queue_family_idx]
+        };
+        let swapchain_khr = Swapchain::create_swapchain(&swapchain_loader, &surface, min_image_count, extent, present_mode,
+            &concurrent_queue_family_indices, vk::SwapchainKHR::null())?;
 
         // Get swapchain images
-        let images = unsafe {
-            swapchain_loader.get_swapchain_images(swapchain_khr).unwrap()
+        let images = match unsafe{swapchain_loader.get_swapchain_images(swapchain_khr)} {
+            Ok(images) => images,
+            Err(result) => {
+                unsafe{swapchain_loader.destroy_swapchain(swapchain_khr, None)};
+                return Err(result.into());
+            }
         };
 
         // Swapchain's image count might be different than min_image_count we passed, so need to query how many it created
         let swapchain_image_count = images.len();
         
-        let image_views = Swapchain::create_swapchain_image_views(&images, &device, surface.format);
-
-        Swapchain {
-            device,
+        let mut swapchain = Swapchain {
+            device: device.clone(),
             surface,
             loader: swapchain_loader,
             raw: swapchain_khr,
             images,
-            image_views,
+            image_views: Vec::new(),
             image_count: swapchain_image_count as u32,
-        }
+            min_image_count,
+            extent,
+            requested_present_mode,
+            present_mode,
+            concurrent_queue_family_indices,
+        };
+        swapchain.image_views = Swapchain::create_swapchain_image_views(&swapchain.images, device, swapchain.surface.format)?;
+        Ok(swapchain)
     }
 
     /// Internal usage, use new() instead.
-    fn create_swapchain(swapchain_loader: &khr::Swapchain, surface: &surface::Surface, min_image_count: u32, width: u32, height: u32)
-    -> vk::SwapchainKHR {
+    /// old_swapchain is retired by the new swapchain, but it still has to be destroyed by the caller.
+    fn create_swapchain(swapchain_loader: &khr::Swapchain, surface: &surface::Surface, min_image_count: u32, extent: vk::Extent2D,
+    present_mode: vk::PresentModeKHR, concurrent_queue_family_indices: &[u32], old_swapchain: vk::SwapchainKHR)
+    -> Result<vk::SwapchainKHR, RendererError> {
         let swapchain_ci = vk::SwapchainCreateInfoKHR {
             s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
             p_next: ptr::null(),
@@ -51,65 +123,104 @@ impl Swapchain {
             min_image_count, // Swapchain will create images with ATLEAST requested amount or more.
             image_format: surface.format,
             image_color_space: surface.color_space,
-            image_extent: vk::Extent2D { height, width },
+            image_extent: extent,
             image_array_layers: 1, // Determines the amount of layers each image consists of.This is always 1 unless you
             // are developing a stereoscopic 3D application.
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT, // specifies what kind of operations we'll use the images
+            image_usage: Swapchain::get_image_usage(surface), // specifies what kind of operations we'll use the images
             // in the swap chain for.It is also possible that you'll render images to a separate image first to perform 
             // operations like post-processing. In that case you may use a value like VK_IMAGE_USAGE_TRANSFER_DST_BIT 
             // instead and use a memory operation to transfer the rendered image to a swap chain image.
-            image_sharing_mode: vk::SharingMode::EXCLUSIVE,
-            queue_family_index_count: 0, // This thing is set image_sharing_mode = if vk::SharingMode::CONCURRENT. For example: 
-            // If you have a different GRAPHICS and PRESENT family queues, this should be "2" with
-            // image_sharing_mode: vk::SharingMode::CONCURRENT.
-            p_queue_family_indices: ptr::null(), // This thing is set if vk::SharingMode::CONCURRENT
+            // CONCURRENT lets both GRAPHICS and PRESENT families use the images without ownership transfers, which is
+            // slower than EXCLUSIVE, so it is only used if they are different families.
+            image_sharing_mode: if concurrent_queue_family_indices.is_empty() {
+                vk::SharingMode::EXCLUSIVE
+            } else {
+                vk::SharingMode::CONCURRENT
+            },
+            queue_family_index_count: concurrent_queue_family_indices.len() as u32, // Only used if CONCURRENT.
+            p_queue_family_indices: concurrent_queue_family_indices.as_ptr(), // Only used if CONCURRENT.
             pre_transform: surface.pre_transform, // Describing the transform, relative to the presentation engine’s natural 
             // orientation, applied to the image content prior to presentation. If it does not match the currentTransform
             //  value returned by vkGetPhysicalDeviceSurfaceCapabilitiesKHR, the presentation engine will transform the 
             // image content as part of the presentation operation.
             composite_alpha: surface.composite_alpha, // Indicating the alpha compositing mode to use when this surface is
             // composited together with other surfaces on certain window systems.
-            present_mode: vk::PresentModeKHR::IMMEDIATE, // TODO: query it.
+            present_mode, // Negotiated by surface::Surface::get_present_mode().
             clipped: vk::TRUE, // specifies whether the Vulkan implementation is allowed to discard rendering operations 
             // that affect regions of the surface that are not visible.
-            old_swapchain: vk::SwapchainKHR::null(),
+            old_swapchain, // Lets the presentation engine reuse resources and finish presenting images of the old swapchain.
         };
+
         let swapchain_khr = unsafe {
-            swapchain_loader.create_swapchain(&swapchain_ci, None).unwrap()
-        };
+            swapchain_loader.create_swapchain(&swapchain_ci, None)
+        }?;
+        Ok(swapchain_khr)
+    }
 
-        swapchain_khr
+    /// TRANSFER_SRC is added if the surface supports it, so that swapchain images can be copied for screenshots.
+    fn get_image_usage(surface: &surface::Surface) -> vk::ImageUsageFlags {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | (surface.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC)
     }
 
-    pub fn recreate_swapchain(&mut self, width: u32, height: u32) {    
-        // Destroying a swapchain automatically destroys all of the swapchain images.   
-        unsafe{self.loader.destroy_swapchain(self.raw, None)};
-        self.images.clear();
-        self.destroy_image_views();
-        
-        let swapchain_khr = Swapchain::create_swapchain(&self.loader, &self.surface, self.image_count, width, height);
+    /// Returns true if swapchain images can be copied from.
+    pub fn supports_transfer_src(&self) -> bool {
+        Swapchain::get_image_usage(&self.surface).contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    }
 
-        let images = unsafe {
-            self.loader.get_swapchain_images(swapchain_khr).unwrap()
-        };
-        let swapchain_image_count = images.len();
+    /// Changes the requested present mode. Returns true if the negotiated present mode has changed, then the swapchain must
+    /// be recreated for it to take effect.
+    pub fn set_present_mode(&mut self, requested_present_mode: PresentMode) -> bool {
+        let present_mode = self.surface.get_present_mode(requested_present_mode.to_vk());
+        self.requested_present_mode = requested_present_mode;
+        if present_mode == self.present_mode {
+            return false;
+        }
+        println!("Swapchain present mode: {:?} -> {:?}", self.present_mode, present_mode);
+        self.present_mode = present_mode;
+        true
+    }
 
-        let image_views = Swapchain::create_swapchain_image_views(&images, &self.device, self.surface.format);
+    /// Creates a new swapchain from the old one, then retires the old one and its image views into deletion_queue, since
+    /// frames in flight might still be using them. width and height are only used if the surface does not dictate its extent.
+    /// If creation fails, the old swapchain is kept, so it is still destroyed on drop and recreation can be tried again.
+    pub fn recreate_swapchain(&mut self, width: u32, height: u32, deletion_queue: &mut deletion::DeletionQueue)
+    -> Result<(), RendererError> {
+        let extent = self.surface.get_extent(width, height)?;
+        let swapchain_khr = Swapchain::create_swapchain(&self.loader, &self.surface, self.min_image_count, extent,
+            self.present_mode, &self.concurrent_queue_family_indices, self.raw)?;
+        let images = match unsafe{self.loader.get_swapchain_images(swapchain_khr)} {
+            Ok(images) => images,
+            Err(result) => {
+                unsafe{self.loader.destroy_swapchain(swapchain_khr, None)};
+                return Err(result.into());
+            }
+        };
+        let image_views = match Swapchain::create_swapchain_image_views(&images, &self.device, self.surface.format) {
+            Ok(image_views) => image_views,
+            Err(error) => {
+                unsafe{self.loader.destroy_swapchain(swapchain_khr, None)};
+                return Err(error);
+            }
+        };
 
-        *self = Swapchain {
-            device: self.device.clone(),
-            surface: self.surface.clone(),
+        // Old swapchain is only retired once the new one is complete.
+        deletion_queue.retire(RetiredSwapchain {
+            _device: self.device.clone(),
             loader: self.loader.clone(),
-            raw: swapchain_khr,
-            images,
-            image_views,
-            image_count: swapchain_image_count as u32
-        }
+            raw: std::mem::replace(&mut self.raw, swapchain_khr),
+            image_views: std::mem::replace(&mut self.image_views, image_views),
+        });
+        self.images = images;
+        self.image_count = self.images.len() as u32;
+        self.extent = extent;
+        Ok(())
     }
 
-    fn create_swapchain_image_views(images: &Vec<vk::Image>, device: &ash::Device, surface_format: vk::Format) -> Vec<vk::ImageView> {
+    /// Views must be dropped before the swapchain is destroyed, since they do not own the swapchain images.
+    fn create_swapchain_image_views(images: &Vec<vk::Image>, device: &Rc<device::Device>, surface_format: vk::Format)
+    -> Result<Vec<image::ImageView>, RendererError> {
         let swapchain_image_count = images.len();
-        let mut image_views: Vec<vk::ImageView> = Vec::with_capacity(swapchain_image_count as usize);
+        let mut image_views: Vec<image::ImageView> = Vec::with_capacity(swapchain_image_count);
         for image in images {
             let image_ci = vk::ImageViewCreateInfo {
                 s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
@@ -132,21 +243,37 @@ impl Swapchain {
                     layer_count: 1,
                 }
             };
-            let image_view = unsafe {
-                device.create_image_view(&image_ci, None).unwrap()
-            };
-            image_views.push(image_view);
+            image_views.push(image::ImageView::new(device, &image_ci, None)?);
         }
 
-        image_views
+        Ok(image_views)
     }
+}
 
-    pub fn destroy_image_views(&mut self) {
-        for image_view in &self.image_views {
-            unsafe {
-                self.device.destroy_image_view(*image_view, None);
-            }
+/// Swapchain that is replaced by a recreated one, destroyed with its image views when dropped.
+struct RetiredSwapchain {
+    _device: Rc<device::Device>,
+    loader: khr::Swapchain,
+    raw: vk::SwapchainKHR,
+    image_views: Vec<image::ImageView>,
+}
+
+impl Drop for RetiredSwapchain {
+    fn drop(&mut self) {
+        // Destroying a swapchain automatically destroys all of the swapchain images.
+        self.image_views.clear();
+        unsafe {
+            self.loader.destroy_swapchain(self.raw, None);
         }
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        // Destroying a swapchain automatically destroys all of the swapchain images.
         self.image_views.clear();
+        unsafe {
+            self.loader.destroy_swapchain(self.raw, None);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,589 @@
+use ash::vk;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use super::{allocator, block_decompression, commandbuffer, cubemap, device, image, mipmap, texture_file, upload};
+use super::error::RendererError;
+extern crate image as img;
+
+/// How the texels of a texture are encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TextureColorSpace {
+    /// Colors like albedo, which are decoded to linear values by the sampler.
+    #[default]
+    Srgb,
+    /// Data that is sampled as is, like normal, roughness or metallic maps.
+    Linear,
+}
+
+impl TextureColorSpace {
+    fn rgba8_format(self) -> vk::Format {
+        match self {
+            TextureColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+            TextureColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    fn of_format(format: vk::Format) -> TextureColorSpace {
+        match format {
+            vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK |
+            vk::Format::BC2_SRGB_BLOCK | vk::Format::BC3_SRGB_BLOCK | vk::Format::BC7_SRGB_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK |
+            vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureColorSpace::Srgb,
+            // ASTC formats alternate between UNORM and SRGB, from ASTC_4X4_UNORM_BLOCK to ASTC_12X12_SRGB_BLOCK.
+            _ if (vk::Format::ASTC_4X4_SRGB_BLOCK.as_raw()..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw()).contains(&format.as_raw()) &&
+                (format.as_raw() - vk::Format::ASTC_4X4_SRGB_BLOCK.as_raw()) % 2 == 0 => TextureColorSpace::Srgb,
+            _ => TextureColorSpace::Linear,
+        }
+    }
+}
+
+/// Where the faces of a cube map are loaded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CubemapSource {
+    /// One square image file per face, in +X, -X, +Y, -Y, +Z, -Z order.
+    Faces([String; 6]),
+    /// Longitude/latitude panorama image file, which is projected onto faces of face_size x face_size texels.
+    Equirectangular { path: String, face_size: u32 },
+}
+
+/// Format that HDR and EXR textures are loaded as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatTextureFormat {
+    /// Half the memory of Rgba32Float, with enough range and precision for HDR colors.
+    #[default]
+    Rgba16Float,
+    /// Full precision, for data textures like height or displacement maps.
+    Rgba32Float,
+}
+
+impl FloatTextureFormat {
+    fn vk_format(self) -> vk::Format {
+        match self {
+            FloatTextureFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+            FloatTextureFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    /// Texel bytes of values in this format.
+    fn encode(self, values: &[f32]) -> Vec<u8> {
+        match self {
+            FloatTextureFormat::Rgba16Float => values.iter().flat_map(|value| half::f16::from_f32(*value).to_ne_bytes()).collect(),
+            FloatTextureFormat::Rgba32Float => values.iter().flat_map(|value| value.to_ne_bytes()).collect(),
+        }
+    }
+}
+
+/// Tightly packed, uncompressed RGBA texels, see Texture::from_texels().
+#[derive(Clone, Copy)]
+pub enum TexelData<'a> {
+    /// 8 bits per channel, uploaded in the color space.
+    Rgba8(&'a [u8], TextureColorSpace),
+    /// Linear floats, uploaded in the float format.
+    Rgba32F(&'a [f32], FloatTextureFormat),
+}
+
+impl TexelData<'_> {
+    /// Texel bytes in the upload format.
+    fn encode(self) -> Vec<u8> {
+        match self {
+            TexelData::Rgba8(texels, _) => texels.to_vec(),
+            TexelData::Rgba32F(values, float_format) => float_format.encode(values),
+        }
+    }
+
+    /// Downsamples each of array_layers layers into every other mip level. Returns the texel bytes of level 1 and after, with
+    /// all layers of a level one after another.
+    fn generate_mip_levels(self, width: u32, height: u32, array_layers: u32, mipmap_filter: mipmap::MipmapFilter) -> Vec<Vec<u8>> {
+        let mut levels: Vec<Vec<u8>> = vec![Vec::new(); mipmap::mip_level_count(width, height) as usize - 1];
+        let mut append_layer_levels = |layer_levels: Vec<Vec<u8>>| {
+            for (level, layer_level) in levels.iter_mut().zip(layer_levels) {
+                level.extend_from_slice(&layer_level);
+            }
+        };
+        match self {
+            TexelData::Rgba8(texels, color_space) => {
+                for layer in texels.chunks_exact(texels.len() / array_layers as usize) {
+                    append_layer_levels(mipmap::generate_rgba8_mip_levels(width, height, layer, color_space == TextureColorSpace::Srgb,
+                        mipmap_filter));
+                }
+            },
+            TexelData::Rgba32F(values, float_format) => {
+                for layer in values.chunks_exact(values.len() / array_layers as usize) {
+                    append_layer_levels(mipmap::generate_rgba32f_mip_levels(width, height, layer, mipmap_filter).iter()
+                        .map(|layer_level| float_format.encode(layer_level))
+                        .collect());
+                }
+            },
+        }
+        levels
+    }
+}
+
+/// Sampled image with its full mip chain, in SHADER_READ_ONLY_OPTIMAL layout once its upload has finished.
+pub struct Texture {
+    /// Views every mip level and array layer, it keeps the image alive. It is a cube view for cube maps and an array view
+    /// for other textures with more than one layer.
+    pub view: image::ImageView,
+    pub format: vk::Format,
+    pub color_space: TextureColorSpace,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+}
+
+impl Texture {
+    /// Loads KTX2 and DDS files with the mip levels and array layers stored in them, see from_file_data(). HDR and EXR files
+    /// are loaded as linear float_format, any other image file as 8 bits per channel RGBA in color_space, see from_texels().
+    ///
+    /// Info: KTX2 and most DDS files store their color space in their format, which is used instead of color_space.
+    pub fn load(device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager, path: &str,
+    color_space: TextureColorSpace, mipmap_filter: mipmap::MipmapFilter, float_format: FloatTextureFormat)
+    -> Result<Texture, RendererError> {
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("ktx2") => return Texture::from_file_data(device, upload_manager, path, texture_file::load_ktx2(path)?, mipmap_filter),
+            Some("dds") => return Texture::from_file_data(device, upload_manager, path, texture_file::load_dds(path, color_space)?,
+                mipmap_filter),
+            _ => {},
+        }
+        let decoded_image = decode_image_file(path)?;
+        let (width, height, texels) = match &decoded_image {
+            DecodedImage::Rgba8(image_buffer) =>
+                (image_buffer.width(), image_buffer.height(), TexelData::Rgba8(image_buffer.as_raw(), color_space)),
+            DecodedImage::Rgba32F(image_buffer) =>
+                (image_buffer.width(), image_buffer.height(), TexelData::Rgba32F(image_buffer.as_raw(), float_format)),
+        };
+        Texture::from_texels(device, upload_manager, width, height, 1, false, texels, mipmap_filter)
+    }
+
+    /// Loads a cube map from image files, see load() for the formats. If any face is an HDR or EXR file, every face is loaded
+    /// as float_format. KTX2 and DDS cube maps are loaded by load() instead.
+    pub fn load_cubemap(device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager, source: &CubemapSource,
+    color_space: TextureColorSpace, mipmap_filter: mipmap::MipmapFilter, float_format: FloatTextureFormat)
+    -> Result<Texture, RendererError> {
+        let is_srgb = color_space == TextureColorSpace::Srgb;
+        match source {
+            CubemapSource::Faces(paths) => {
+                let mut faces = Vec::with_capacity(paths.len());
+                for path in paths {
+                    let face = decode_image_file(path)?;
+                    let (width, height) = face.extent();
+                    let face_size = faces.first().map_or(width, |first: &DecodedImage| first.extent().0);
+                    if width != face_size || height != face_size {
+                        return Err(RendererError::InvalidTextureFile {
+                            path: path.clone(),
+                            reason: format!("cube map faces must be square and {}x{} like the first face", face_size, face_size),
+                        });
+                    }
+                    faces.push(face);
+                }
+                let face_size = faces[0].extent().0;
+
+                if faces.iter().all(|face| matches!(face, DecodedImage::Rgba8(_))) {
+                    let texels: Vec<u8> = faces.iter().flat_map(|face| face.to_rgba8(is_srgb)).collect();
+                    Texture::from_texels(device, upload_manager, face_size, face_size, cubemap::CUBE_FACE_COUNT, true,
+                        TexelData::Rgba8(&texels, color_space), mipmap_filter)
+                } else {
+                    let texels: Vec<f32> = faces.iter().flat_map(|face| face.to_rgba32f(is_srgb)).collect();
+                    Texture::from_texels(device, upload_manager, face_size, face_size, cubemap::CUBE_FACE_COUNT, true,
+                        TexelData::Rgba32F(&texels, float_format), mipmap_filter)
+                }
+            },
+            CubemapSource::Equirectangular { path, face_size } => {
+                let panorama = decode_image_file(path)?;
+                let (width, height) = panorama.extent();
+                let faces = cubemap::equirectangular_to_cube_faces(width, height, &panorama.to_rgba32f(is_srgb), *face_size);
+                match panorama {
+                    DecodedImage::Rgba8(_) => Texture::from_texels(device, upload_manager, *face_size, *face_size,
+                        cubemap::CUBE_FACE_COUNT, true, TexelData::Rgba8(&mipmap::encode_rgba8(&faces, is_srgb), color_space),
+                        mipmap_filter),
+                    DecodedImage::Rgba32F(_) => Texture::from_texels(device, upload_manager, *face_size, *face_size,
+                        cubemap::CUBE_FACE_COUNT, true, TexelData::Rgba32F(&faces, float_format), mipmap_filter),
+                }
+            },
+        }
+    }
+
+    /// Uploads tightly packed RGBA texels of array_layers layers, one after another, and generates the other mip levels of
+    /// every layer with mipmap_filter. Box filtered levels are blitted on the device in the same upload batch if the format
+    /// supports linear blits, otherwise every level is downsampled on the CPU and uploaded. Cube maps have 6 layers per cube.
+    /// Nothing is submitted, the texture can be sampled by graphics queue commands that are submitted after the batch.
+    // Note: Every loader ends up here, so the arguments describe any 2D, array or cube texture.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_texels(device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager, width: u32, height: u32,
+    array_layers: u32, is_cubemap: bool, texels: TexelData, mipmap_filter: mipmap::MipmapFilter) -> Result<Texture, RendererError> {
+        let mip_levels = mipmap::mip_level_count(width, height);
+        let (format, color_space) = match texels {
+            TexelData::Rgba8(_, color_space) => (color_space.rgba8_format(), color_space),
+            TexelData::Rgba32F(_, float_format) => (float_format.vk_format(), TextureColorSpace::Linear),
+        };
+        let linear_blit_features = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST |
+            vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        let is_blitting = mipmap_filter == mipmap::MipmapFilter::Box && format_features(device, format).contains(linear_blit_features);
+        if mipmap_filter == mipmap::MipmapFilter::Box && !is_blitting {
+            println!("Texture: {:?} format does not support linear blits, mip levels are downsampled on the CPU.", format);
+        }
+        let mut image_usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        if is_blitting {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let image = Rc::new(image::Image::new(device, allocator::AllocationUsage::Texture, image_create_flags(is_cubemap), width,
+            height, mip_levels, array_layers, vk::SampleCountFlags::TYPE_1, format, vk::ImageTiling::OPTIMAL, image_usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL)?);
+
+        let mut image_subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: array_layers,
+        };
+        let mut extent = vk::Extent3D {
+            width,
+            height,
+            depth: 1
+        };
+        let base_level = texels.encode();
+        if is_blitting {
+            // After doing the copy, the first mipmap level(0) becomes a read source for blit:
+            upload_manager.upload_image(image.raw, image_subresource, extent, &base_level, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ)?;
+            // Blits need a GRAPHICS queue, so they are recorded into the graphics command buffer of the batch:
+            let cmd_buffer = upload_manager.graphics_cmd_buffer()?;
+            Texture::record_mipmap_generation(device, cmd_buffer, image.raw, width, height, mip_levels, array_layers);
+        } else {
+            let levels = texels.generate_mip_levels(width, height, array_layers, mipmap_filter);
+            for (mip_level, level_texels) in std::iter::once(&base_level).chain(levels.iter()).enumerate() {
+                let (level_width, level_height) = mipmap::mip_level_extent(width, height, mip_level as u32);
+                image_subresource.mip_level = mip_level as u32;
+                extent.width = level_width;
+                extent.height = level_height;
+                upload_manager.upload_image(image.raw, image_subresource, extent, level_texels, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ)?;
+            }
+        }
+
+        let view = image.create_image_view(format, image_view_type(array_layers, is_cubemap), mip_levels, 0, array_layers,
+            vk::ImageAspectFlags::COLOR)?;
+        Ok(Texture {
+            view,
+            format,
+            color_space,
+            width,
+            height,
+            mip_levels,
+            array_layers,
+        })
+    }
+
+    /// Uploads every mip level and array layer of a texture file as is. Block formats that the device cannot sample are
+    /// decompressed on the CPU first, if block_decompression supports them. Files that ask for their mip levels to be
+    /// generated go through from_texels() with mipmap_filter, which needs RGBA8 or RGBA float texels. Nothing is submitted,
+    /// see from_texels().
+    pub fn from_file_data(device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager, path: &str,
+    mut file_data: texture_file::TextureFileData, mipmap_filter: mipmap::MipmapFilter) -> Result<Texture, RendererError> {
+        let invalid_texel_data = || RendererError::InvalidTextureFile { path: path.to_owned(), reason: "texel data is too short".to_owned() };
+        let layer_count = file_data.array_layers as usize;
+        if file_data.levels.iter().any(|level| level.is_empty() || level.len() % layer_count != 0) {
+            return Err(invalid_texel_data());
+        }
+        if image_view_type(file_data.array_layers, file_data.is_cubemap) == vk::ImageViewType::CUBE_ARRAY &&
+        !device.has_image_cube_array() {
+            return Err(RendererError::UnsupportedTextureFile {
+                path: path.to_owned(),
+                reason: format!("it is an array of {} cube maps, but the device does not support image cube arrays",
+                    file_data.array_layers / cubemap::CUBE_FACE_COUNT),
+            });
+        }
+
+        if !is_format_sampleable(device, file_data.format) {
+            let Some(decompressed_format) = block_decompression::decompressed_format(file_data.format)
+                .filter(|decompressed_format| is_format_sampleable(device, *decompressed_format)) else {
+                return Err(RendererError::UnsupportedTextureFile {
+                    path: path.to_owned(),
+                    reason: format!("{:?} format is not supported by the device, and only BC1-BC7, ETC2 and LDR ASTC blocks can \
+                        be decompressed on the CPU", file_data.format),
+                });
+            };
+            println!("Texture: {:?} format of '{}' is not supported by the device, decompressing it to {:?}.", file_data.format, path,
+                decompressed_format);
+            let texel_size = texture_file::block_info(decompressed_format).map_or(4, |(_, block_size)| block_size as usize);
+            for (level_idx, level) in file_data.levels.iter_mut().enumerate() {
+                let level_width = u32::max(file_data.width >> level_idx, 1);
+                let level_height = u32::max(file_data.height >> level_idx, 1);
+                let mut decompressed_level = Vec::with_capacity(level_width as usize * level_height as usize * texel_size * layer_count);
+                for layer in level.chunks_exact(level.len() / layer_count) {
+                    decompressed_level.extend(block_decompression::decompress(file_data.format, level_width, level_height, layer)
+                        .ok_or_else(invalid_texel_data)?);
+                }
+                *level = decompressed_level;
+            }
+            file_data.format = decompressed_format;
+        }
+
+        if file_data.generate_mip_levels {
+            let base_level = &file_data.levels[0];
+            let values: Vec<f32>;
+            let texels = match file_data.format {
+                vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM =>
+                    TexelData::Rgba8(base_level, TextureColorSpace::of_format(file_data.format)),
+                vk::Format::R16G16B16A16_SFLOAT => {
+                    values = base_level.chunks_exact(2).map(|bytes| half::f16::from_ne_bytes([bytes[0], bytes[1]]).to_f32()).collect();
+                    TexelData::Rgba32F(&values, FloatTextureFormat::Rgba16Float)
+                },
+                vk::Format::R32G32B32A32_SFLOAT => {
+                    values = base_level.chunks_exact(4).map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+                    TexelData::Rgba32F(&values, FloatTextureFormat::Rgba32Float)
+                },
+                format => return Err(RendererError::UnsupportedTextureFile {
+                    path: path.to_owned(),
+                    reason: format!("mip levels can not be generated for {:?} format, only for RGBA8 and RGBA float formats", format),
+                }),
+            };
+            return Texture::from_texels(device, upload_manager, file_data.width, file_data.height, file_data.array_layers,
+                file_data.is_cubemap, texels, mipmap_filter);
+        }
+
+        let format = file_data.format;
+        let mip_levels = file_data.levels.len() as u32;
+        let image = Rc::new(image::Image::new(device, allocator::AllocationUsage::Texture, image_create_flags(file_data.is_cubemap),
+            file_data.width, file_data.height, mip_levels, file_data.array_layers, vk::SampleCountFlags::TYPE_1, format, vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED, vk::MemoryPropertyFlags::DEVICE_LOCAL)?);
+        // Every mip level is copied with all of its layers, then made ready to be read from fragment shader:
+        for (level_idx, level) in file_data.levels.iter().enumerate() {
+            let image_subresource = vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: level_idx as u32,
+                base_array_layer: 0,
+                layer_count: file_data.array_layers,
+            };
+            let extent = vk::Extent3D {
+                width: u32::max(file_data.width >> level_idx, 1),
+                height: u32::max(file_data.height >> level_idx, 1),
+                depth: 1
+            };
+            upload_manager.upload_image(image.raw, image_subresource, extent, level, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ)?;
+        }
+
+        let view = image.create_image_view(format, image_view_type(file_data.array_layers, file_data.is_cubemap), mip_levels, 0,
+            file_data.array_layers, vk::ImageAspectFlags::COLOR)?;
+        Ok(Texture {
+            view,
+            format,
+            color_space: TextureColorSpace::of_format(format),
+            width: file_data.width,
+            height: file_data.height,
+            mip_levels,
+            array_layers: file_data.array_layers,
+        })
+    }
+
+    /// Blits every mip level of all array_layers layers from the previous one. Level 0 must be in TRANSFER_SRC_OPTIMAL layout, then all levels are
+    /// left in SHADER_READ_ONLY_OPTIMAL layout for the fragment shader. Format must support linear blits.
+    fn record_mipmap_generation(device: &ash::Device, cmd_buffer: vk::CommandBuffer, image: vk::Image, width: u32, height: u32,
+    mip_levels: u32, array_layers: u32) {
+        // mipmap_level 0 is reserved for the original size image.
+        for mipmap_level in 1..mip_levels {
+            let (src_width, src_height) = mipmap::mip_level_extent(width, height, mipmap_level - 1);
+            let (dst_width, dst_height) = mipmap::mip_level_extent(width, height, mipmap_level);
+            let image_blit = vk::ImageBlit {
+                src_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: mipmap_level - 1,
+                    base_array_layer: 0,
+                    layer_count: array_layers,
+                },
+                src_offsets: [
+                    vk::Offset3D {x: 0, y: 0, z: 0},
+                    vk::Offset3D {
+                        x: src_width as i32,
+                        y: src_height as i32,
+                        z: 1
+                    }],
+                dst_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: mipmap_level,
+                    base_array_layer: 0,
+                    layer_count: array_layers,
+                },
+                dst_offsets: [
+                    vk::Offset3D {x: 0, y: 0, z: 0},
+                    vk::Offset3D {
+                        x: dst_width as i32,
+                        y: dst_height as i32,
+                        z: 1
+                    }],
+            };
+            // This mipmap level will have undefined layout and no access flag prior so make it ready for dst write
+            let image_subresource_range = vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: mipmap_level,   // Starting from this mipmap_level...
+                level_count: 1, // ... just get this many level into the image view.
+                base_array_layer: 0,
+                layer_count: array_layers,
+            };
+            commandbuffer::transition_image_layout(device, cmd_buffer, image, image_subresource_range,
+                vk::ImageLayout::UNDEFINED,         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),           vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TRANSFER,   vk::PipelineStageFlags::TRANSFER);
+            unsafe {
+                device.cmd_blit_image(cmd_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[image_blit], vk::Filter::LINEAR);
+            }
+            // The newly blitted mipmap level becomes the src read for the next loop:
+            commandbuffer::transition_image_layout(device, cmd_buffer, image, image_subresource_range,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,       vk::AccessFlags::TRANSFER_READ,
+                vk::PipelineStageFlags::TRANSFER,      vk::PipelineStageFlags::TRANSFER);
+        }
+        // Make all mipmap levels ready to be read from fragment shader:
+        let image_subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: mip_levels,
+            base_array_layer: 0,
+            layer_count: array_layers,
+        };
+        commandbuffer::transition_image_layout(device, cmd_buffer, image, image_subresource_range,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,  vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,         vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,       vk::PipelineStageFlags::FRAGMENT_SHADER);
+    }
+}
+
+/// Decoded image file. Float images, like the ones in HDR and EXR files, keep their values.
+enum DecodedImage {
+    Rgba8(img::RgbaImage),
+    Rgba32F(img::Rgba32FImage),
+}
+
+impl DecodedImage {
+    fn extent(&self) -> (u32, u32) {
+        match self {
+            DecodedImage::Rgba8(image_buffer) => image_buffer.dimensions(),
+            DecodedImage::Rgba32F(image_buffer) => image_buffer.dimensions(),
+        }
+    }
+
+    /// Float images are clamped to [0, 1].
+    fn to_rgba8(&self, is_srgb: bool) -> Vec<u8> {
+        match self {
+            DecodedImage::Rgba8(image_buffer) => image_buffer.as_raw().clone(),
+            DecodedImage::Rgba32F(image_buffer) => mipmap::encode_rgba8(image_buffer.as_raw(), is_srgb),
+        }
+    }
+
+    /// Linear values, the color channels of sRGB images are decoded.
+    fn to_rgba32f(&self, is_srgb: bool) -> Vec<f32> {
+        match self {
+            DecodedImage::Rgba8(image_buffer) => mipmap::decode_rgba8(image_buffer.as_raw(), is_srgb),
+            DecodedImage::Rgba32F(image_buffer) => image_buffer.as_raw().clone(),
+        }
+    }
+}
+
+fn decode_image_file(path: &str) -> Result<DecodedImage, RendererError> {
+    let dynamic_image = img::io::Reader::open(path)
+        .map_err(img::ImageError::IoError)
+        .and_then(|image_reader| image_reader.decode())
+        .map_err(|error| RendererError::TextureFile { path: path.to_owned(), error })?;
+    Ok(match dynamic_image {
+        img::DynamicImage::ImageRgb32F(_) | img::DynamicImage::ImageRgba32F(_) => DecodedImage::Rgba32F(dynamic_image.into_rgba32f()),
+        _ => DecodedImage::Rgba8(dynamic_image.into_rgba8()),
+    })
+}
+
+fn image_create_flags(is_cubemap: bool) -> vk::ImageCreateFlags {
+    if is_cubemap { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() }
+}
+
+/// Views cube maps as cubes and textures with more than one layer as arrays. CUBE_ARRAY views need the image cube array
+/// feature, see device::Device::has_image_cube_array().
+fn image_view_type(array_layers: u32, is_cubemap: bool) -> vk::ImageViewType {
+    match (is_cubemap, array_layers) {
+        (true, cubemap::CUBE_FACE_COUNT) => vk::ImageViewType::CUBE,
+        (true, _) => vk::ImageViewType::CUBE_ARRAY,
+        (false, 1) => vk::ImageViewType::TYPE_2D,
+        (false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+    }
+}
+
+/// Features of format with optimal tiling.
+fn format_features(device: &device::Device, format: vk::Format) -> vk::FormatFeatureFlags {
+    let format_properties = unsafe {
+        device.instance().get_physical_device_format_properties(device.physical_device(), format)
+    };
+    format_properties.optimal_tiling_features
+}
+
+fn is_format_sampleable(device: &device::Device, format: vk::Format) -> bool {
+    format_features(device, format).contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+}
+
+/// Files that a cached texture is loaded from.
+#[derive(PartialEq, Eq, Hash)]
+enum TextureKey {
+    File(PathBuf),
+    CubemapFaces(Vec<PathBuf>),
+    Equirectangular(PathBuf, u32),
+}
+
+/// Different spellings of the same file share the texture, missing files fail while loading.
+fn canonical_path(path: &str) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// Loads every texture file once and hands out shared handles to it. Textures stay cached for as long as the cache lives.
+pub struct TextureCache {
+    textures: HashMap<(TextureKey, TextureColorSpace), Rc<Texture>>,
+    /// Mip levels of the textures that are loaded without them are generated with this.
+    mipmap_filter: mipmap::MipmapFilter,
+    float_format: FloatTextureFormat,
+}
+
+impl TextureCache {
+    pub fn new(mipmap_filter: mipmap::MipmapFilter, float_format: FloatTextureFormat) -> TextureCache {
+        TextureCache {
+            textures: HashMap::new(),
+            mipmap_filter,
+            float_format,
+        }
+    }
+
+    /// Returns the cached texture of path, or loads it through upload_manager if it is not cached yet; see Texture::load().
+    /// The same file loaded as Srgb and as Linear are two different textures.
+    pub fn get_or_load(&mut self, device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager, path: &str,
+    color_space: TextureColorSpace) -> Result<Rc<Texture>, RendererError> {
+        let key = (TextureKey::File(canonical_path(path)), color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = Rc::new(Texture::load(device, upload_manager, path, color_space, self.mipmap_filter, self.float_format)?);
+        if texture.color_space != color_space {
+            println!("Texture cache: '{}' is stored as {:?} instead of the requested {:?}.", path, texture.color_space, color_space);
+        }
+        println!("Texture cache: loaded '{}' as {:?} ({:?}), {}x{} with {} mip levels and {} layers.", path, texture.color_space,
+            texture.format, texture.width, texture.height, texture.mip_levels, texture.array_layers);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    /// Cube map version of get_or_load(), see Texture::load_cubemap().
+    pub fn get_or_load_cubemap(&mut self, device: &Rc<device::Device>, upload_manager: &mut upload::UploadManager,
+    source: &CubemapSource, color_space: TextureColorSpace) -> Result<Rc<Texture>, RendererError> {
+        let key = match source {
+            CubemapSource::Faces(paths) => TextureKey::CubemapFaces(paths.iter().map(|path| canonical_path(path)).collect()),
+            CubemapSource::Equirectangular { path, face_size } => TextureKey::Equirectangular(canonical_path(path), *face_size),
+        };
+        let key = (key, color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = Rc::new(Texture::load_cubemap(device, upload_manager, source, color_space, self.mipmap_filter, self.float_format)?);
+        println!("Texture cache: loaded cube map {:?} as {:?}, {}x{} faces with {} mip levels.", source, color_space, texture.width,
+            texture.height, texture.mip_levels);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+}
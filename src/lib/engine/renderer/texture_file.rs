@@ -0,0 +1,256 @@
+use ash::vk;
+use std::io::Read;
+use super::error::RendererError;
+use super::texture::TextureColorSpace;
+
+/// Texels of a KTX2 or DDS file, with the mip levels and array layers that are stored in it.
+pub struct TextureFileData {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// 6 per cube for cube maps.
+    pub array_layers: u32,
+    pub is_cubemap: bool,
+    /// Every array layer of a mip level is stored one after another, in the layout that a single buffer to image copy of all
+    /// layers expects. Level 0 is the full size one.
+    pub levels: Vec<Vec<u8>>,
+    /// The file only stores level 0 and asks for the other mip levels to be generated, like KTX2 files with a level count of 0.
+    pub generate_mip_levels: bool,
+}
+
+/// First and last format of a range of formats with the same texel block extent and size in bytes, in VkFormat order.
+const FORMAT_BLOCKS: [(vk::Format, vk::Format, (u32, u32), u32); 37] = [
+    (vk::Format::R4G4_UNORM_PACK8, vk::Format::R4G4_UNORM_PACK8, (1, 1), 1),
+    (vk::Format::R4G4B4A4_UNORM_PACK16, vk::Format::A1R5G5B5_UNORM_PACK16, (1, 1), 2),
+    (vk::Format::R8_UNORM, vk::Format::R8_SRGB, (1, 1), 1),
+    (vk::Format::R8G8_UNORM, vk::Format::R8G8_SRGB, (1, 1), 2),
+    (vk::Format::R8G8B8_UNORM, vk::Format::B8G8R8_SRGB, (1, 1), 3),
+    (vk::Format::R8G8B8A8_UNORM, vk::Format::A2B10G10R10_SINT_PACK32, (1, 1), 4),
+    (vk::Format::R16_UNORM, vk::Format::R16_SFLOAT, (1, 1), 2),
+    (vk::Format::R16G16_UNORM, vk::Format::R16G16_SFLOAT, (1, 1), 4),
+    (vk::Format::R16G16B16_UNORM, vk::Format::R16G16B16_SFLOAT, (1, 1), 6),
+    (vk::Format::R16G16B16A16_UNORM, vk::Format::R16G16B16A16_SFLOAT, (1, 1), 8),
+    (vk::Format::R32_UINT, vk::Format::R32_SFLOAT, (1, 1), 4),
+    (vk::Format::R32G32_UINT, vk::Format::R32G32_SFLOAT, (1, 1), 8),
+    (vk::Format::R32G32B32_UINT, vk::Format::R32G32B32_SFLOAT, (1, 1), 12),
+    (vk::Format::R32G32B32A32_UINT, vk::Format::R32G32B32A32_SFLOAT, (1, 1), 16),
+    (vk::Format::B10G11R11_UFLOAT_PACK32, vk::Format::E5B9G9R9_UFLOAT_PACK32, (1, 1), 4),
+    (vk::Format::BC1_RGB_UNORM_BLOCK, vk::Format::BC1_RGBA_SRGB_BLOCK, (4, 4), 8),
+    (vk::Format::BC2_UNORM_BLOCK, vk::Format::BC3_SRGB_BLOCK, (4, 4), 16),
+    (vk::Format::BC4_UNORM_BLOCK, vk::Format::BC4_SNORM_BLOCK, (4, 4), 8),
+    (vk::Format::BC5_UNORM_BLOCK, vk::Format::BC7_SRGB_BLOCK, (4, 4), 16),
+    (vk::Format::ETC2_R8G8B8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK, (4, 4), 8),
+    (vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK, (4, 4), 16),
+    (vk::Format::EAC_R11_UNORM_BLOCK, vk::Format::EAC_R11_SNORM_BLOCK, (4, 4), 8),
+    (vk::Format::EAC_R11G11_UNORM_BLOCK, vk::Format::EAC_R11G11_SNORM_BLOCK, (4, 4), 16),
+    (vk::Format::ASTC_4X4_UNORM_BLOCK, vk::Format::ASTC_4X4_SRGB_BLOCK, (4, 4), 16),
+    (vk::Format::ASTC_5X4_UNORM_BLOCK, vk::Format::ASTC_5X4_SRGB_BLOCK, (5, 4), 16),
+    (vk::Format::ASTC_5X5_UNORM_BLOCK, vk::Format::ASTC_5X5_SRGB_BLOCK, (5, 5), 16),
+    (vk::Format::ASTC_6X5_UNORM_BLOCK, vk::Format::ASTC_6X5_SRGB_BLOCK, (6, 5), 16),
+    (vk::Format::ASTC_6X6_UNORM_BLOCK, vk::Format::ASTC_6X6_SRGB_BLOCK, (6, 6), 16),
+    (vk::Format::ASTC_8X5_UNORM_BLOCK, vk::Format::ASTC_8X5_SRGB_BLOCK, (8, 5), 16),
+    (vk::Format::ASTC_8X6_UNORM_BLOCK, vk::Format::ASTC_8X6_SRGB_BLOCK, (8, 6), 16),
+    (vk::Format::ASTC_8X8_UNORM_BLOCK, vk::Format::ASTC_8X8_SRGB_BLOCK, (8, 8), 16),
+    (vk::Format::ASTC_10X5_UNORM_BLOCK, vk::Format::ASTC_10X5_SRGB_BLOCK, (10, 5), 16),
+    (vk::Format::ASTC_10X6_UNORM_BLOCK, vk::Format::ASTC_10X6_SRGB_BLOCK, (10, 6), 16),
+    (vk::Format::ASTC_10X8_UNORM_BLOCK, vk::Format::ASTC_10X8_SRGB_BLOCK, (10, 8), 16),
+    (vk::Format::ASTC_10X10_UNORM_BLOCK, vk::Format::ASTC_10X10_SRGB_BLOCK, (10, 10), 16),
+    (vk::Format::ASTC_12X10_UNORM_BLOCK, vk::Format::ASTC_12X10_SRGB_BLOCK, (12, 10), 16),
+    (vk::Format::ASTC_12X12_UNORM_BLOCK, vk::Format::ASTC_12X12_SRGB_BLOCK, (12, 12), 16),
+];
+
+/// Texel block extent and size in bytes of the color formats that texture files can be loaded as. None for the depth and
+/// stencil, 64 bit and extension formats.
+pub fn block_info(format: vk::Format) -> Option<((u32, u32), u32)> {
+    FORMAT_BLOCKS.iter()
+        .find(|(first, last, _, _)| (first.as_raw()..=last.as_raw()).contains(&format.as_raw()))
+        .map(|(_, _, block_extent, block_size)| (*block_extent, *block_size))
+}
+
+fn unsupported_format(path: &str, format: vk::Format) -> RendererError {
+    RendererError::UnsupportedTextureFile { path: path.to_owned(), reason: format!("{:?} format is not supported", format) }
+}
+
+/// Size in bytes of an array layer of a mip level that is width x height texels.
+fn layer_size(block_extent: (u32, u32), block_size: u32, width: u32, height: u32) -> usize {
+    (width.div_ceil(block_extent.0) * height.div_ceil(block_extent.1) * block_size) as usize
+}
+
+/// Loads KTX2 files. Zstandard and ZLIB supercompressed levels are inflated here, so they are uploaded as is. Files with a
+/// level count of 0 only store level 0, the other mip levels are generated for them.
+///
+/// Info: Basis Universal files are not supported, neither BasisLZ/ETC1S nor UASTC ones. They have to be transcoded to a
+/// Vulkan format, like a BC or ASTC one, before they are loaded. There is no transcoder in the engine, see README.md.
+pub fn load_ktx2(path: &str) -> Result<TextureFileData, RendererError> {
+    let bytes = read_file(path)?;
+    let reader = ktx2::Reader::new(bytes.as_slice()).map_err(|error| RendererError::KtxFile { path: path.to_owned(), error })?;
+    let header = reader.header();
+
+    // Basis Universal files are the only ones without a Vulkan format. ETC1S is always BasisLZ supercompressed.
+    let Some(format) = header.format else {
+        let encoding = if header.supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ) { "BasisLZ/ETC1S" } else { "UASTC" };
+        return Err(RendererError::UnsupportedTextureFile {
+            path: path.to_owned(),
+            reason: format!("{} Basis Universal textures can not be transcoded, transcode them to a Vulkan format first", encoding),
+        });
+    };
+    if header.pixel_depth > 1 {
+        return Err(RendererError::UnsupportedTextureFile { path: path.to_owned(), reason: "3D textures are not supported".to_owned() });
+    }
+    let format = vk::Format::from_raw(format.0.get() as i32);
+    let (block_extent, block_size) = block_info(format).ok_or_else(|| unsupported_format(path, format))?;
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    let array_layers = header.layer_count.max(1) * header.face_count;
+
+    let mut levels = Vec::with_capacity(reader.levels().len());
+    for (level_idx, level) in reader.levels().enumerate() {
+        let level = match header.supercompression_scheme {
+            None => level.to_vec(),
+            Some(ktx2::SupercompressionScheme::Zstandard) => {
+                let mut inflated = Vec::new();
+                ruzstd::StreamingDecoder::new(level)
+                    .map_err(|error| error.to_string())
+                    .and_then(|mut decoder| decoder.read_to_end(&mut inflated).map_err(|error| error.to_string()))
+                    .map_err(|reason| RendererError::InvalidTextureFile { path: path.to_owned(), reason })?;
+                inflated
+            },
+            Some(ktx2::SupercompressionScheme::ZLIB) => {
+                let mut inflated = Vec::new();
+                flate2::read::ZlibDecoder::new(level).read_to_end(&mut inflated)
+                    .map_err(|error| RendererError::InvalidTextureFile { path: path.to_owned(), reason: error.to_string() })?;
+                inflated
+            },
+            Some(scheme) => return Err(RendererError::UnsupportedTextureFile {
+                path: path.to_owned(),
+                reason: format!("{:?} supercompression is not supported", scheme),
+            }),
+        };
+        let level_size = layer_size(block_extent, block_size, u32::max(width >> level_idx, 1), u32::max(height >> level_idx, 1)) *
+            array_layers as usize;
+        if level.len() != level_size {
+            return Err(RendererError::InvalidTextureFile {
+                path: path.to_owned(),
+                reason: format!("mip level {} is {} bytes instead of {}", level_idx, level.len(), level_size),
+            });
+        }
+        levels.push(level);
+    }
+
+    Ok(TextureFileData {
+        format,
+        width,
+        height,
+        array_layers,
+        is_cubemap: header.face_count == 6,
+        levels,
+        generate_mip_levels: header.level_count == 0,
+    })
+}
+
+/// Loads DDS files, both the legacy ones and the ones with a DX10 header. Legacy files do not store their color space, so
+/// they are loaded as color_space.
+pub fn load_dds(path: &str, color_space: TextureColorSpace) -> Result<TextureFileData, RendererError> {
+    let bytes = read_file(path)?;
+    let dds = ddsfile::Dds::read(bytes.as_slice()).map_err(|error| RendererError::DdsFile { path: path.to_owned(), error })?;
+    let Some(format) = dds.get_dxgi_format().and_then(dxgi_to_vk_format).or_else(|| dds.get_d3d_format().and_then(|format| d3d_to_vk_format(format, color_space)))
+    else {
+        let format_name = dds.get_dxgi_format().map(|format| format!("{:?}", format))
+            .or_else(|| dds.get_d3d_format().map(|format| format!("{:?}", format)))
+            .unwrap_or_else(|| "unknown".to_owned());
+        return Err(RendererError::UnsupportedTextureFile { path: path.to_owned(), reason: format!("{} format is not supported", format_name) });
+    };
+    if dds.get_depth() > 1 {
+        return Err(RendererError::UnsupportedTextureFile { path: path.to_owned(), reason: "3D textures are not supported".to_owned() });
+    }
+    let (block_extent, block_size) = block_info(format).ok_or_else(|| unsupported_format(path, format))?;
+
+    // DDS files store the whole mip chain of a layer before the next layer, so the layers are gathered per level.
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let is_cubemap = match &dds.header10 {
+        Some(header10) => header10.misc_flag.contains(ddsfile::MiscFlag::TEXTURECUBE),
+        None => dds.header.caps2.contains(ddsfile::Caps2::CUBEMAP),
+    };
+    // DX10 headers count cubes instead of faces.
+    let array_layers = match &dds.header10 {
+        Some(header10) if is_cubemap => header10.array_size * 6,
+        _ => dds.get_num_array_layers(),
+    };
+    let mip_levels = dds.get_num_mipmap_levels();
+    let level_sizes: Vec<usize> = (0..mip_levels)
+        .map(|level| layer_size(block_extent, block_size, u32::max(width >> level, 1), u32::max(height >> level, 1)))
+        .collect();
+    let layer_stride: usize = level_sizes.iter().sum();
+    if dds.data.len() < layer_stride * array_layers as usize {
+        return Err(RendererError::InvalidTextureFile { path: path.to_owned(), reason: "texel data is too short".to_owned() });
+    }
+
+    let mut levels: Vec<Vec<u8>> = level_sizes.iter().map(|level_size| Vec::with_capacity(level_size * array_layers as usize)).collect();
+    for layer in 0..array_layers as usize {
+        let mut offset = layer * layer_stride;
+        for (level, level_size) in level_sizes.iter().enumerate() {
+            levels[level].extend_from_slice(&dds.data[offset..offset + level_size]);
+            offset += level_size;
+        }
+    }
+
+    Ok(TextureFileData {
+        format,
+        width,
+        height,
+        array_layers,
+        is_cubemap,
+        levels,
+        generate_mip_levels: false,
+    })
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, RendererError> {
+    std::fs::read(path).map_err(|error| RendererError::InvalidTextureFile { path: path.to_owned(), reason: error.to_string() })
+}
+
+fn dxgi_to_vk_format(format: ddsfile::DxgiFormat) -> Option<vk::Format> {
+    use ddsfile::DxgiFormat;
+    match format {
+        DxgiFormat::R8G8B8A8_UNorm => Some(vk::Format::R8G8B8A8_UNORM),
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => Some(vk::Format::R8G8B8A8_SRGB),
+        DxgiFormat::B8G8R8A8_UNorm => Some(vk::Format::B8G8R8A8_UNORM),
+        DxgiFormat::B8G8R8A8_UNorm_sRGB => Some(vk::Format::B8G8R8A8_SRGB),
+        DxgiFormat::R16G16B16A16_Float => Some(vk::Format::R16G16B16A16_SFLOAT),
+        DxgiFormat::R32G32B32A32_Float => Some(vk::Format::R32G32B32A32_SFLOAT),
+        DxgiFormat::BC1_UNorm => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        DxgiFormat::BC1_UNorm_sRGB => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        DxgiFormat::BC2_UNorm => Some(vk::Format::BC2_UNORM_BLOCK),
+        DxgiFormat::BC2_UNorm_sRGB => Some(vk::Format::BC2_SRGB_BLOCK),
+        DxgiFormat::BC3_UNorm => Some(vk::Format::BC3_UNORM_BLOCK),
+        DxgiFormat::BC3_UNorm_sRGB => Some(vk::Format::BC3_SRGB_BLOCK),
+        DxgiFormat::BC4_UNorm => Some(vk::Format::BC4_UNORM_BLOCK),
+        DxgiFormat::BC4_SNorm => Some(vk::Format::BC4_SNORM_BLOCK),
+        DxgiFormat::BC5_UNorm => Some(vk::Format::BC5_UNORM_BLOCK),
+        DxgiFormat::BC5_SNorm => Some(vk::Format::BC5_SNORM_BLOCK),
+        DxgiFormat::BC6H_UF16 => Some(vk::Format::BC6H_UFLOAT_BLOCK),
+        DxgiFormat::BC6H_SF16 => Some(vk::Format::BC6H_SFLOAT_BLOCK),
+        DxgiFormat::BC7_UNorm => Some(vk::Format::BC7_UNORM_BLOCK),
+        DxgiFormat::BC7_UNorm_sRGB => Some(vk::Format::BC7_SRGB_BLOCK),
+        _ => None,
+    }
+}
+
+fn d3d_to_vk_format(format: ddsfile::D3DFormat, color_space: TextureColorSpace) -> Option<vk::Format> {
+    use ddsfile::D3DFormat;
+    let is_srgb = color_space == TextureColorSpace::Srgb;
+    match format {
+        D3DFormat::A8B8G8R8 if is_srgb => Some(vk::Format::R8G8B8A8_SRGB),
+        D3DFormat::A8B8G8R8 => Some(vk::Format::R8G8B8A8_UNORM),
+        D3DFormat::A8R8G8B8 if is_srgb => Some(vk::Format::B8G8R8A8_SRGB),
+        D3DFormat::A8R8G8B8 => Some(vk::Format::B8G8R8A8_UNORM),
+        D3DFormat::A16B16G16R16F => Some(vk::Format::R16G16B16A16_SFLOAT),
+        D3DFormat::A32B32G32R32F => Some(vk::Format::R32G32B32A32_SFLOAT),
+        D3DFormat::DXT1 if is_srgb => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        D3DFormat::DXT1 => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        D3DFormat::DXT3 if is_srgb => Some(vk::Format::BC2_SRGB_BLOCK),
+        D3DFormat::DXT3 => Some(vk::Format::BC2_UNORM_BLOCK),
+        D3DFormat::DXT5 if is_srgb => Some(vk::Format::BC3_SRGB_BLOCK),
+        D3DFormat::DXT5 => Some(vk::Format::BC3_UNORM_BLOCK),
+        _ => None,
+    }
+}
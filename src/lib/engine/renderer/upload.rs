@@ -0,0 +1,647 @@
+use ash::vk;
+use std::collections::VecDeque;
+use std::ptr;
+use std::rc::Rc;
+use super::{allocator, buffer, commandbuffer, device};
+use super::error::RendererError;
+
+/// Staging ranges are aligned to this, which covers the texel and block sizes of every format and optimal copy offsets.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+/// Pipeline stages that read uploaded data on the graphics queue.
+const CONSUMER_STAGE_MASK: vk::PipelineStageFlags = vk::PipelineStageFlags::from_raw(
+    vk::PipelineStageFlags::VERTEX_INPUT.as_raw() | vk::PipelineStageFlags::VERTEX_SHADER.as_raw() |
+    vk::PipelineStageFlags::FRAGMENT_SHADER.as_raw() | vk::PipelineStageFlags::TRANSFER.as_raw());
+/// Accesses of CONSUMER_STAGE_MASK that read uploaded data.
+const CONSUMER_ACCESS_MASK: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::VERTEX_ATTRIBUTE_READ.as_raw() | vk::AccessFlags::INDEX_READ.as_raw() | vk::AccessFlags::UNIFORM_READ.as_raw() |
+    vk::AccessFlags::SHADER_READ.as_raw() | vk::AccessFlags::TRANSFER_READ.as_raw());
+
+/// Identifies a submitted batch of uploads, see UploadManager::flush().
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UploadId(u64);
+
+/// Command buffers and synchronization objects of a batch, which are reused by later batches once it has finished.
+#[derive(Clone, Copy)]
+struct BatchObjects {
+    /// Copies are recorded into this, it is submitted to the transfer queue.
+    transfer_cmd_buffer: vk::CommandBuffer,
+    /// Ownership acquire barriers and graphics work are recorded into this, it is submitted to the graphics queue after
+    /// transfer_cmd_buffer. Null if the transfer and graphics families are the same, transfer_cmd_buffer is used instead.
+    graphics_cmd_buffer: vk::CommandBuffer,
+    /// Signaled by the transfer submission, waited for by the graphics submission. Null if there is no graphics_cmd_buffer.
+    semaphore: vk::Semaphore,
+    /// Signaled by the last submission of the batch.
+    fence: vk::Fence,
+}
+
+struct UploadBatch {
+    id: UploadId,
+    objects: BatchObjects,
+    /// True if any staging range of the ring buffer belongs to this batch.
+    uses_ring: bool,
+    /// Ring head after the last staging range of this batch. Ring tail moves here once the batch has finished.
+    ring_end: vk::DeviceSize,
+    /// Staging buffers of uploads that are bigger than the ring buffer, dropped once the batch has finished.
+    oversized_staging_buffers: Vec<buffer::Buffer>,
+}
+
+/// Uploads host data into device local buffers and images through a persistently mapped staging ring buffer. Copies are
+/// recorded into a batch until flush() submits them all at once with a fence, so nothing blocks while frames keep
+/// rendering. Staging ranges of a batch are reused once its fence is signaled, see collect_finished().
+///
+/// If there is a transfer family without GRAPHICS, copies are submitted to its queue and each resource is released to the
+/// graphics family right after its copy. Then the graphics queue acquires them in a second submission that waits on a
+/// semaphore, which also runs the graphics work of the batch, see graphics_cmd_buffer(). Otherwise everything is submitted to
+/// the graphics queue as a single command buffer that ends with a memory barrier.
+///
+/// Info: Either way, the graphics queue runs a barrier after the copies, so every command submitted to it after flush()
+/// reads the uploaded data; waiting on the fence is only needed before touching the data on the host.
+pub struct UploadManager {
+    device: Rc<device::Device>,
+    transfer_queue: vk::Queue,
+    transfer_queue_family_idx: u32,
+    graphics_queue: vk::Queue,
+    graphics_queue_family_idx: u32,
+    transfer_cmd_pool: device::CommandPool,
+    /// None if the transfer and graphics families are the same.
+    graphics_cmd_pool: Option<device::CommandPool>,
+    staging_buffer: buffer::Buffer,
+    staging_mapped_ptr: *mut u8,
+    /// Staging ranges of staging_buffer.
+    ring: StagingRing,
+    /// Batch that copies are currently recorded into, begun lazily.
+    recording_batch: Option<UploadBatch>,
+    /// Submitted batches in submission order.
+    in_flight_batches: VecDeque<UploadBatch>,
+    /// Objects of finished batches, which are reused by the next batches.
+    free_batch_objects: Vec<BatchObjects>,
+    next_batch_id: u64,
+    /// Every batch with an id lower than or equal to this has finished.
+    last_finished_batch_id: u64,
+}
+
+impl UploadManager {
+    /// transfer_queue_family_idx can be the same family as graphics_queue_family_idx, then there are no ownership transfers.
+    pub fn new(device: &Rc<device::Device>, transfer_queue: vk::Queue, transfer_queue_family_idx: u32,
+    graphics_queue: vk::Queue, graphics_queue_family_idx: u32, staging_buffer_size: vk::DeviceSize)
+    -> Result<UploadManager, RendererError> {
+        let transfer_cmd_pool = UploadManager::create_cmd_pool(device, transfer_queue_family_idx)?;
+        let graphics_cmd_pool = if transfer_queue_family_idx == graphics_queue_family_idx {
+            None
+        } else {
+            Some(UploadManager::create_cmd_pool(device, graphics_queue_family_idx)?)
+        };
+
+        // Only the transfer queue reads the staging buffer.
+        let staging_buffer = buffer::Buffer::new(device, allocator::AllocationUsage::Staging, staging_buffer_size,
+            vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &transfer_queue_family_idx)?;
+        let staging_mapped_ptr = staging_buffer.mapped_ptr().expect("Staging buffer must be HOST_VISIBLE!");
+        println!("Upload manager: staging ring buffer is {} MiB, uploads are submitted to queue family {}.",
+            staging_buffer_size / (1024 * 1024), transfer_queue_family_idx);
+
+        Ok(UploadManager {
+            device: device.clone(),
+            transfer_queue,
+            transfer_queue_family_idx,
+            graphics_queue,
+            graphics_queue_family_idx,
+            transfer_cmd_pool,
+            graphics_cmd_pool,
+            staging_buffer,
+            staging_mapped_ptr,
+            ring: StagingRing::new(staging_buffer_size),
+            recording_batch: None,
+            in_flight_batches: VecDeque::new(),
+            free_batch_objects: Vec::new(),
+            next_batch_id: 1,
+            last_finished_batch_id: 0,
+        })
+    }
+
+    /// Returns the command buffer of the recording batch that is submitted to the graphics queue, so that graphics work like
+    /// mipmap generation can be recorded in between uploads. It runs after the copies of the batch that are recorded
+    /// before, and after the uploaded resources are acquired by the graphics family. The returned command buffer must not
+    /// be used after the next call to any other function of the upload manager, since the batch might get flushed.
+    pub fn graphics_cmd_buffer(&mut self) -> Result<vk::CommandBuffer, RendererError> {
+        let objects = self.begin_batch()?.objects;
+        Ok(if self.has_ownership_transfers() {objects.graphics_cmd_buffer} else {objects.transfer_cmd_buffer})
+    }
+
+    /// True if uploads are submitted to a different queue family than the graphics family.
+    fn has_ownership_transfers(&self) -> bool {
+        self.transfer_queue_family_idx != self.graphics_queue_family_idx
+    }
+
+    /// Records a copy of data into dst_buffer at dst_offset.
+    pub fn upload_buffer<T>(&mut self, dst_buffer: vk::Buffer,
+    dst_offset: vk::DeviceSize, data: &[T]) -> Result<(), RendererError> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let (src_buffer, src_offset) = self.stage(data.as_ptr() as *const u8, size)?;
+        let copy_region = vk::BufferCopy {
+            src_offset,
+            dst_offset,
+            size,
+        };
+        let objects = self.begin_batch()?.objects;
+        unsafe {
+            self.device.cmd_copy_buffer(objects.transfer_cmd_buffer, src_buffer, dst_buffer, &[copy_region]);
+        }
+
+        if self.has_ownership_transfers() {
+            // Info: Release and acquire barriers must match, except for the access and stage masks of the other queue.
+            let mut buffer_memory_barrier = vk::BufferMemoryBarrier {
+                s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                p_next: ptr::null(),
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::empty(),
+                src_queue_family_index: self.transfer_queue_family_idx,
+                dst_queue_family_index: self.graphics_queue_family_idx,
+                buffer: dst_buffer,
+                offset: dst_offset,
+                size,
+            };
+            unsafe {
+                self.device.cmd_pipeline_barrier(objects.transfer_cmd_buffer, vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::DependencyFlags::empty(), &[], &[buffer_memory_barrier], &[]);
+            }
+            buffer_memory_barrier.src_access_mask = vk::AccessFlags::empty();
+            buffer_memory_barrier.dst_access_mask = CONSUMER_ACCESS_MASK;
+            unsafe {
+                self.device.cmd_pipeline_barrier(objects.graphics_cmd_buffer, vk::PipelineStageFlags::ALL_COMMANDS, CONSUMER_STAGE_MASK,
+                    vk::DependencyFlags::empty(), &[], &[buffer_memory_barrier], &[]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a copy of tightly packed texels into the given subresource of dst_image. Previous contents of the subresource
+    /// are discarded. It is then transitioned into final_layout for the graphics queue commands in dst_stage_mask, which
+    /// access it with dst_access_mask.
+    // Note: Arguments are the copy region and the barrier that hands the image over to the graphics queue.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_image(&mut self, dst_image: vk::Image, image_subresource: vk::ImageSubresourceLayers, extent: vk::Extent3D, data: &[u8], final_layout: vk::ImageLayout,
+    dst_stage_mask: vk::PipelineStageFlags, dst_access_mask: vk::AccessFlags) -> Result<(), RendererError> {
+        let (src_buffer, src_offset) = self.stage(data.as_ptr(), data.len() as vk::DeviceSize)?;
+        let image_subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: image_subresource.aspect_mask,
+            base_mip_level: image_subresource.mip_level,
+            level_count: 1,
+            base_array_layer: image_subresource.base_array_layer,
+            layer_count: image_subresource.layer_count,
+        };
+        let buffer_image_copy = vk::BufferImageCopy {
+            buffer_offset: src_offset,
+            buffer_row_length: 0, // 0 means tightly packed according to image_extent.
+            buffer_image_height: 0,
+            image_subresource,
+            image_offset: vk::Offset3D {
+                ..Default::default()
+            },
+            image_extent: extent,
+        };
+        let objects = self.begin_batch()?.objects;
+        commandbuffer::transition_image_layout(&self.device, objects.transfer_cmd_buffer, dst_image, image_subresource_range,
+            vk::ImageLayout::UNDEFINED,          vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::NONE,               vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER);
+        unsafe {
+            self.device.cmd_copy_buffer_to_image(objects.transfer_cmd_buffer, src_buffer, dst_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[buffer_image_copy]);
+        }
+
+        if !self.has_ownership_transfers() {
+            commandbuffer::transition_image_layout(&self.device, objects.transfer_cmd_buffer, dst_image, image_subresource_range,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL, final_layout,
+                vk::AccessFlags::TRANSFER_WRITE,       dst_access_mask,
+                vk::PipelineStageFlags::TRANSFER,      dst_stage_mask);
+            return Ok(());
+        }
+        // The layout transition happens once, between the release and the acquire, since both barriers have the same layouts.
+        let mut image_memory_barrier = vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+            p_next: ptr::null(),
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: final_layout,
+            src_queue_family_index: self.transfer_queue_family_idx,
+            dst_queue_family_index: self.graphics_queue_family_idx,
+            image: dst_image,
+            subresource_range: image_subresource_range,
+        };
+        unsafe {
+            self.device.cmd_pipeline_barrier(objects.transfer_cmd_buffer, vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::DependencyFlags::empty(), &[], &[], &[image_memory_barrier]);
+        }
+        image_memory_barrier.src_access_mask = vk::AccessFlags::empty();
+        image_memory_barrier.dst_access_mask = dst_access_mask;
+        unsafe {
+            self.device.cmd_pipeline_barrier(objects.graphics_cmd_buffer, vk::PipelineStageFlags::ALL_COMMANDS, dst_stage_mask,
+                vk::DependencyFlags::empty(), &[], &[], &[image_memory_barrier]);
+        }
+        Ok(())
+    }
+
+    /// Submits the recording batch. Returns its id, or the id of the last submitted batch if nothing is recorded.
+    pub fn flush(&mut self) -> Result<UploadId, RendererError> {
+        let Some(batch) = self.recording_batch.take() else {
+            return Ok(UploadId(self.next_batch_id - 1));
+        };
+
+        let objects = batch.objects;
+        let mut is_transfer_submitted = false;
+        let result = if self.has_ownership_transfers() {
+            // Acquire barriers of the graphics submission wait for the copies through the semaphore.
+            let wait_dst_stage_mask = vk::PipelineStageFlags::ALL_COMMANDS;
+            let transfer_submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                p_next: ptr::null(),
+                wait_semaphore_count: 0,
+                p_wait_semaphores: ptr::null(),
+                p_wait_dst_stage_mask: ptr::null(),
+                command_buffer_count: 1,
+                p_command_buffers: &objects.transfer_cmd_buffer,
+                signal_semaphore_count: 1,
+                p_signal_semaphores: &objects.semaphore,
+            };
+            let graphics_submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                p_next: ptr::null(),
+                wait_semaphore_count: 1,
+                p_wait_semaphores: &objects.semaphore,
+                p_wait_dst_stage_mask: &wait_dst_stage_mask,
+                command_buffer_count: 1,
+                p_command_buffers: &objects.graphics_cmd_buffer,
+                signal_semaphore_count: 0,
+                p_signal_semaphores: ptr::null(),
+            };
+            unsafe {
+                self.device.end_command_buffer(objects.transfer_cmd_buffer)
+                    .and_then(|_| self.device.end_command_buffer(objects.graphics_cmd_buffer))
+                    .and_then(|_| self.device.reset_fences(&[objects.fence]))
+                    .and_then(|_| self.device.queue_submit(self.transfer_queue, &[transfer_submit_info], vk::Fence::null()))
+                    .and_then(|_| {
+                        is_transfer_submitted = true;
+                        self.device.queue_submit(self.graphics_queue, &[graphics_submit_info], objects.fence)
+                    })
+            }
+        } else {
+            // Makes transfer writes visible to every later command of the queue that reads vertex, index, uniform or shader data.
+            let memory_barrier = vk::MemoryBarrier {
+                s_type: vk::StructureType::MEMORY_BARRIER,
+                p_next: ptr::null(),
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: CONSUMER_ACCESS_MASK,
+            };
+            let submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                p_next: ptr::null(),
+                wait_semaphore_count: 0,
+                p_wait_semaphores: ptr::null(),
+                p_wait_dst_stage_mask: ptr::null(),
+                command_buffer_count: 1,
+                p_command_buffers: &objects.transfer_cmd_buffer,
+                signal_semaphore_count: 0,
+                p_signal_semaphores: ptr::null(),
+            };
+            unsafe {
+                self.device.cmd_pipeline_barrier(objects.transfer_cmd_buffer, vk::PipelineStageFlags::TRANSFER, CONSUMER_STAGE_MASK,
+                    vk::DependencyFlags::empty(), &[memory_barrier], &[], &[]);
+                self.device.end_command_buffer(objects.transfer_cmd_buffer)
+                    .and_then(|_| self.device.reset_fences(&[objects.fence]))
+                    .and_then(|_| self.device.queue_submit(self.graphics_queue, &[submit_info], objects.fence))
+            }
+        };
+        if let Err(result) = result {
+            if is_transfer_submitted {
+                // Copies of the transfer submission might still be reading the staging ranges of the batch, and its semaphore
+                // stays signaled with nothing waiting on it. Once the device is idle, every batch has finished, so the ring
+                // is empty again. The semaphore is replaced, since binary semaphores can not be unsignaled by the host.
+                unsafe{self.device.device_wait_idle()}?;
+                self.collect_finished()?;
+                unsafe{self.device.destroy_semaphore(objects.semaphore, None)};
+                let semaphore = self.create_semaphore()?;
+                self.free_batch_objects.push(BatchObjects { semaphore, ..objects });
+            } else {
+                // Nothing of the batch is submitted, so its staging ranges and buffers can be released right away.
+                self.free_batch_objects.push(objects);
+            }
+            return Err(result.into());
+        }
+
+        let id = batch.id;
+        self.in_flight_batches.push_back(batch);
+        Ok(id)
+    }
+
+    /// Returns true if the batch has finished on the device. Call collect_finished() first to get up to date results.
+    pub fn is_finished(&self, id: UploadId) -> bool {
+        id.0 <= self.last_finished_batch_id
+    }
+
+    /// Flushes the recording batch if it is the one waited for, then blocks until the batch has finished.
+    pub fn wait(&mut self, id: UploadId) -> Result<(), RendererError> {
+        if self.recording_batch.as_ref().is_some_and(|batch| batch.id <= id) {
+            self.flush()?;
+        }
+        while !self.is_finished(id) {
+            let Some(batch) = self.in_flight_batches.front() else { break };
+            unsafe {
+                self.device.wait_for_fences(&[batch.objects.fence], true, u64::MAX)?;
+            }
+            self.collect_finished()?;
+        }
+        Ok(())
+    }
+
+    /// Releases the staging ranges, command buffers and fences of finished batches. Should be called once per frame.
+    pub fn collect_finished(&mut self) -> Result<(), RendererError> {
+        // Batches finish in submission order, so the first unfinished batch ends the search.
+        while let Some(batch) = self.in_flight_batches.front() {
+            if !unsafe{self.device.get_fence_status(batch.objects.fence)}? {
+                break;
+            }
+            let batch = self.in_flight_batches.pop_front().unwrap();
+            if batch.uses_ring {
+                self.ring.release(batch.ring_end);
+            }
+            // Oversized staging buffers of the batch are dropped with it.
+            self.last_finished_batch_id = batch.id.0;
+            self.free_batch_objects.push(batch.objects);
+        }
+        Ok(())
+    }
+
+    /// Begins a new batch if there is no recording batch, and returns the recording batch.
+    fn begin_batch(&mut self) -> Result<&mut UploadBatch, RendererError> {
+        if self.recording_batch.is_none() {
+            let objects = match self.free_batch_objects.pop() {
+                Some(objects) => objects,
+                None => self.create_batch_objects()?,
+            };
+            let cmd_buffer_begin_info = vk::CommandBufferBeginInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+                p_next: ptr::null(),
+                flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                p_inheritance_info: ptr::null(),
+            };
+            // Info: Beginning a command buffer implicitly resets it.
+            let result = unsafe {
+                self.device.begin_command_buffer(objects.transfer_cmd_buffer, &cmd_buffer_begin_info).and_then(|_| {
+                    if self.has_ownership_transfers() {
+                        self.device.begin_command_buffer(objects.graphics_cmd_buffer, &cmd_buffer_begin_info)
+                    } else {
+                        Ok(())
+                    }
+                })
+            };
+            if let Err(result) = result {
+                self.free_batch_objects.push(objects);
+                return Err(result.into());
+            }
+            self.recording_batch = Some(UploadBatch {
+                id: UploadId(self.next_batch_id),
+                objects,
+                uses_ring: false,
+                ring_end: 0,
+                oversized_staging_buffers: Vec::new(),
+            });
+            self.next_batch_id += 1;
+        }
+        Ok(self.recording_batch.as_mut().unwrap())
+    }
+
+    fn create_cmd_pool(device: &Rc<device::Device>, queue_family_idx: u32) -> Result<device::CommandPool, RendererError> {
+        let cmd_pool_ci = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            // Command buffers are reused for later batches, so each of them must be resettable by itself.
+            flags: vk::CommandPoolCreateFlags::TRANSIENT | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: queue_family_idx,
+        };
+        let cmd_pool = unsafe{device.create_command_pool(&cmd_pool_ci, None)}?;
+        Ok(device::Owned::new(device, cmd_pool))
+    }
+
+    /// Command buffers are freed with their pools, so they are not freed here on errors.
+    fn create_batch_objects(&self) -> Result<BatchObjects, RendererError> {
+        let allocate_cmd_buffer = |command_pool: vk::CommandPool| {
+            let cmd_buffer_alloc_info = vk::CommandBufferAllocateInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+                p_next: ptr::null(),
+                command_pool,
+                level: vk::CommandBufferLevel::PRIMARY,
+                command_buffer_count: 1,
+            };
+            unsafe{self.device.allocate_command_buffers(&cmd_buffer_alloc_info)}.map(|cmd_buffers| cmd_buffers[0])
+        };
+        let transfer_cmd_buffer = allocate_cmd_buffer(self.transfer_cmd_pool.raw())?;
+        let (graphics_cmd_buffer, semaphore) = if let Some(graphics_cmd_pool) = &self.graphics_cmd_pool {
+            let graphics_cmd_buffer = allocate_cmd_buffer(graphics_cmd_pool.raw())?;
+            (graphics_cmd_buffer, self.create_semaphore()?)
+        } else {
+            (vk::CommandBuffer::null(), vk::Semaphore::null())
+        };
+        let fence_ci = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::FenceCreateFlags::empty(),
+        };
+        match unsafe{self.device.create_fence(&fence_ci, None)} {
+            Ok(fence) => Ok(BatchObjects {
+                transfer_cmd_buffer,
+                graphics_cmd_buffer,
+                semaphore,
+                fence,
+            }),
+            Err(result) => {
+                unsafe{self.device.destroy_semaphore(semaphore, None)};
+                Err(result.into())
+            }
+        }
+    }
+
+    fn create_semaphore(&self) -> Result<vk::Semaphore, RendererError> {
+        let semaphore_ci = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::SemaphoreCreateFlags::empty(),
+        };
+        Ok(unsafe{self.device.create_semaphore(&semaphore_ci, None)}?)
+    }
+
+    /// Copies size bytes of host data into a staging range and returns the buffer and offset to copy from. If the ring is
+    /// full, finished batches are released first, then the oldest batches are waited for. Data bigger than the whole ring
+    /// gets a staging buffer of its own.
+    fn stage(&mut self, data_ptr: *const u8, size: vk::DeviceSize)
+    -> Result<(vk::Buffer, vk::DeviceSize), RendererError> {
+        if size > self.ring.size {
+            println!("Upload manager: {} KiB does not fit into the staging ring buffer, using a separate staging buffer.",
+                size / 1024);
+            let staging_buffer = buffer::Buffer::new(&self.device, allocator::AllocationUsage::Staging, size,
+                vk::BufferUsageFlags::TRANSFER_SRC, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                &self.transfer_queue_family_idx)?;
+            staging_buffer.copy_host_data_into_buffer(data_ptr, size as usize);
+            let raw = staging_buffer.raw;
+            self.begin_batch()?.oversized_staging_buffers.push(staging_buffer);
+            return Ok((raw, 0));
+        }
+
+        self.collect_finished()?;
+        let offset = loop {
+            if let Some(offset) = self.allocate_ring_range(size) {
+                break offset;
+            }
+            // The recording batch is the only one left holding staging ranges, so it has to be submitted to free them.
+            if self.in_flight_batches.is_empty() {
+                self.flush()?;
+            }
+            let Some(batch) = self.in_flight_batches.front() else {
+                unreachable!("The ring must be empty if there are no batches holding staging ranges!");
+            };
+            unsafe {
+                self.device.wait_for_fences(&[batch.objects.fence], true, u64::MAX)?;
+            }
+            self.collect_finished()?;
+        };
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data_ptr, self.staging_mapped_ptr.add(offset as usize), size as usize);
+        }
+        let ring_end = self.ring.head;
+        let batch = self.begin_batch()?;
+        batch.uses_ring = true;
+        batch.ring_end = ring_end;
+        Ok((self.staging_buffer.raw, offset))
+    }
+
+    /// Returns the offset of a free range of the ring, or None if the ring has no contiguous free range of that size.
+    fn allocate_ring_range(&mut self, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let is_ring_empty = !self.recording_batch.as_ref().is_some_and(|batch| batch.uses_ring) &&
+            !self.in_flight_batches.iter().any(|batch| batch.uses_ring);
+        self.ring.allocate(size, is_ring_empty)
+    }
+}
+
+/// Offsets of the staging ring buffer. Staging ranges are taken from head and released from tail, in the same order.
+/// head == tail means that the ring is full unless there are no staging ranges in use, which only the batches know.
+struct StagingRing {
+    size: vk::DeviceSize,
+    head: vk::DeviceSize,
+    tail: vk::DeviceSize,
+}
+
+impl StagingRing {
+    fn new(size: vk::DeviceSize) -> StagingRing {
+        StagingRing {
+            size,
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// Returns the offset of a free range, or None if there is no contiguous free range of that size. is_empty resets the
+    /// ring to 0 first, if no staging ranges are in use.
+    fn allocate(&mut self, size: vk::DeviceSize, is_empty: bool) -> Option<vk::DeviceSize> {
+        if is_empty {
+            self.head = 0;
+            self.tail = 0;
+        }
+
+        let aligned_head = align_up(self.head, STAGING_ALIGNMENT);
+        let offset = if is_empty || self.head > self.tail {
+            // Free ranges are [head, end) and [0, tail).
+            if aligned_head + size <= self.size {
+                aligned_head
+            } else if size <= self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if self.head < self.tail && aligned_head + size <= self.tail {
+            aligned_head
+        } else {
+            return None;
+        };
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    /// Releases every staging range that ends at or before end, which is a head returned by an earlier allocation.
+    fn release(&mut self, end: vk::DeviceSize) {
+        self.tail = end;
+    }
+}
+
+impl Drop for UploadManager {
+    /// Submits the recording batch and waits for every batch, then destroys the objects of the batches. The staging
+    /// buffers and command pools are dropped afterwards, which also frees the command buffers.
+    fn drop(&mut self) {
+        if let Err(error) = self.flush().and_then(|id| self.wait(id)) {
+            println!("Upload manager: could not finish the uploads before being dropped: {}", error);
+        }
+        for batch in self.recording_batch.take().into_iter().chain(self.in_flight_batches.drain(..)) {
+            self.free_batch_objects.push(batch.objects);
+        }
+        unsafe {
+            for objects in &self.free_batch_objects {
+                self.device.destroy_fence(objects.fence, None);
+                self.device.destroy_semaphore(objects.semaphore, None);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_aligned_ranges_until_full() {
+        let mut ring = StagingRing::new(64);
+        assert_eq!(ring.allocate(10, true), Some(0));
+        assert_eq!(ring.allocate(10, false), Some(16));
+        assert_eq!(ring.allocate(32, false), Some(32));
+        assert_eq!(ring.allocate(1, false), None);
+        // Nothing in use anymore.
+        assert_eq!(ring.allocate(64, true), Some(0));
+        assert_eq!(ring.allocate(65, true), None);
+    }
+
+    #[test]
+    fn wraps_around_to_released_ranges() {
+        let mut ring = StagingRing::new(64);
+        assert_eq!(ring.allocate(32, true), Some(0));
+        assert_eq!(ring.allocate(16, false), Some(32));
+        // The first batch finished, but 24 bytes do not fit in front of the end.
+        ring.release(32);
+        assert_eq!(ring.allocate(24, false), Some(0));
+        // Head is behind tail now, so only [24, 32) is free.
+        assert_eq!(ring.allocate(16, false), None);
+        assert_eq!(ring.allocate(8, false), None); // Aligned to 32.
+        ring.release(48);
+        assert_eq!(ring.allocate(16, false), Some(32));
+        assert_eq!(ring.allocate(1, false), None);
+    }
+
+    #[test]
+    fn does_not_wrap_over_ranges_in_use() {
+        let mut ring = StagingRing::new(64);
+        assert_eq!(ring.allocate(16, true), Some(0));
+        assert_eq!(ring.allocate(32, false), Some(16));
+        ring.release(16);
+        // [48, 64) and [0, 16) are free, but not contiguous.
+        assert_eq!(ring.allocate(24, false), None);
+        assert_eq!(ring.allocate(16, false), Some(48));
+        assert_eq!(ring.allocate(16, false), Some(0));
+        // Full, head == tail.
+        assert_eq!(ring.allocate(1, false), None);
+    }
+}
@@ -1,10 +1,15 @@
 use ash::{vk};
+use raw_window_handle::RawDisplayHandle;
 use std::{
-    ptr, io::Read,
-    ffi::{CString, c_void}
+    ptr,
+    ffi::{CString, c_void},
+    rc::Rc
 };
 use super::queries;
 use super::image;
+use super::allocator;
+use super::device;
+use super::error::RendererError;
 
 pub fn create_app_info() -> vk::ApplicationInfo {
     let app_name = CString::new("Hanokei App").unwrap();
@@ -17,15 +22,19 @@ pub fn create_app_info() -> vk::ApplicationInfo {
         application_version: vk::make_api_version(0, 0, 1, 0),
         p_engine_name: engine_name.as_ptr(),
         engine_version: vk::make_api_version(0, 0, 1, 0),
-        api_version: vk::make_api_version(0, 0, 1, 0),
+        // Vulkan 1.1 for vkGetPhysicalDeviceMemoryProperties2, which VK_EXT_memory_budget is queried with.
+        api_version: vk::API_VERSION_1_1,
     }
 }
 
-pub fn create_instance(entry: &ash::Entry, app_info: &vk::ApplicationInfo) -> ash::Instance {
-    let instance_ext_names = queries::get_instance_extension_names(entry);
+/// display_handle is None for headless renderers which do not need any surface extensions.
+/// with_swapchain_colorspace enables VK_EXT_swapchain_colorspace, which exposes HDR color spaces of surfaces.
+pub fn create_instance(entry: &ash::Entry, app_info: &vk::ApplicationInfo, display_handle: Option<RawDisplayHandle>,
+with_swapchain_colorspace: bool) -> Result<ash::Instance, RendererError> {
+    let instance_ext_names = queries::get_instance_extension_names(entry, display_handle, with_swapchain_colorspace)?;
     // Note: val_layer_names variable must be created here just to extend the lifetimes of CStrings inside Vector.
     // Otherwise, pointers become dangling.
-    let val_layer_names = queries::get_instance_layer_names(entry); 
+    let val_layer_names = if cfg!(debug_assertions) {queries::get_instance_layer_names(entry)?} else {Vec::new()};
     let p_val_layer_names: Vec<*const i8> = val_layer_names.iter().map(|value| {value.as_ptr()}).collect();
 
     let instance_ci = vk::InstanceCreateInfo {
@@ -43,9 +52,10 @@ pub fn create_instance(entry: &ash::Entry, app_info: &vk::ApplicationInfo) -> as
         enabled_extension_count: instance_ext_names.len() as u32,
         pp_enabled_extension_names: instance_ext_names.as_ptr(),
     };
-    unsafe {
-        entry.create_instance(&instance_ci, None).unwrap()
-    }
+    let instance = unsafe {
+        entry.create_instance(&instance_ci, None)
+    }?;
+    Ok(instance)
 }
 
 pub struct QueueCreateInfo<'a> {
@@ -58,7 +68,7 @@ pub struct QueueCreateInfo<'a> {
 }
 impl<'a> QueueCreateInfo<'a> {
     #[inline(always)]
-    pub fn new(queue_family_index: u32, queue_count: u32, queue_priorities: &[f32]) -> QueueCreateInfo {
+    pub fn new(queue_family_index: u32, queue_count: u32, queue_priorities: &[f32]) -> QueueCreateInfo<'_> {
         debug_assert!(queue_priorities.len() == queue_count as usize);
         QueueCreateInfo { 
             family_index: queue_family_index,
@@ -67,8 +77,12 @@ impl<'a> QueueCreateInfo<'a> {
         }
     }
 }
-/// Logical device also creates Queues in queue_family_indices.
-pub fn create_device(instance : &ash::Instance, physical_device: vk::PhysicalDevice, queue_create_infos: &[QueueCreateInfo]) -> ash::Device {
+/// Logical device also creates Queues in queue_family_indices. with_sampler_anisotropy and with_image_cube_array enable the
+/// optional features, caller must check queries::get_max_sampler_anisotropy() and queries::has_image_cube_array_support()
+/// first.
+pub fn create_device(instance : &ash::Instance, physical_device: vk::PhysicalDevice, queue_create_infos: &[QueueCreateInfo],
+with_swapchain: bool, with_memory_budget: bool, with_sampler_anisotropy: bool, with_image_cube_array: bool)
+-> Result<ash::Device, RendererError> {
     let mut device_queue_cis = Vec::with_capacity(queue_create_infos.len());
     for queue_ci in queue_create_infos {
         let device_queue_ci = vk::DeviceQueueCreateInfo {
@@ -82,7 +96,7 @@ pub fn create_device(instance : &ash::Instance, physical_device: vk::PhysicalDev
         device_queue_cis.push(device_queue_ci);
     }
     
-    let device_ext_names = queries::get_device_extension_names(instance, physical_device);
+    let device_ext_names = queries::get_device_extension_names(instance, physical_device, with_swapchain, with_memory_budget)?;
     let device_create_info = ash::vk::DeviceCreateInfo {
         s_type: vk::StructureType::DEVICE_CREATE_INFO,
         p_next: ptr::null(),
@@ -93,17 +107,22 @@ pub fn create_device(instance : &ash::Instance, physical_device: vk::PhysicalDev
         pp_enabled_layer_names: ptr::null(), // Device-only layers are deprecated.
         enabled_extension_count: device_ext_names.len() as u32,
         pp_enabled_extension_names: device_ext_names.as_ptr(),
-        p_enabled_features: &vk::PhysicalDeviceFeatures::builder().sampler_anisotropy(true).build()
+        p_enabled_features: &vk::PhysicalDeviceFeatures::builder()
+            .sampler_anisotropy(with_sampler_anisotropy)
+            .image_cube_array(with_image_cube_array)
+            .build()
     };
 
-    unsafe {
-        instance.create_device(physical_device, &device_create_info, None).unwrap()
-    }
+    let device = unsafe {
+        instance.create_device(physical_device, &device_create_info, None)
+    }?;
+    Ok(device)
 }
 
-pub fn create_shader_module(device: &ash::Device, path: &str) -> vk::ShaderModule {
-    let spirv_file = std::fs::File::open(path).unwrap();
-    let spirv_bytes: Vec<u8> = spirv_file.bytes().filter_map(|byte| {byte.ok()}).collect();
+pub fn create_shader_module(device: &Rc<device::Device>, path: &str) -> Result<device::ShaderModule, RendererError> {
+    let spirv_bytes: Vec<u8> = std::fs::read(path).map_err(|error| {
+        RendererError::ShaderFile { path: path.to_owned(), error }
+    })?;
 
     let shader_module_ci = vk::ShaderModuleCreateInfo {
         s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
@@ -113,13 +132,15 @@ pub fn create_shader_module(device: &ash::Device, path: &str) -> vk::ShaderModul
         p_code: spirv_bytes.as_ptr() as *const u32,
     };
 
-    unsafe {
-        device.create_shader_module(&shader_module_ci, None).unwrap()
-    }  
+    let shader_module = unsafe {
+        device.create_shader_module(&shader_module_ci, None)
+    }?;
+    Ok(device::Owned::new(device, shader_module))
 }
 
-pub fn create_pipeline_shader_stage_create_info(main_fn_name: &CString, shader_stage: vk::ShaderStageFlags, shader_module: vk::ShaderModule)
--> vk::PipelineShaderStageCreateInfo {    
+/// specialization_info sets the specialization constants of the shader, it must outlive the pipeline creation.
+pub fn create_pipeline_shader_stage_create_info(main_fn_name: &CString, shader_stage: vk::ShaderStageFlags, shader_module: vk::ShaderModule,
+specialization_info: Option<&vk::SpecializationInfo>) -> vk::PipelineShaderStageCreateInfo {    
     vk::PipelineShaderStageCreateInfo {
         s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
         p_next: ptr::null(),
@@ -127,56 +148,88 @@ pub fn create_pipeline_shader_stage_create_info(main_fn_name: &CString, shader_s
         stage: shader_stage,
         module: shader_module,
         p_name: main_fn_name.as_ptr(),
-        p_specialization_info: ptr::null(),
+        p_specialization_info: specialization_info.map_or(ptr::null(), |specialization_info| specialization_info),
     }
 }
 
-pub fn create_depth_images_and_views (device: &ash::Device, instance: &ash::Instance, physical_device: vk::PhysicalDevice, width: u32,
-height: u32, depth_format: vk::Format, msaa_sample_count: vk::SampleCountFlags, count: usize) -> (Vec<image::Image>, Vec<vk::ImageView>) {
-    let mut depth_images = Vec::with_capacity(count);
+/// Views keep their images alive, so only the views are returned.
+pub fn create_depth_image_views(device: &Rc<device::Device>, width: u32, height: u32, depth_format: vk::Format,
+msaa_sample_count: vk::SampleCountFlags, count: usize) -> Result<Vec<image::ImageView>, RendererError> {
     let mut depth_image_views = Vec::with_capacity(count);
     for _ in 0..count {
-        let depth_image = image::Image::new(&device, &instance, physical_device, width, height, 1, msaa_sample_count, depth_format,
-            vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::MemoryPropertyFlags::DEVICE_LOCAL);
-        let depth_image_view = depth_image.create_image_view(&device, depth_format, 1, vk::ImageAspectFlags::DEPTH);
+        let depth_image = Rc::new(image::Image::new(device, allocator::AllocationUsage::Depth, vk::ImageCreateFlags::empty(), width,
+            height, 1, 1, msaa_sample_count, depth_format, vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL)?);
+        let depth_image_view = depth_image.create_image_view(depth_format, vk::ImageViewType::TYPE_2D, 1, 0, 1, vk::ImageAspectFlags::DEPTH)?;
 
-        depth_images.push(depth_image);
         depth_image_views.push(depth_image_view);
     }
 
-    (depth_images, depth_image_views)
+    Ok(depth_image_views)
 }
 
-pub fn create_msaa_color_images_and_views(device: &ash::Device, instance: &ash::Instance, physical_device: vk::PhysicalDevice, width: u32,
-height: u32, format: vk::Format, count: usize) -> (Vec<image::Image>, Vec<vk::ImageView>) {
-    let mut msaa_color_images = Vec::with_capacity(count);
+/// Views keep their images alive, so only the views are returned.
+pub fn create_msaa_color_image_views(device: &Rc<device::Device>, width: u32, height: u32, format: vk::Format,
+msaa_sample_count: vk::SampleCountFlags, count: usize) -> Result<Vec<image::ImageView>, RendererError> {
     let mut msaa_color_image_views = Vec::with_capacity(count);
     for _ in 0..count {
-        let msaa_color_image = image::Image::new(&device, &instance, physical_device, width, height, 1, vk::SampleCountFlags::TYPE_8,
-            format, vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL);
-        let msaa_color_image_view = msaa_color_image.create_image_view(&device, format, 1, vk::ImageAspectFlags::COLOR);
+        let msaa_color_image = Rc::new(image::Image::new(device, allocator::AllocationUsage::Msaa, vk::ImageCreateFlags::empty(), width,
+            height, 1, 1, msaa_sample_count, format, vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL)?);
+        let msaa_color_image_view = msaa_color_image.create_image_view(format, vk::ImageViewType::TYPE_2D, 1, 0, 1, vk::ImageAspectFlags::COLOR)?;
         
-        msaa_color_images.push(msaa_color_image);
         msaa_color_image_views.push(msaa_color_image_view);
     }
-    (msaa_color_images, msaa_color_image_views)
+    Ok(msaa_color_image_views)
 }
 
-pub fn create_framebuffer(device: &ash::Device, attachments: &[vk::ImageView], 
+pub fn create_framebuffer(device: &Rc<device::Device>, attachments: &[vk::ImageView], 
 render_pass: vk::RenderPass, width: u32, height: u32)
--> vk::Framebuffer {
+-> Result<device::Framebuffer, RendererError> {
     let framebuffer_ci = vk::FramebufferCreateInfo {
         s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
         p_next: ptr::null(),
         flags: vk::FramebufferCreateFlags::empty(),
-        render_pass: render_pass,
+        render_pass,
         attachment_count: attachments.len() as u32,
         p_attachments: attachments.as_ptr(),
-        width: width,
-        height: height,
+        width,
+        height,
         layers: 1, // Width, height and layers define dimensions.
     };
 
-    unsafe{device.create_framebuffer(&framebuffer_ci, None)}.unwrap()
-}
\ No newline at end of file
+    let framebuffer = unsafe{device.create_framebuffer(&framebuffer_ci, None)}?;
+    Ok(device::Owned::new(device, framebuffer))
+}
+pub fn create_descriptor_set_layout(device: &Rc<device::Device>, bindings: &[vk::DescriptorSetLayoutBinding])
+-> Result<device::DescriptorSetLayout, RendererError> {
+    let descriptor_layout_ci = vk::DescriptorSetLayoutCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        p_next: ptr::null(),
+        flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
+    };
+    let descriptor_set_layout = unsafe {
+        device.create_descriptor_set_layout(&descriptor_layout_ci, None)
+    }?;
+    Ok(device::Owned::new(device, descriptor_set_layout))
+}
+
+/// Allocates count descriptor sets of the same layout. They are freed with the pool.
+pub fn allocate_descriptor_sets(device: &Rc<device::Device>, descriptor_pool: vk::DescriptorPool,
+descriptor_set_layout: vk::DescriptorSetLayout, count: u32) -> Result<Vec<vk::DescriptorSet>, RendererError> {
+    let descriptor_set_layouts = vec![descriptor_set_layout; count as usize];
+    let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        p_next: ptr::null(),
+        descriptor_pool,
+        descriptor_set_count: count, // Allocates this many descriptor sets by...
+        p_set_layouts: descriptor_set_layouts.as_ptr(), // ...using these layouts. So you basically can combine different amount of...
+        // ...descriptor sets and descriptors arbitrarily! It's a little bit confusing matter at first.
+    };
+    let descriptor_sets = unsafe {
+        device.allocate_descriptor_sets(&descriptor_set_alloc_info)
+    }?;
+    Ok(descriptor_sets)
+}
@@ -1,8 +1,26 @@
 // Note: do not include mods here, just use. And do not include lib.rs here.
 
 use hanokei_lib::engine::Engine;
+use hanokei_lib::engine::renderer::{Renderer, RendererConfig};
 
 fn main() {
-    let engine = Engine::new();
-    engine.loop_start();
+    // "--headless <path.png>" renders a single frame without a window and saves it.
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 3 && args[1] == "--headless" {
+        let result = Renderer::new_headless(1280, 720, &RendererConfig::default())
+            .and_then(|mut renderer| renderer.render_offscreen_to_png(&args[2]));
+        if let Err(error) = result {
+            eprintln!("Headless rendering failed: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    match Engine::new() {
+        Ok(engine) => engine.loop_start(),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
 }